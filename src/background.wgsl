@@ -0,0 +1,49 @@
+// Full-screen gradient drawn before the particles, solid backgrounds are cleared instead
+
+struct Background {
+    // 1: vertical gradient from first (top) to second (bottom)
+    // 2: radial gradient from first (center) to second (corners)
+    kind: u32,
+    padding1: u32,
+    padding2: u32,
+    padding3: u32,
+    first: vec4<f32>,
+    second: vec4<f32>,
+};
+
+struct Resolution {
+    width: f32,
+    height: f32,
+};
+
+@group(0) @binding(0) var<uniform> background: Background;
+@group(0) @binding(1) var<uniform> resolution: Resolution;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// A single triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = input.position.xy / vec2<f32>(resolution.width, resolution.height);
+
+    var t = uv.y;
+    if background.kind == 2u {
+        // circular in pixels, reaching the second color exactly in the corners
+        let aspect = resolution.width / resolution.height;
+        let offset = (uv * 2.0 - 1.0) * vec2<f32>(aspect, 1.0);
+        t = length(offset) / length(vec2<f32>(aspect, 1.0));
+    }
+
+    return mix(background.first, background.second, clamp(t, 0.0, 1.0));
+}
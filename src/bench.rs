@@ -0,0 +1,182 @@
+use std::{sync::Arc, time::Instant};
+
+use crate::{
+    cli::Comparison, config::GameConfiguration, gpu::Gpu, layout::ParticleLayout, state::State,
+};
+
+/// Frames rendered after switching particle counts before measuring starts, so pipeline
+/// creation and buffer uploads do not count towards the results.
+const WARMUP_FRAMES: u32 = 10;
+
+/// What the event loop should do after a benchmarked frame.
+pub enum BenchProgress {
+    Continue,
+    /// The current count is done, continue with this many particles.
+    Next(u32),
+    Finished,
+}
+
+/// Measures frame times for one or more particle counts in a row, printing a result line
+/// for each.
+pub struct Benchmark {
+    counts: Vec<u32>,
+    frames: u32,
+    current: usize,
+    warmup: u32,
+    frame_times: Vec<f32>,
+    last_frame: Option<Instant>,
+}
+
+impl Benchmark {
+    pub fn new(counts: Vec<u32>, frames: u32) -> Self {
+        println!("particle layout: {}", ParticleLayout::CURRENT.name());
+        println!(
+            "{:>12} {:>8} {:>10} {:>10} {:>10} {:>8}",
+            "particles", "frames", "mean ms", "min ms", "max ms", "fps"
+        );
+        Self {
+            counts,
+            frames: frames.max(1),
+            current: 0,
+            warmup: WARMUP_FRAMES,
+            frame_times: Vec::with_capacity(frames as usize),
+            last_frame: None,
+        }
+    }
+
+    /// Particle count measured first.
+    pub fn first_count(&self) -> u32 {
+        self.counts[0]
+    }
+
+    /// Largest particle count of the run, which the particle buffer has to be sized for.
+    pub fn max_count(&self) -> u32 {
+        self.counts.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Records that a frame was rendered.
+    pub fn frame_rendered(&mut self) -> BenchProgress {
+        let now = Instant::now();
+        let last_frame = self.last_frame.replace(now);
+
+        if self.warmup > 0 {
+            self.warmup -= 1;
+            return BenchProgress::Continue;
+        }
+        if let Some(last_frame) = last_frame {
+            self.frame_times
+                .push((now - last_frame).as_secs_f32() * 1000.0);
+        }
+        if self.frame_times.len() < self.frames as usize {
+            return BenchProgress::Continue;
+        }
+
+        self.print_result();
+        self.frame_times.clear();
+        self.warmup = WARMUP_FRAMES;
+        self.current += 1;
+        match self.counts.get(self.current) {
+            Some(&count) => BenchProgress::Next(count),
+            None => BenchProgress::Finished,
+        }
+    }
+
+    fn print_result(&self) {
+        let [mean, min, max] = statistics(&self.frame_times);
+        println!(
+            "{:>12} {:>8} {:>10.3} {:>10.3} {:>10.3} {:>8.1}",
+            self.counts[self.current],
+            self.frame_times.len(),
+            mean,
+            min,
+            max,
+            1000.0 / mean
+        );
+    }
+}
+
+/// Substeps `compare submit` runs at least, enough for the submits to add up.
+const BATCHED_SUBSTEPS: u32 = 8;
+
+/// Name of a variant `compare` measures and how it changes the configuration.
+type Variant = (&'static str, fn(&mut GameConfiguration));
+
+/// Every variant of `comparison`, the baseline first.
+fn variants(comparison: Comparison) -> Vec<Variant> {
+    match comparison {
+        Comparison::Splats => vec![
+            ("quads", |config| config.smooth_render = false),
+            ("splats", |config| config.smooth_render = true),
+        ],
+        Comparison::Submit => vec![
+            ("separate", |config| {
+                config.substeps = config.substeps.max(BATCHED_SUBSTEPS);
+                config.batch_substeps = false;
+            }),
+            ("batched", |config| {
+                config.substeps = config.substeps.max(BATCHED_SUBSTEPS);
+                config.batch_substeps = true;
+            }),
+        ],
+        Comparison::Segments => vec![
+            ("quads", |config| config.particle_segments = 0),
+            ("8 sides", |config| config.particle_segments = 8),
+            ("32 sides", |config| config.particle_segments = 32),
+            ("64 sides", |config| config.particle_segments = 64),
+        ],
+    }
+}
+
+/// Renders `frames` frames of every variant of `comparison` into offscreen targets of
+/// headless states on `gpu`, waiting for the GPU after each frame, and prints a result line
+/// per variant. Every variant starts from `config`, so only the compared setting differs.
+pub fn compare(gpu: &Arc<Gpu>, comparison: Comparison, config: &GameConfiguration, frames: u32) {
+    println!("particle layout: {}", ParticleLayout::CURRENT.name());
+    println!(
+        "{:>10} {:>12} {:>8} {:>10} {:>10} {:>10} {:>8}",
+        "variant", "particles", "frames", "mean ms", "min ms", "max ms", "fps"
+    );
+    for (name, change) in variants(comparison) {
+        let mut config = config.clone();
+        change(&mut config);
+        let mut state = State::new_headless_shared(gpu.clone(), config);
+        // every frame simulates the same time, however long it takes to measure
+        state.use_fixed_timestep = true;
+        let mut frame = || {
+            state.update();
+            state.render_offscreen();
+            state.gpu.device.poll(wgpu::Maintain::Wait);
+        };
+
+        for _ in 0..WARMUP_FRAMES {
+            frame();
+        }
+        let frame_times: Vec<f32> = (0..frames.max(1))
+            .map(|_| {
+                let start = Instant::now();
+                frame();
+                start.elapsed().as_secs_f32() * 1000.0
+            })
+            .collect();
+
+        let [mean, min, max] = statistics(&frame_times);
+        println!(
+            "{:>10} {:>12} {:>8} {:>10.3} {:>10.3} {:>10.3} {:>8.1}",
+            name,
+            state.active_particles(),
+            frame_times.len(),
+            mean,
+            min,
+            max,
+            1000.0 / mean
+        );
+    }
+}
+
+/// Mean, minimum and maximum of `frame_times`.
+fn statistics(frame_times: &[f32]) -> [f32; 3] {
+    let mean = frame_times.iter().sum::<f32>() / frame_times.len() as f32;
+    let min = frame_times.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = frame_times.iter().copied().fold(0.0, f32::max);
+    [mean, min, max]
+}
@@ -0,0 +1,26 @@
+// Full-screen pass adding the paint accumulated on the canvas to the frame
+
+@group(0) @binding(0) var canvas_texture: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// A single triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let paint = textureLoad(canvas_texture, vec2<i32>(input.position.xy), 0).rgb;
+
+    // the canvas keeps growing where particles pass often, saturate it smoothly towards
+    // full color instead of clipping
+    return vec4<f32>(1.0 - exp(-paint), 1.0);
+}
@@ -0,0 +1,118 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use log::debug;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, MouseButton},
+    keyboard::{Key, NamedKey},
+};
+
+use crate::{selftest, state::State};
+
+/// Actions between two readbacks checking that the particles are still finite.
+const CHECK_INTERVAL: u32 = 100;
+
+/// Named keys pressed at random, besides every character in `State::CHARACTER_KEYS`. F2 is
+/// left out because it prints the whole configuration.
+const NAMED_KEYS: &[NamedKey] = &[
+    NamedKey::Space,
+    NamedKey::Backspace,
+    NamedKey::F1,
+    NamedKey::F3,
+    NamedKey::F11,
+    NamedKey::Escape,
+    NamedKey::PageUp,
+    NamedKey::PageDown,
+];
+
+/// One input fired at the state, in the form the event loop passes it on.
+#[derive(Debug)]
+enum Action {
+    MouseMoved(PhysicalPosition<f64>),
+    MouseInput(MouseButton, ElementState),
+    KeyPressed(Key),
+    Resize(PhysicalSize<u32>),
+    Update,
+}
+
+impl Action {
+    /// Draws a random action. The cursor may leave the window and sizes may be zero, like
+    /// they can be for real windows.
+    fn random(rng: &mut StdRng, size: PhysicalSize<u32>) -> Self {
+        match rng.gen_range(0..20) {
+            0..=7 => Action::Update,
+            8..=12 => Action::MouseMoved(PhysicalPosition::new(
+                rng.gen_range(-0.5..1.5) * size.width as f64,
+                rng.gen_range(-0.5..1.5) * size.height as f64,
+            )),
+            13..=14 => {
+                let button = if rng.gen_bool(0.5) {
+                    MouseButton::Left
+                } else {
+                    MouseButton::Right
+                };
+                let state = if rng.gen_bool(0.5) {
+                    ElementState::Pressed
+                } else {
+                    ElementState::Released
+                };
+                Action::MouseInput(button, state)
+            }
+            15..=18 => {
+                let characters = State::CHARACTER_KEYS;
+                let index = rng.gen_range(0..characters.len() + NAMED_KEYS.len());
+                Action::KeyPressed(match characters.get(index) {
+                    Some(key) => Key::Character((*key).into()),
+                    None => Key::Named(NAMED_KEYS[index - characters.len()]),
+                })
+            }
+            _ => Action::Resize(PhysicalSize::new(
+                rng.gen_range(0..=1024),
+                rng.gen_range(0..=1024),
+            )),
+        }
+    }
+
+    fn apply(&self, state: &mut State) {
+        match self {
+            Action::MouseMoved(position) => state.mouse_moved(*position),
+            Action::MouseInput(button, button_state) => state.mouse_input(*button, *button_state),
+            Action::KeyPressed(key) => state.key_pressed(key, None),
+            Action::Resize(size) => state.resize(*size),
+            Action::Update => state.update(),
+        }
+    }
+}
+
+/// Fires `iterations` random inputs at `state`, the same sequence for the same `seed`, and
+/// checks that none of them panics and that the particles stay finite. Prints the result
+/// and returns whether the run passed.
+pub fn run(state: &mut State, seed: u64, iterations: u32) -> bool {
+    println!("chaos run with seed {}, {} iterations", seed, iterations);
+    let mut rng = StdRng::seed_from_u64(seed);
+    // every update advances by the same amount, so the run is reproducible
+    state.use_fixed_timestep = true;
+
+    for iteration in 0..iterations {
+        let action = Action::random(&mut rng, state.size);
+        debug!("chaos {}: {:?}", iteration, action);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| action.apply(state)));
+        if result.is_err() {
+            println!("FAIL iteration {}: {:?} panicked", iteration, action);
+            return false;
+        }
+
+        let check = (iteration + 1) % CHECK_INTERVAL == 0 || iteration + 1 == iterations;
+        if check && let Err(reason) = selftest::all_finite(&state.read_particles()) {
+            println!(
+                "FAIL iteration {} after {:?}: {}",
+                iteration, action, reason
+            );
+            return false;
+        }
+    }
+
+    println!("PASS {} iterations", iterations);
+    true
+}
@@ -0,0 +1,123 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+/// GPU particle simulator driven by compute shaders.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Configuration file to load, created with default values when it does not exist.
+    #[arg(long, global = true, default_value = "config.json")]
+    pub config: PathBuf,
+
+    /// Render with the adapter of this index in `--list-adapters`, instead of `adapter`
+    /// from the configuration.
+    #[arg(long, global = true, value_name = "INDEX")]
+    pub adapter: Option<usize>,
+
+    /// Print every adapter with its index and exit.
+    #[arg(long)]
+    pub list_adapters: bool,
+
+    /// Close the window after this many seconds, counted once the simulation is set up,
+    /// to bound automated runs. Has no effect on `selftest`, `chaos` and `compare`, which
+    /// end on their own.
+    #[arg(long, global = true, value_name = "SECONDS", value_parser = parse_seconds)]
+    pub duration: Option<Duration>,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+    /// Open the interactive simulation (the default when no subcommand is given).
+    Run {
+        /// Capture cursor, mouse button and key input to this file for `replay`.
+        #[arg(long, value_name = "FILE")]
+        record: Option<PathBuf>,
+        /// Accept newline delimited JSON requests on this TCP address, e.g.
+        /// `127.0.0.1:7878`. Requests are not captured by `--record`.
+        #[arg(long, value_name = "ADDR")]
+        control_socket: Option<String>,
+        /// Switch commands at the simulated times listed in this file, one JSON object per
+        /// line, e.g. `{"time": 2.5, "command": "dust"}`.
+        #[arg(long, value_name = "FILE")]
+        timeline: Option<PathBuf>,
+        /// Write the particles to `frame_NNNNN.bin` files in this directory every
+        /// `dump_interval` frames.
+        #[arg(long, value_name = "DIR")]
+        dump_particles: Option<PathBuf>,
+    },
+    /// Render a fixed number of frames and print frame time statistics.
+    Bench {
+        /// Frames to measure, after a short warmup.
+        #[arg(long, default_value_t = 600)]
+        frames: u32,
+        /// Particle count to benchmark, instead of `num_particles` from the configuration.
+        #[arg(long)]
+        particles: Option<u32>,
+    },
+    /// Render a fixed number of frames of each variant of a setting without a window and
+    /// print one result line per variant, waiting for the GPU after every frame.
+    Compare {
+        /// Setting to compare.
+        comparison: Comparison,
+        /// Frames to measure per variant, after a short warmup.
+        #[arg(long, default_value_t = 300)]
+        frames: u32,
+        /// Particle count to compare at, instead of `num_particles` from the configuration.
+        #[arg(long)]
+        particles: Option<u32>,
+    },
+    /// Benchmark several particle counts in a row and print one result line per count.
+    Sweep {
+        /// Comma separated particle counts, e.g. `100000,1000000,10000000`.
+        #[arg(long, value_delimiter = ',', required = true)]
+        counts: Vec<u32>,
+        /// Frames to measure per count, after a short warmup.
+        #[arg(long, default_value_t = 300)]
+        frames: u32,
+    },
+    /// Run every command for a few steps on a small particle set and verify the results,
+    /// exiting with a non-zero status if any check fails.
+    Selftest,
+    /// Fire a random but reproducible stream of cursor moves, clicks, key presses, resizes
+    /// and updates at a small headless simulation, failing if any of them panics or the
+    /// particles stop being finite.
+    Chaos {
+        /// Seed of the input stream, the same seed replays the same inputs. Random when
+        /// omitted, and printed either way.
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Inputs to fire.
+        #[arg(long, default_value_t = 2000)]
+        iterations: u32,
+    },
+    /// Play back input captured with `run --record`.
+    Replay {
+        /// Recording to play back.
+        file: PathBuf,
+        /// Write the particles to `frame_NNNNN.bin` files in this directory every
+        /// `dump_interval` frames.
+        #[arg(long, value_name = "DIR")]
+        dump_particles: Option<PathBuf>,
+    },
+}
+
+/// Settings `compare` measures the variants of.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Comparison {
+    /// Particles drawn as quads against `smooth_render` splats.
+    Splats,
+    /// A submit per substep against `batch_substeps`, with at least 8 substeps.
+    Submit,
+    /// Quads against `particle_segments` polygons of 8, 32 and 64 sides.
+    Segments,
+}
+
+/// A non-negative, finite number of seconds.
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value.parse().map_err(|err| format!("{}", err))?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("{} is not a duration", seconds))
+}
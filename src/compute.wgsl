@@ -2,27 +2,185 @@ struct Particle {
     position: vec2<f32>,
     velocity: vec2<f32>,
     acceleration: vec2<f32>,
+    pinned: u32,
+    // random offset in [0, 1) of per-particle effects like the pulse
+    phase: f32,
 };
 
 struct TimeUniform {
     delta_time: f32,
     particle_count: u32,
-    padding1: vec2<f32>,
+    // simulation step counter, wraps around
+    frame: u32,
+    // simulated seconds since the start up to the end of this step, stands still while
+    // paused and follows time_scale
+    elapsed_time: f32,
     padding2: vec4<f32>,
 };
 
-struct MousePosition {
-    position: vec2<f32>,
-};
+// MousePosition, Simulation, Command, the command constants and field_acceleration,
+// from field.wgsl
+// $RUST_FIELD
+// $RUST_FIELDEND
 
-struct Command {
-    command: u32,
-};
+// Invocations per workgroup, and the side of 2D workgroups, generated from
+// state::WORKGROUP_SIZE and dispatch::TILE_SIZE
+// $RUST_WORKGROUP
+const WORKGROUP_SIZE: u32 = 1024u;
+const TILE_SIZE: u32 = 16u;
+// $RUST_WORKGROUPEND
+
+// Whether results may differ in the last bits for speed, generated from the fast_math
+// setting. See mul_add and separation.
+// $RUST_FAST_MATH
+const FAST_MATH: bool = false;
+// $RUST_FAST_MATHEND
 
 @group(0) @binding(0) var<uniform> time: TimeUniform;
+// The particle storage and its accessors, generated for the particle layout of the build.
+// Particles are only ever read and written through these, see layout.rs.
+// $RUST_PARTICLES
 @group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
+
+fn load_particle(index: u32) -> Particle {
+    return particles[index];
+}
+
+fn particle_position(index: u32) -> vec2<f32> {
+    return particles[index].position;
+}
+
+fn particle_velocity(index: u32) -> vec2<f32> {
+    return particles[index].velocity;
+}
+
+fn particle_pinned(index: u32) -> bool {
+    return particles[index].pinned != 0u;
+}
+
+fn store_particle(index: u32, particle: Particle) {
+    particles[index] = particle;
+}
+
+fn set_particle_position(index: u32, position: vec2<f32>) {
+    particles[index].position = position;
+}
+
+fn set_particle_velocity(index: u32, velocity: vec2<f32>) {
+    particles[index].velocity = velocity;
+}
+// $RUST_PARTICLESEND
 @group(0) @binding(2) var<uniform> mouse_position: MousePosition;
 @group(0) @binding(3) var<uniform> command: Command;
+@group(0) @binding(4) var<uniform> simulation: Simulation;
+
+// Uniform grid over the [-1, 1] square, rebuilt every step by the *_grid kernels below
+struct Grid {
+    cells_per_axis: u32,
+    cell_count: u32,
+    cell_size: f32,
+    // particles closer than this push each other apart, 0 disables it
+    min_distance: f32,
+    // fraction of the overlap of a pair resolved per step
+    min_distance_stiffness: f32,
+    // particle i belongs to band i % color_bands
+    color_bands: u32,
+    // pull towards the neighbors of the same band within band_cohesion_radius, 0 disables it
+    band_cohesion: f32,
+    band_cohesion_radius: f32,
+};
+
+@group(0) @binding(5) var<uniform> grid: Grid;
+// particles per cell, also the fill cursor while scattering
+@group(0) @binding(6) var<storage, read_write> cell_counts: array<atomic<u32>>;
+// offset of every cell's range in the sorted arrays, plus the total at the end
+@group(0) @binding(7) var<storage, read_write> cell_starts: array<u32>;
+@group(0) @binding(8) var<storage, read_write> sorted_indices: array<u32>;
+@group(0) @binding(9) var<storage, read_write> sorted_positions: array<vec2<f32>>;
+
+// Aggregates over all particles, read back by stats.rs
+struct Stats {
+    // bits of the largest speed, non-negative floats order like their bits
+    max_speed: atomic<u32>,
+    // sum of 0.5 * |v|^2 over all particles as 64 bit fixed point with 16 fractional bits,
+    // there are no float atomics
+    kinetic_energy_low: atomic<u32>,
+    kinetic_energy_high: atomic<u32>,
+    // particles with a finite position, which the position sum is taken over
+    finite_count: atomic<u32>,
+    // sum of the finite positions as 64 bit two's complement fixed point with 16
+    // fractional bits, like the kinetic energy
+    position_sum_x_low: atomic<u32>,
+    position_sum_x_high: atomic<u32>,
+    position_sum_y_low: atomic<u32>,
+    position_sum_y_high: atomic<u32>,
+};
+
+@group(0) @binding(10) var<storage, read_write> stats: Stats;
+
+// positions at the start of the step, so Chain never reads a predecessor mid-update
+@group(0) @binding(11) var<storage, read_write> chain_positions: array<vec2<f32>>;
+// rounding error of every position, the low half of the high precision positions
+@group(0) @binding(12) var<storage, read_write> position_low: array<vec2<f32>>;
+
+// Settings of record_trails, see trails.rs
+struct Trail {
+    // positions kept per particle
+    length: u32,
+    // nonzero fills the whole history with the current position
+    reset: u32,
+    padding1: u32,
+    padding2: u32,
+};
+
+// Bound in a bind group of their own, only record_trails uses them
+// the last trail.length positions of every particle, most recent first
+@group(0) @binding(13) var<storage, read_write> trail_history: array<vec2<f32>>;
+@group(0) @binding(14) var<uniform> trail: Trail;
+
+// One spring of a particle of the net, see net.rs
+struct Spring {
+    // index of the particle at the other end, NO_SPRING for an unused slot
+    other: u32,
+    rest_length: f32,
+    stiffness: f32,
+    padding: u32,
+};
+
+// Settings of apply_springs
+struct Net {
+    gravity: vec2<f32>,
+    damping: f32,
+    // particles of the net simulated this step
+    particle_count: u32,
+};
+
+// Bound in a bind group of their own, only apply_springs uses them
+// SPRINGS_PER_PARTICLE slots for every particle of the net
+@group(0) @binding(15) var<storage, read> springs: array<Spring>;
+@group(0) @binding(16) var<uniform> net: Net;
+
+// Settings of update_tints
+struct Tint {
+    color: vec4<f32>,
+    // particles closer than this to the cursor take on color
+    radius: f32,
+    // seconds over which a tint fades to about a third of its strength
+    fade_time: f32,
+    padding1: u32,
+    padding2: u32,
+};
+
+// Bound in a bind group of their own, only update_tints uses them
+// RGBA per particle, alpha is how strongly it replaces the particle's color
+@group(0) @binding(18) var<storage, read_write> tints: array<vec4<f32>>;
+@group(0) @binding(19) var<uniform> tint: Tint;
+
+const SPRINGS_PER_PARTICLE: u32 = 8u;
+const NO_SPRING: u32 = 0xffffffffu;
+
+// Neighbors looked at per cell, bounds the cost of very crowded cells
+const MAX_NEIGHBORS_PER_CELL: u32 = 32u;
 
 
 // fast pseudorandom number generation based on index
@@ -38,86 +196,855 @@ fn f32_from_u32(value: u32) -> f32 {
     return f32(value) / 4294967295.0; // Normalize to [0, 1]
 }
 
-const NUDGE_AMOUNT: f32 = 0.01;
+// Smoothly interpolated random values on an integer lattice, in [0, 1]
+fn value_noise(p: vec3<f32>) -> f32 {
+    let cell = vec3<i32>(floor(p));
+    let t = fract(p);
+    // quintic fade, continuous up to the second derivative
+    let f = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+
+    let n000 = lattice_value(cell);
+    let n100 = lattice_value(cell + vec3<i32>(1, 0, 0));
+    let n010 = lattice_value(cell + vec3<i32>(0, 1, 0));
+    let n110 = lattice_value(cell + vec3<i32>(1, 1, 0));
+    let n001 = lattice_value(cell + vec3<i32>(0, 0, 1));
+    let n101 = lattice_value(cell + vec3<i32>(1, 0, 1));
+    let n011 = lattice_value(cell + vec3<i32>(0, 1, 1));
+    let n111 = lattice_value(cell + vec3<i32>(1, 1, 1));
+
+    let x00 = mix(n000, n100, f.x);
+    let x10 = mix(n010, n110, f.x);
+    let x01 = mix(n001, n101, f.x);
+    let x11 = mix(n011, n111, f.x);
+    return mix(mix(x00, x10, f.y), mix(x01, x11, f.y), f.z);
+}
+
+fn lattice_value(cell: vec3<i32>) -> f32 {
+    let h = (u32(cell.x) * 73856093u) ^ (u32(cell.y) * 19349663u) ^ (u32(cell.z) * 83492791u);
+    return f32_from_u32(fast_random(h | 1u));
+}
+
+// Divergence free noise: the curl of a scalar noise potential, so particles swirl around
+// instead of bunching up in sinks. `t` moves the potential through time.
+fn curl_noise(p: vec2<f32>, t: f32) -> vec2<f32> {
+    let eps = 0.01;
+    let dx = value_noise(vec3<f32>(p.x + eps, p.y, t)) - value_noise(vec3<f32>(p.x - eps, p.y, t));
+    let dy = value_noise(vec3<f32>(p.x, p.y + eps, t)) - value_noise(vec3<f32>(p.x, p.y - eps, t));
+    return vec2<f32>(dy, -dx) / (2.0 * eps);
+}
+
+// a * b + c. With FAST_MATH as a single fused multiply-add, which skips rounding the
+// product: not less accurate, but no longer bit-identical to the separate operations.
+fn mul_add(a: vec2<f32>, b: f32, c: vec2<f32>) -> vec2<f32> {
+    if FAST_MATH {
+        return fma(a, vec2<f32>(b), c);
+    }
+    return a * b + c;
+}
+
+// Advances a critically damped spring (damping = 2 * sqrt(stiffness), unit mass) towards
+// `anchor` using its closed form solution, so it converges without overshoot for any
+// stiffness and delta time.
+fn spring_to(p: Particle, anchor: vec2<f32>, stiffness: f32) -> Particle {
+    var particle = p;
+    let omega = sqrt(stiffness);
+    let t = time.delta_time;
+    let offset = particle.position - anchor;
+    let b = particle.velocity + omega * offset;
+    let decay = exp(-omega * t);
+
+    particle.acceleration = -stiffness * offset - 2.0 * omega * particle.velocity;
+    particle.position = anchor + (offset + b * t) * decay;
+    particle.velocity = (particle.velocity - omega * b * t) * decay;
+    return particle;
+}
+
+fn collapse(p: Particle) -> Particle {
+    return spring_to(p, mouse_position.position, command.params.x);
+}
+
+// A particle freshly emitted at the cursor: it leaves upwards within the spray half angle
+// at around the emission speed, plus the cursor's own motion, so moving the cursor sweeps
+// the stream
+fn emit(seed: u32) -> Particle {
+    let angle_rng = fast_random(seed);
+    let speed_rng = fast_random(angle_rng);
+    let angle = 1.5707964 + (f32_from_u32(angle_rng) * 2.0 - 1.0) * command.params.y;
+    // some speed variation, so the particles do not travel as a ring
+    let speed = command.params.x * (0.75 + 0.5 * f32_from_u32(speed_rng));
+
+    var particle: Particle;
+    particle.position = mouse_position.position;
+    particle.velocity = vec2<f32>(cos(angle), sin(angle)) * speed + mouse_position.fling_velocity;
+    particle.acceleration = vec2<f32>(0.0, 0.0);
+    return particle;
+}
+
+// Pulls a particle after its predecessor, stopping `link_length` short of it so the chain
+// keeps its length instead of piling up. The head chases the cursor itself.
+fn chain(index: u32, p: Particle) -> Particle {
+    if index == 0u {
+        return spring_to(p, mouse_position.position, command.params.x);
+    }
+
+    let leader = chain_positions[index - 1u];
+    let offset = p.position - leader;
+    let dist = length(offset);
+    var anchor = leader;
+    if dist > 0.0 {
+        anchor = mul_add(offset / dist, min(command.params.y, dist), anchor);
+    }
+    return spring_to(p, anchor, command.params.x);
+}
+
+// Pulls a particle to its slot of a phyllotaxis (sunflower) spiral around the cursor. Slot i
+// sits at radius sqrt(i / n) and turns by the golden angle from slot i - 1, which packs the
+// slots evenly over a disc.
+fn spiral(index: u32, p: Particle) -> Particle {
+    // the golden angle is 1 / phi^2 of a turn, 2^32 / phi^2 = 0x61C88647. Multiplying in
+    // integers wraps around exactly, where index * angle in f32 loses all precision once
+    // the index reaches the millions.
+    let turn = f32(index * 0x61C88647u) * 2.3283064e-10;
+    let angle = turn * 6.2831855;
+    let radius = command.params.y * sqrt(f32(index) / f32(max(time.particle_count, 1u)));
+    let anchor = mouse_position.position + radius * vec2<f32>(cos(angle), sin(angle));
+    return spring_to(p, anchor, command.params.x);
+}
+
+// Soft spring pulling particles back once they are within the margin of the screen edge,
+// so they stay visible without the velocity flip of bounce
+fn containment(position: vec2<f32>) -> vec2<f32> {
+    if simulation.keep_on_screen == 0u {
+        return vec2<f32>(0.0, 0.0);
+    }
+    let excess = max(abs(position) - (1.0 - simulation.keep_on_screen_margin), vec2<f32>(0.0));
+    return -sign(position) * excess * simulation.keep_on_screen_stiffness;
+}
+
+// Moves `position` by `step`. With high precision positions the rounding error of the sum
+// is carried over to the next step (Kahan summation), so small steps add up exactly
+// instead of being rounded away. Relies on the compiler not reassociating the float math.
+fn advance(index: u32, position: vec2<f32>, step: vec2<f32>) -> vec2<f32> {
+    if simulation.high_precision == 0u {
+        return position + step;
+    }
+    let low = position_low[index] + step;
+    let sum = position + low;
+    position_low[index] = low - (sum - position);
+    return sum;
+}
+
+// Reflects particles that left the [-1, 1] square back inside, losing some speed
+fn bounce(p: Particle) -> Particle {
+    var particle = p;
+
+    // Optimized boundary collision (avoid branch divergence where possible)
+    let pos_abs = abs(particle.position);
+    let x_overflow = pos_abs.x > 1.0;
+    let y_overflow = pos_abs.y > 1.0;
+
+    if x_overflow || y_overflow {
+        // Handle X boundary
+        if x_overflow {
+            let sign_x = sign(particle.position.x);
+            particle.velocity.x = -particle.velocity.x * 0.8;
+            particle.position.x = sign_x * 0.99;
+        }
+        
+        // Handle Y boundary
+        if y_overflow {
+            let sign_y = sign(particle.position.y);
+            particle.velocity.y = -particle.velocity.y * 0.8;
+            particle.position.y = sign_y * 0.99;
+        }
+    }
+
+    return particle;
+}
+
+// Flattens the 2D dispatch from state::dispatch_size back into a particle index. Rows are
+// num_workgroups.x workgroups wide, so this stays correct however wide the dispatch is.
+fn particle_index(global_id: vec3<u32>, num_workgroups: vec3<u32>) -> u32 {
+    return global_id.x + global_id.y * num_workgroups.x * WORKGROUP_SIZE;
+}
+
+// Cell of a position, positions outside the grid belong to the nearest edge cell
+fn cell_coords(position: vec2<f32>) -> vec2<i32> {
+    let coords = vec2<i32>(floor((position + 1.0) / grid.cell_size));
+    return clamp(coords, vec2<i32>(0), vec2<i32>(i32(grid.cells_per_axis) - 1));
+}
+
+fn cell_id(coords: vec2<i32>) -> u32 {
+    return u32(coords.y) * grid.cells_per_axis + u32(coords.x);
+}
+
+// One invocation per cell, dispatched in 2D tiles
+@compute @workgroup_size(TILE_SIZE, TILE_SIZE)
+fn clear_grid(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id.xy >= vec2<u32>(grid.cells_per_axis)) {
+        return;
+    }
+    atomicStore(&cell_counts[cell_id(vec2<i32>(global_id.xy))], 0u);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn count_grid(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = particle_index(global_id, num_workgroups);
+    if index >= time.particle_count {
+        return;
+    }
+    atomicAdd(&cell_counts[cell_id(cell_coords(particle_position(index)))], 1u);
+}
+
+var<workgroup> scan_sums: array<u32, WORKGROUP_SIZE>;
+
+// Exclusive prefix sum of the cell counts into cell_starts, run as a single workgroup.
+// Every invocation sums a contiguous chunk of cells, the chunk sums are scanned in
+// workgroup memory, then every invocation writes the starts of its chunk.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn scan_grid(@builtin(local_invocation_index) local: u32) {
+    let chunk = (grid.cell_count + WORKGROUP_SIZE - 1u) / WORKGROUP_SIZE;
+    let begin = min(local * chunk, grid.cell_count);
+    let end = min(begin + chunk, grid.cell_count);
+
+    var sum = 0u;
+    for (var cell = begin; cell < end; cell++) {
+        sum += atomicLoad(&cell_counts[cell]);
+    }
+    scan_sums[local] = sum;
+    workgroupBarrier();
+
+    // inclusive Hillis-Steele scan of the chunk sums
+    for (var offset = 1u; offset < WORKGROUP_SIZE; offset *= 2u) {
+        var value = scan_sums[local];
+        if local >= offset {
+            value += scan_sums[local - offset];
+        }
+        workgroupBarrier();
+        scan_sums[local] = value;
+        workgroupBarrier();
+    }
+
+    var start = scan_sums[local] - sum;
+    for (var cell = begin; cell < end; cell++) {
+        cell_starts[cell] = start;
+        start += atomicLoad(&cell_counts[cell]);
+        // reset to be reused as the fill cursor by scatter_grid
+        atomicStore(&cell_counts[cell], 0u);
+    }
+    if local == WORKGROUP_SIZE - 1u {
+        cell_starts[grid.cell_count] = scan_sums[local];
+    }
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn scatter_grid(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = particle_index(global_id, num_workgroups);
+    if index >= time.particle_count {
+        return;
+    }
+    let position = particle_position(index);
+    let cell = cell_id(cell_coords(position));
+    let slot = cell_starts[cell] + atomicAdd(&cell_counts[cell], 1u);
+    sorted_indices[slot] = index;
+    sorted_positions[slot] = position;
+}
+
+// Copies the positions Chain reads, dispatched right before the update
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn snapshot_positions(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = particle_index(global_id, num_workgroups);
+    if index >= time.particle_count {
+        return;
+    }
+    chain_positions[index] = particle_position(index);
+}
+
+// Shifts every particle's trail by one entry and records its position before it moves
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn record_trails(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = particle_index(global_id, num_workgroups);
+    if index >= time.particle_count {
+        return;
+    }
+    let position = particle_position(index);
+    let start = index * trail.length;
+    if trail.reset != 0u {
+        for (var i = 0u; i < trail.length; i++) {
+            trail_history[start + i] = position;
+        }
+        return;
+    }
+    for (var i = trail.length - 1u; i > 0u; i--) {
+        trail_history[start + i] = trail_history[start + i - 1u];
+    }
+    trail_history[start] = position;
+}
+
+// Pulls every particle of the net along its springs by Hooke's law and adds the net's
+// gravity and damping, dispatched right before the update. Only velocities are written, so
+// every particle sees its neighbors where they start the step.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn apply_springs(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = particle_index(global_id, num_workgroups);
+    if index >= net.particle_count || particle_pinned(index) {
+        return;
+    }
+
+    let position = particle_position(index);
+    let velocity = particle_velocity(index);
+    var acceleration = net.gravity - net.damping * velocity;
+    for (var slot = 0u; slot < SPRINGS_PER_PARTICLE; slot++) {
+        let spring = springs[index * SPRINGS_PER_PARTICLE + slot];
+        if spring.other == NO_SPRING {
+            continue;
+        }
+        let offset = particle_position(spring.other) - position;
+        let span = length(offset);
+        if span > 0.0 {
+            acceleration += spring.stiffness * (span - spring.rest_length) / span * offset;
+        }
+    }
+    set_particle_velocity(index, mul_add(acceleration, time.delta_time, velocity));
+}
+
+@compute @workgroup_size(1)
+fn clear_stats() {
+    atomicStore(&stats.max_speed, 0u);
+    atomicStore(&stats.kinetic_energy_low, 0u);
+    atomicStore(&stats.kinetic_energy_high, 0u);
+    atomicStore(&stats.finite_count, 0u);
+    atomicStore(&stats.position_sum_x_low, 0u);
+    atomicStore(&stats.position_sum_x_high, 0u);
+    atomicStore(&stats.position_sum_y_low, 0u);
+    atomicStore(&stats.position_sum_y_high, 0u);
+}
+
+// value * 2^16 as the low and high word of a 64 bit two's complement number. Magnitudes
+// are limited to 2^46, so a sum of many of them still fits.
+fn fixed_point(value: f32) -> vec2<u32> {
+    // the magnitude split at 2^32: the high word takes magnitude / 2^16
+    let magnitude = min(abs(value), 70368744177664.0);
+    let high = floor(magnitude / 65536.0);
+    let low = u32(min((magnitude - high * 65536.0) * 65536.0, 4294967040.0));
+    var fixed = vec2<u32>(low, u32(high));
+    if value < 0.0 {
+        fixed = vec2<u32>(~fixed.x + 1u, ~fixed.y + select(0u, 1u, fixed.x == 0u));
+    }
+    return fixed;
+}
+
+// Carry out of the low word when old + added wrapped around
+fn carry(old: u32, added: u32) -> u32 {
+    return select(0u, 1u, old + added < old);
+}
+
+// Adds a non-negative value to the fixed point kinetic energy, carrying from the low word
+// into the high one
+fn add_kinetic_energy(value: f32) {
+    let fixed = fixed_point(value);
+    let old = atomicAdd(&stats.kinetic_energy_low, fixed.x);
+    atomicAdd(&stats.kinetic_energy_high, fixed.y + carry(old, fixed.x));
+}
+
+// Adds a workgroup's position sum onto the fixed point sums in stats, as there are no
+// float atomics. The high words wrap around like two's complement numbers do, so
+// negative values add up correctly.
+fn add_position_sum(sum: vec2<f32>) {
+    let x = fixed_point(sum.x);
+    let old_x = atomicAdd(&stats.position_sum_x_low, x.x);
+    atomicAdd(&stats.position_sum_x_high, x.y + carry(old_x, x.x));
+    let y = fixed_point(sum.y);
+    let old_y = atomicAdd(&stats.position_sum_y_low, y.x);
+    atomicAdd(&stats.position_sum_y_high, y.y + carry(old_y, y.x));
+}
+
+var<workgroup> reduce_max: array<u32, WORKGROUP_SIZE>;
+var<workgroup> reduce_sum: array<f32, WORKGROUP_SIZE>;
+
+// Sum of `value` over the workgroup, returned to every invocation. Reuses reduce_sum, so
+// several sums fit the workgroup memory limit, one after the other.
+fn workgroup_sum(local: u32, value: f32) -> f32 {
+    reduce_sum[local] = value;
+    workgroupBarrier();
+    for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if local < stride {
+            reduce_sum[local] += reduce_sum[local + stride];
+        }
+        workgroupBarrier();
+    }
+    let sum = reduce_sum[0];
+    // everyone has read the sum before the next one overwrites it
+    workgroupBarrier();
+    return sum;
+}
+
+// Largest particle speed, total kinetic energy (unit masses) and the sum of the finite
+// positions: tree reductions per workgroup, then one atomic update per workgroup
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn reduce_stats(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    // no early return, every invocation has to reach the barriers
+    let index = particle_index(global_id, num_workgroups);
+    var speed = 0.0;
+    var position = vec2<f32>(0.0, 0.0);
+    var finite = 0.0;
+    if index < time.particle_count {
+        speed = length(particle_velocity(index));
+        // leaves out NaN and infinite speeds, whose bits would win every comparison
+        if !(speed < 3.4e38) {
+            speed = 0.0;
+        }
+        // comparisons with NaN are false, so this also leaves out NaN positions
+        let loaded = particle_position(index);
+        if all(abs(loaded) < vec2<f32>(3.4e38)) {
+            position = loaded;
+            finite = 1.0;
+        }
+    }
+    reduce_max[local] = bitcast<u32>(speed);
+    workgroupBarrier();
+
+    for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if local < stride {
+            reduce_max[local] = max(reduce_max[local], reduce_max[local + stride]);
+        }
+        workgroupBarrier();
+    }
+
+    let energy = workgroup_sum(local, 0.5 * speed * speed);
+    let sum_x = workgroup_sum(local, position.x);
+    let sum_y = workgroup_sum(local, position.y);
+    // exact, a workgroup counts far less than 2^24
+    let count = workgroup_sum(local, finite);
+
+    if local == 0u {
+        atomicMax(&stats.max_speed, reduce_max[0]);
+        add_kinetic_energy(energy);
+        atomicAdd(&stats.finite_count, u32(count));
+        add_position_sum(vec2<f32>(sum_x, sum_y));
+    }
+}
+
+// Acceleration pushing a particle away from every neighbor closer than grid.min_distance,
+// a stiff spring approximating hard sphere collisions. The spring is expressed as the
+// fraction of the overlap resolved in one step, which stays stable at any timestep.
+fn separation(index: u32, position: vec2<f32>) -> vec2<f32> {
+    if grid.min_distance <= 0.0 {
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    let min_distance_sq = grid.min_distance * grid.min_distance;
+    let center = cell_coords(position);
+    var push = vec2<f32>(0.0, 0.0);
+
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let coords = center + vec2<i32>(dx, dy);
+            if any(coords < vec2<i32>(0)) || any(coords >= vec2<i32>(i32(grid.cells_per_axis))) {
+                continue;
+            }
+
+            let cell = cell_id(coords);
+            let start = cell_starts[cell];
+            let end = min(cell_starts[cell + 1u], start + MAX_NEIGHBORS_PER_CELL);
+            for (var slot = start; slot < end; slot++) {
+                let other = sorted_indices[slot];
+                if other == index {
+                    continue;
+                }
+                let offset = position - sorted_positions[slot];
+                let dist_sq = dot(offset, offset);
+                if dist_sq >= min_distance_sq {
+                    continue;
+                }
+
+                var dist = sqrt(dist_sq);
+                var normal: vec2<f32>;
+                if dist > 0.0 {
+                    if FAST_MATH {
+                        // one approximate inverse square root instead of the exact root
+                        // and a division, off by a few ulp
+                        let inv_dist = inverseSqrt(dist_sq);
+                        dist = dist_sq * inv_dist;
+                        normal = offset * inv_dist;
+                    } else {
+                        normal = offset / dist;
+                    }
+                } else {
+                    // coincident pair, split along a direction derived from both indices,
+                    // flipped for one of them so they move apart
+                    let angle = f32_from_u32(fast_random(min(index, other) * 31u + max(index, other))) * 6.2831853;
+                    normal = vec2<f32>(cos(angle), sin(angle)) * select(-1.0, 1.0, index < other);
+                }
+                push += normal * (grid.min_distance - dist);
+            }
+        }
+    }
+
+    // both particles of a pair move, so half the stiffness closes that fraction of the gap
+    let dt = max(time.delta_time, 1e-6);
+    return push * (0.5 * grid.min_distance_stiffness) / (dt * dt);
+}
+
+// Continuous cellular automaton on the neighbor grid: a particle with fewer than
+// params.z neighbors within params.x is pulled towards their center of mass, one with more
+// than params.w is pushed away from it, and anything in between coasts. The pull and push
+// grow with the distance from the thresholds, so sparse groups gather and crowded ones
+// shed particles until the whole cloud settles into blobs of a preferred density.
+fn life(index: u32, position: vec2<f32>) -> vec2<f32> {
+    let radius = command.params.x;
+    if command.command != COMMAND_LIFE || radius <= 0.0 {
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    let radius_sq = radius * radius;
+    let center = cell_coords(position);
+    var count = 0u;
+    var sum = vec2<f32>(0.0, 0.0);
+
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let coords = center + vec2<i32>(dx, dy);
+            if any(coords < vec2<i32>(0)) || any(coords >= vec2<i32>(i32(grid.cells_per_axis))) {
+                continue;
+            }
+
+            let cell = cell_id(coords);
+            let start = cell_starts[cell];
+            let end = min(cell_starts[cell + 1u], start + MAX_NEIGHBORS_PER_CELL);
+            for (var slot = start; slot < end; slot++) {
+                if sorted_indices[slot] == index {
+                    continue;
+                }
+                let offset = sorted_positions[slot] - position;
+                if dot(offset, offset) < radius_sq {
+                    count++;
+                    sum += offset;
+                }
+            }
+        }
+    }
+
+    if count == 0u {
+        // nothing to gather around
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    let neighbors = f32(count);
+    let min_neighbors = max(command.params.z, 1.0);
+    let max_neighbors = max(command.params.w, 1.0);
+    // positive below min_neighbors, negative above max_neighbors, 0 in between
+    let pull = clamp((min_neighbors - neighbors) / min_neighbors, 0.0, 1.0)
+        - clamp((neighbors - max_neighbors) / max_neighbors, 0.0, 1.0);
+    // offset to the center of mass in units of the radius
+    let toward = sum / (neighbors * radius);
+    return command.params.y * pull * toward;
+}
+
+// Pulls a particle towards the center of mass of the neighbors of its own color band
+// within grid.band_cohesion_radius, so the bands gather into patches of one color
+fn band_cohesion(index: u32, position: vec2<f32>) -> vec2<f32> {
+    if grid.band_cohesion <= 0.0 {
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    let radius = grid.band_cohesion_radius;
+    let radius_sq = radius * radius;
+    let band = index % grid.color_bands;
+    let center = cell_coords(position);
+    var count = 0u;
+    var sum = vec2<f32>(0.0, 0.0);
+
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let coords = center + vec2<i32>(dx, dy);
+            if any(coords < vec2<i32>(0)) || any(coords >= vec2<i32>(i32(grid.cells_per_axis))) {
+                continue;
+            }
+
+            let cell = cell_id(coords);
+            let start = cell_starts[cell];
+            let end = min(cell_starts[cell + 1u], start + MAX_NEIGHBORS_PER_CELL);
+            for (var slot = start; slot < end; slot++) {
+                let other = sorted_indices[slot];
+                if other == index || other % grid.color_bands != band {
+                    continue;
+                }
+                let offset = sorted_positions[slot] - position;
+                if dot(offset, offset) < radius_sq {
+                    count++;
+                    sum += offset;
+                }
+            }
+        }
+    }
+
+    if count == 0u {
+        return vec2<f32>(0.0, 0.0);
+    }
+    // offset to the center of mass in units of the radius
+    return grid.band_cohesion * sum / (f32(count) * radius);
+}
+
+// Random walk: a gaussian velocity kick every step, drawn from a hash of the particle and
+// the frame. Kicks with a standard deviation of params.x * sqrt(dt) make the spread of a
+// cloud grow the same way over time whatever the step size, so the acceleration is the
+// kick divided by dt.
+fn brownian(index: u32) -> vec2<f32> {
+    let strength = command.params.x;
+    if command.command != COMMAND_BROWNIAN || strength <= 0.0 {
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    let rng = fast_random(index * 2654435761u + time.frame * 2246822519u + 3u);
+    // Box-Muller, the first uniform is kept away from 0 so the log stays finite
+    let u1 = max(f32_from_u32(rng), 1e-7);
+    let u2 = f32_from_u32(fast_random(rng));
+    let gaussian = sqrt(-2.0 * log(u1)) * vec2<f32>(cos(6.2831853 * u2), sin(6.2831853 * u2));
+    return strength * gaussian * inverseSqrt(max(time.delta_time, 1e-6));
+}
+
+// Vectors FieldFlow follows, an image stretched over the window with its first row at the
+// bottom. A single zero texel without field_texture.
+@group(0) @binding(17) var field_texture: texture_2d<f32>;
+
+// The field_texture vector at `position`, interpolated between the four nearest texels
+// since Rg32Float cannot be filtered by a sampler. Positions outside the window take the
+// vector at the nearest edge.
+fn sample_flow_field(position: vec2<f32>) -> vec2<f32> {
+    let size = vec2<f32>(textureDimensions(field_texture));
+    let texel = clamp((position * 0.5 + 0.5) * size - 0.5, vec2<f32>(0.0, 0.0), size - 1.0);
+    let first = vec2<i32>(floor(texel));
+    let last = min(first + 1, vec2<i32>(size) - 1);
+    let t = texel - floor(texel);
+    let bottom = mix(
+        textureLoad(field_texture, first, 0).xy,
+        textureLoad(field_texture, vec2<i32>(last.x, first.y), 0).xy,
+        t.x,
+    );
+    let top = mix(
+        textureLoad(field_texture, vec2<i32>(first.x, last.y), 0).xy,
+        textureLoad(field_texture, last, 0).xy,
+        t.x,
+    );
+    return mix(bottom, top, t.y);
+}
+
+// FieldFlow: params.x times the field vector under the particle, as an acceleration, or
+// with a steering rate in params.y as the velocity the particle's own approaches. The rate
+// is capped at one step, so the velocity never overshoots the field's.
+fn field_flow(particle: Particle) -> vec2<f32> {
+    if command.command != COMMAND_FIELD_FLOW {
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    let flow = command.params.x * sample_flow_field(particle.position);
+    let steering = command.params.y;
+    if steering > 0.0 {
+        let rate = min(steering, 1.0 / max(time.delta_time, 1e-6));
+        return rate * (flow - particle.velocity);
+    }
+    return flow;
+}
 
 // Increased workgroup size from 64 to 256 for better GPU utilization
-@compute @workgroup_size(1024)
-fn update_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    // Calculate the actual particle index from 2D dispatch
-    let index = global_id.x + global_id.y * 65535u * 1024u;
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn update_particles(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = particle_index(global_id, num_workgroups);
 
+    // The dispatch is rounded up to whole workgroups, the invocations past the last
+    // particle must not touch the buffer
     if index >= time.particle_count {
         return;
     }
 
+    // Anchors stay where they were pinned, only the forces on the others see them
+    if particle_pinned(index) {
+        return;
+    }
+
     switch command.command {
-        case 1u: {
+        case COMMAND_SHUFFLE: {
             // "Shuffle" mode, randomly shift the positions of particles by a small amount
             let rng = fast_random(index);
+            let amount = command.params.y;
 
             let small_shift = vec2<f32>(
-                f32_from_u32(rng) * NUDGE_AMOUNT - NUDGE_AMOUNT * 0.5,
-                f32_from_u32(fast_random(rng)) * NUDGE_AMOUNT - NUDGE_AMOUNT * 0.5
+                f32_from_u32(rng) * amount - amount * 0.5,
+                f32_from_u32(fast_random(rng)) * amount - amount * 0.5
             );
-            particles[index].position += small_shift;
+            set_particle_position(index, particle_position(index) + small_shift);
+        }
+
+        case COMMAND_COLLAPSE: {
+            // "Collapse" mode, replaces the cursor attraction below entirely
+            store_particle(index, collapse(load_particle(index)));
+            return;
+        }
+
+        case COMMAND_CHAIN: {
+            // "Chain" mode, also replaces the cursor attraction
+            store_particle(index, bounce(chain(index, load_particle(index))));
+            return;
+        }
+
+        case COMMAND_SPIRAL: {
+            // "Spiral" mode, replaces the cursor attraction with a spring to the particle's slot
+            store_particle(index, bounce(spiral(index, load_particle(index))));
+            return;
+        }
+
+        case COMMAND_EMIT: {
+            // "Emit" mode, particles respawn at the cursor once they expire or fly too far.
+            // Expiring with probability dt / lifetime every step gives exponentially
+            // distributed lifetimes with that mean, without keeping an age per particle.
+            let offset = particle_position(index) - mouse_position.position;
+            let max_distance = command.params.w;
+            let rng = fast_random(index * 747796405u + time.frame * 2891336453u + 1u);
+            let expired = f32_from_u32(rng) < time.delta_time / command.params.z;
+            if expired || dot(offset, offset) > max_distance * max_distance {
+                store_particle(index, emit(rng));
+                return;
+            }
+        }
+
+        case COMMAND_GRAB: {
+            // "Grab" mode, particles near the held cursor move rigidly with it
+            let offset = particle_position(index) - mouse_position.position;
+            let radius = command.params.x;
+            if mouse_position.left_pressed != 0u && dot(offset, offset) < radius * radius {
+                var particle = load_particle(index);
+                // the raw velocity is exactly this step's cursor displacement, the smoothed
+                // one survives the step the button is released in as the fling
+                particle.position += mouse_position.velocity * time.delta_time;
+                particle.velocity = mouse_position.fling_velocity;
+                particle.acceleration = vec2<f32>(0.0, 0.0);
+                store_particle(index, bounce(particle));
+                return;
+            }
         }
 
         default: {
-            // this mode includes 0, which is the "Roam" mode
+            // this mode includes COMMAND_ROAM
             // no operation
         }
     }
     
     // Get current particle data (reduces redundant memory access)
-    var particle = particles[index];
-    
-    // Calculate direction to mouse position
-    let direction = mouse_position.position - particle.position;
+    var particle = load_particle(index);
+
+    // Acceleration is rebuilt from the active forces every step, unless it is configured
+    // to persist, in which case the forces below add onto last step's acceleration
+    if simulation.accumulate_acceleration == 0u {
+        particle.acceleration = vec2<f32>(0.0, 0.0);
+    }
+    particle.acceleration += separation(index, particle.position);
+    particle.acceleration += life(index, particle.position);
+    particle.acceleration += band_cohesion(index, particle.position);
+    particle.acceleration += brownian(index);
+    particle.acceleration += field_flow(particle);
+    particle.acceleration += containment(particle.position);
     
-    // Early-out for particles that are too far from mouse to be affected significantly
-    let dist_sq = dot(direction, direction);
+    // Early-out for particles that are too far from the attractors to be affected significantly
+    let primary_offset = mouse_position.position - particle.position;
+    let secondary_offset = mouse_position.secondary - particle.position;
+    var dist_sq = dot(primary_offset, primary_offset);
+    if mouse_position.secondary_active != 0u {
+        dist_sq = min(dist_sq, dot(secondary_offset, secondary_offset));
+    }
     if dist_sq > 10.0 {
         // Only apply minimal updates for distant particles
-        particle.position += particle.velocity * time.delta_time;
-        particles[index] = particle;
+        particle.velocity = mul_add(particle.acceleration, time.delta_time, particle.velocity);
+        particle.position = advance(index, particle.position, particle.velocity * time.delta_time);
+        store_particle(index, particle);
         return;
     }
-    
-    // Optimized acceleration calculation (combined operations)
-    let unit_size: f32 = 0.1;
-    let scaled_dir = direction * unit_size;
-    let mag_factor = 1.0 / (dot(scaled_dir, scaled_dir) + 0.1);
-    
-    // Directly compute normalized direction * magnitude factor
-    particle.acceleration = normalize(direction) * mag_factor;
+
+    particle.acceleration += field_acceleration(command, mouse_position, simulation, particle.position);
+
+    if command.command == COMMAND_DUST {
+        // every particle samples the noise at its own time offset, so neighbours drift apart
+        // while each one still moves smoothly from step to step
+        let phase = f32_from_u32(fast_random(index + 1u)) * 8.0;
+        let t = time.elapsed_time * command.params.w + phase;
+        particle.acceleration += command.params.y * curl_noise(particle.position * command.params.z, t);
+    }
     
     // Update velocity (combine calculations)
-    particle.velocity = particle.velocity * 0.99999 + particle.acceleration * time.delta_time;
+    particle.velocity = mul_add(particle.acceleration, time.delta_time, particle.velocity * (1.0 - simulation.drag));
     
     // Update position
-    particle.position += particle.velocity * time.delta_time;
+    particle.position = advance(index, particle.position, particle.velocity * time.delta_time);
     
-    // Optimized boundary collision (avoid branch divergence where possible)
-    let pos_abs = abs(particle.position);
-    let x_overflow = pos_abs.x > 1.0;
-    let y_overflow = pos_abs.y > 1.0;
+    // Write back particle data in one operation
+    store_particle(index, bounce(particle));
+}
 
-    if x_overflow || y_overflow {
-        // Handle X boundary
-        if x_overflow {
-            let sign_x = sign(particle.position.x);
-            particle.velocity.x = -particle.velocity.x * 0.8;
-            particle.position.x = sign_x * 0.99;
-        }
-        
-        // Handle Y boundary
-        if y_overflow {
-            let sign_y = sign(particle.position.y);
-            particle.velocity.y = -particle.velocity.y * 0.8;
-            particle.position.y = sign_y * 0.99;
-        }
+// Plain ballistic integration without any cursor or command forces
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn integrate_particles(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = particle_index(global_id, num_workgroups);
+
+    if index >= time.particle_count {
+        return;
     }
-    
-    // Write back particle data in one operation
-    particles[index] = particle;
+
+    // Anchors stay put here as well
+    if particle_pinned(index) {
+        return;
+    }
+
+    var particle = load_particle(index);
+    if simulation.accumulate_acceleration == 0u {
+        particle.acceleration = vec2<f32>(0.0, 0.0);
+    }
+    particle.velocity = mul_add(particle.acceleration, time.delta_time, particle.velocity);
+    particle.position = advance(index, particle.position, particle.velocity * time.delta_time);
+    store_particle(index, bounce(particle));
+}
+
+// Fades every particle's tint and gives the ones near the cursor the tint color, dispatched
+// after the update so the tint follows where the particles ended up
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn update_tints(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = particle_index(global_id, num_workgroups);
+    if index >= time.particle_count || index >= arrayLength(&tints) {
+        return;
+    }
+
+    var color = tints[index];
+    color.a *= exp(-time.delta_time / max(tint.fade_time, 1e-6));
+    let offset = particle_position(index) - mouse_position.position;
+    if dot(offset, offset) < tint.radius * tint.radius {
+        color = tint.color;
+    }
+    tints[index] = color;
 }
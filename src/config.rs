@@ -0,0 +1,1340 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    grid,
+    quality::DynamicQualityConfig,
+    state,
+    supersample::MAX_SUPERSAMPLE,
+    trails,
+    types::{Command, Particle},
+};
+
+/// Range `intensity` is limited to, where particles neither vanish nor saturate at once.
+pub const MIN_INTENSITY: f32 = 0.01;
+pub const MAX_INTENSITY: f32 = 100.0;
+
+/// Range the quad size keys and the overlay slider move `quad_size` in.
+pub const MIN_QUAD_SIZE: f32 = 0.0001;
+pub const MAX_QUAD_SIZE: f32 = 0.05;
+
+/// Most sides `particle_segments` gives a particle.
+pub const MAX_PARTICLE_SEGMENTS: u32 = 64;
+
+/// Most integration substeps run per frame, each costs a full compute pass.
+pub const MAX_SUBSTEPS: u32 = 16;
+
+/// Range `time_scale` is limited to. Frames are capped at 0.1 simulated seconds anyway.
+pub const MIN_TIME_SCALE: f32 = 1.0 / 64.0;
+pub const MAX_TIME_SCALE: f32 = 8.0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfiguration {
+    pub num_particles: u32,
+    pub quad_size: f32,
+    /// Inner size of the window in physical pixels, updated when the window is closed.
+    pub window_width: u32,
+    pub window_height: u32,
+    /// Outer position of the window in physical pixels, left to the window manager if unset.
+    pub window_position: Option<[i32; 2]>,
+    pub window_title: String,
+    /// Key that quits like closing the window, either the character it types (e.g. "q")
+    /// or the name of a special key (e.g. "Escape", "F10"). Empty disables it.
+    pub quit_key: String,
+    /// Only quit once the quit key is pressed a second time shortly after the first.
+    pub confirm_quit: bool,
+    /// Simulated milliseconds after a command key during which further command keys are
+    /// ignored, 0 for none. Does not run out while paused.
+    pub command_cooldown_ms: u64,
+    /// Switch to the next of `auto_cycle_commands` every this many simulated seconds, for
+    /// unattended displays. `None` leaves the command to the user.
+    pub auto_cycle: Option<f32>,
+    /// Command names `auto_cycle` rotates through in order, every command if empty.
+    pub auto_cycle_commands: Vec<String>,
+    /// Simulated seconds without input before `auto_cycle` resumes after any input.
+    pub auto_cycle_resume_after: f32,
+    /// Index of the adapter to render with, as listed by `--list-adapters`. The adapter
+    /// best suited to the window is picked if unset.
+    pub adapter: Option<usize>,
+    /// Set by `--adapter`, which takes precedence over `adapter` for this run only and is
+    /// never written to the file.
+    #[serde(skip)]
+    pub adapter_override: Option<usize>,
+    /// Width / height ratio the window is kept at, if any.
+    pub lock_aspect: Option<f32>,
+    /// Accumulate particles additively in a floating point target and tonemap it to the screen.
+    pub hdr: bool,
+    /// Curve compressing the HDR target into displayable range.
+    pub tonemap: Tonemap,
+    /// Brightness multiplier applied to the HDR target before tonemapping.
+    pub exposure: f32,
+    /// Draw every particle as a small Gaussian splat blended into the HDR target instead
+    /// of a hard quad, so millions of tiny particles blend into smooth densities instead
+    /// of aliasing. Turns on the HDR target, whose tonemap pass resolves the splats.
+    pub smooth_render: bool,
+    /// Radius of a splat in pixels with `smooth_render`, replacing `quad_size`.
+    pub splat_radius: f32,
+    /// Draw the trails and particles at this many times the window resolution on each axis
+    /// and average them back down, anti-aliasing them for stills and recordings. 1 draws
+    /// at the window resolution. Limited to `1..=MAX_SUPERSAMPLE`.
+    pub supersample: f32,
+    /// Multiplier of every particle's color, to match additive blending to the particle
+    /// density. Limited to `MIN_INTENSITY..=MAX_INTENSITY`.
+    pub intensity: f32,
+    /// Frames the GPU may queue ahead of the display, 1 for the lowest latency up to 3 for
+    /// the smoothest frame pacing.
+    pub max_frame_latency: u32,
+    /// Keep each particle's acceleration between steps instead of rebuilding it from the
+    /// active forces every step, so forces build up momentum over time.
+    pub accumulate_acceleration: bool,
+    /// Fraction of velocity lost every step near the cursor.
+    pub drag: f32,
+    /// Seed of the random starting particles, so runs with the same seed start from the
+    /// same ones. A new seed every run when absent; `run --record` stores the one it used
+    /// for `replay`.
+    pub seed: Option<u64>,
+    /// Velocity every particle starts with, before the random spread is added.
+    pub initial_velocity_bias: [f32; 2],
+    /// Largest random deviation from the bias on each axis of the starting velocity.
+    pub initial_velocity_spread: f32,
+    /// Acceleration every particle starts with.
+    pub initial_acceleration: InitialAcceleration,
+    /// CSV file of `x,y,vx,vy` rows the particles start from instead of random ones. The
+    /// number of rows replaces `num_particles`.
+    pub initial_particles: Option<PathBuf>,
+    /// PFM image of the vectors FieldFlow steers the particles by, stretched over the
+    /// window.
+    pub field_texture: Option<PathBuf>,
+    /// Added to the squared distance of inverse distance forces, keeping them finite next
+    /// to an attractor.
+    pub softening: f32,
+    /// Particles closer than this push each other apart, 0 disables it.
+    pub min_distance: f32,
+    /// Fraction of the overlap of a pair closer than `min_distance` that is resolved per
+    /// step.
+    pub min_distance_stiffness: f32,
+    /// What is drawn behind the particles.
+    pub background: Background,
+    /// Step size in seconds used when recording, replaying or single-stepping.
+    pub fixed_timestep: f32,
+    /// Integration steps every frame is split into, each advancing by an equal share of the
+    /// frame time. More substeps keep stiff springs and close encounters stable at the cost
+    /// of one compute pass each. Limited to `1..=MAX_SUBSTEPS`.
+    pub substeps: u32,
+    /// Record all substeps of a frame into one command buffer and submit it once, instead
+    /// of submitting every substep on its own.
+    pub batch_substeps: bool,
+    /// Simulated seconds per second of frame time, for slow motion below 1 and fast
+    /// forward above. Scales the fixed timestep as well. Limited to
+    /// `MIN_TIME_SCALE..=MAX_TIME_SCALE`.
+    pub time_scale: f32,
+    /// Strength of the pull towards the cursor, relative to the command's strength.
+    pub primary_attractor_weight: f32,
+    /// Strength of the pull towards the attractor placed with the right mouse button.
+    pub secondary_attractor_weight: f32,
+    /// Length of the velocity lines drawn per unit of speed.
+    pub velocity_vector_scale: f32,
+    /// Slowly turn the drawn particles around the screen center, for screensaver-like
+    /// output. The cursor, picking and the field and grid views turn along.
+    pub auto_rotate: bool,
+    /// Angular speed of `auto_rotate` in radians per second, negative turns clockwise.
+    pub auto_rotate_speed: f32,
+    /// Draw particles as instances of a 6 vertex quad instead of one large vertex range.
+    pub instanced_rendering: bool,
+    /// Draw every particle as a regular polygon with this many sides instead of a quad,
+    /// which looks round at large sizes but takes 3 vertices per side. Below 3 keeps the
+    /// quad. Limited to `MAX_PARTICLE_SEGMENTS`.
+    pub particle_segments: u32,
+    /// Draw faster particles in front of slower ones through a depth buffer, instead of in
+    /// buffer order. Has no effect with `hdr`, whose additive blending ignores order.
+    pub depth_sort: bool,
+    /// Let particles paint onto a persistent canvas shown behind them, building up a
+    /// drawing of their paths until it is cleared.
+    pub canvas_mode: bool,
+    /// Fraction of a particle's color added to the canvas per frame.
+    pub canvas_deposit: f32,
+    /// Recent positions drawn as a fading line behind every particle, 0 disables trails.
+    /// At most `trails::MAX_TRAIL_LENGTH`.
+    pub trail_length: u32,
+    /// How much every particle's size and brightness swing around their usual values,
+    /// from 0 (off) to 1, each particle offset by its own phase so they twinkle rather
+    /// than beat together.
+    pub pulse_amount: f32,
+    /// Pulse cycles per simulated second, so the pulse stops while paused.
+    pub pulse_speed: f32,
+    /// Color particle i with the hue of band `i % color_bands`, spread evenly around the
+    /// color wheel, instead of by its velocity. 0 keeps the velocity colors.
+    pub color_bands: u32,
+    /// Acceleration pulling every particle towards the neighbors of its own color band
+    /// within `band_cohesion_radius`, so the bands gather into patches of one color. 0
+    /// disables it, as do fewer than 2 `color_bands`.
+    pub band_cohesion: f32,
+    pub band_cohesion_radius: f32,
+    /// Tint of the particles per command name, multiplied into the velocity or band colors
+    /// so the active command shows. The alpha blends between no tint at 0 and the full
+    /// tint at 1. Commands left out use their default tint.
+    pub command_colors: BTreeMap<String, [f32; 4]>,
+    /// Hide the OS cursor over the window and draw a ring at the cursor instead, marking
+    /// the point the particles are attracted to.
+    pub show_custom_cursor: bool,
+    /// Gently pull particles back once they come within `keep_on_screen_margin` of the
+    /// screen edge, with a spring of `keep_on_screen_stiffness`.
+    pub keep_on_screen: bool,
+    pub keep_on_screen_margin: f32,
+    pub keep_on_screen_stiffness: f32,
+    /// Keep every position as a high and low f32 pair while integrating, so steps far below
+    /// the f32 resolution of a position still move it.
+    pub high_precision: bool,
+    /// Compile the compute shader with fused multiply-adds and approximate inverse square
+    /// roots, trading bit-exact results for speed. Only read on startup.
+    pub fast_math: bool,
+    /// Simulation steps of `fixed_timestep` run before the first frame is shown, so the
+    /// initial distribution has settled by then.
+    pub warmup_steps: u32,
+    /// Reduce the total kinetic energy on the GPU and show it, with its drift since the
+    /// command started, in the F1 overlay.
+    pub show_kinetic_energy: bool,
+    /// Frames between reductions of the particle statistics (speed, energy, center of
+    /// mass), each read back asynchronously. At least 1.
+    pub stats_interval: u32,
+    /// Frames between two particle dumps of `--dump-particles`. At least 1.
+    pub dump_interval: u32,
+    /// Springs joining the first particles into a cloth-like net.
+    pub net: NetConfig,
+    /// A color per particle, given by the cursor and fading over time.
+    pub tint: TintConfig,
+    /// Lower redraw rate while the scene is static.
+    pub idle: IdleConfig,
+    /// Automatic particle count reduction when the frame rate drops.
+    pub dynamic_quality: DynamicQualityConfig,
+    /// Tunables of every command, the active command's are uploaded each frame.
+    pub commands: CommandsConfig,
+}
+
+impl Default for GameConfiguration {
+    fn default() -> Self {
+        Self {
+            num_particles: 1000,
+            quad_size: 0.001,
+            window_width: 1280,
+            window_height: 720,
+            window_position: None,
+            window_title: "Red Triangle".to_string(),
+            quit_key: "Escape".to_string(),
+            confirm_quit: false,
+            command_cooldown_ms: 0,
+            auto_cycle: None,
+            auto_cycle_commands: Vec::new(),
+            auto_cycle_resume_after: 10.0,
+            adapter: None,
+            adapter_override: None,
+            lock_aspect: None,
+            hdr: false,
+            tonemap: Tonemap::Reinhard,
+            exposure: 1.0,
+            smooth_render: false,
+            splat_radius: 1.5,
+            supersample: 1.0,
+            intensity: 1.0,
+            max_frame_latency: 1,
+            accumulate_acceleration: false,
+            drag: 0.00001,
+            seed: None,
+            initial_velocity_bias: [0.0, 0.0],
+            initial_velocity_spread: 0.1,
+            initial_acceleration: InitialAcceleration::Zero,
+            initial_particles: None,
+            field_texture: None,
+            softening: 0.1,
+            min_distance: 0.0,
+            min_distance_stiffness: 0.25,
+            background: Background::default(),
+            fixed_timestep: 1.0 / 60.0,
+            substeps: 1,
+            batch_substeps: true,
+            time_scale: 1.0,
+            primary_attractor_weight: 1.0,
+            secondary_attractor_weight: 1.0,
+            velocity_vector_scale: 0.1,
+            auto_rotate: false,
+            auto_rotate_speed: 0.1,
+            instanced_rendering: false,
+            particle_segments: 0,
+            depth_sort: false,
+            canvas_mode: false,
+            canvas_deposit: 0.05,
+            trail_length: 0,
+            pulse_amount: 0.0,
+            pulse_speed: 1.0,
+            color_bands: 0,
+            command_colors: Command::ALL
+                .into_iter()
+                .map(|command| (command.name().to_string(), command.default_color()))
+                .collect(),
+            band_cohesion: 0.0,
+            band_cohesion_radius: 0.05,
+            show_custom_cursor: false,
+            keep_on_screen: false,
+            keep_on_screen_margin: 0.05,
+            keep_on_screen_stiffness: 50.0,
+            high_precision: false,
+            fast_math: false,
+            warmup_steps: 0,
+            show_kinetic_energy: false,
+            stats_interval: 10,
+            dump_interval: 1,
+            net: NetConfig::default(),
+            tint: TintConfig::default(),
+            idle: IdleConfig::default(),
+            dynamic_quality: DynamicQualityConfig::default(),
+            commands: CommandsConfig::default(),
+        }
+    }
+}
+
+impl GameConfiguration {
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        // read from the path, or create it if it doesnt exist with default.
+        if path.exists() {
+            let config = Self::load(path)?;
+            info!("loaded configuration from {}", path.display());
+            Ok(config)
+        } else {
+            let default_config = GameConfiguration::default();
+            default_config.save(path)?;
+            info!("wrote default configuration to {}", path.display());
+            Ok(default_config)
+        }
+    }
+
+    /// Reads a configuration file, failing if it does not exist.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Writes the configuration back to `path` as pretty JSON.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// `max_frame_latency` limited to the range drivers handle well.
+    pub fn frame_latency(&self) -> u32 {
+        self.max_frame_latency.clamp(1, 3)
+    }
+
+    /// Whether the same-band cohesion force is applied, which needs the spatial grid.
+    pub fn band_cohesion_enabled(&self) -> bool {
+        self.color_bands >= 2 && self.band_cohesion > 0.0 && self.band_cohesion_radius > 0.0
+    }
+
+    /// Index of the adapter to render with, if one was chosen.
+    pub fn adapter(&self) -> Option<usize> {
+        self.adapter_override.or(self.adapter)
+    }
+
+    /// The tint of the particles while `command` is active.
+    pub fn command_color(&self, command: Command) -> [f32; 4] {
+        self.command_colors
+            .get(command.name())
+            .copied()
+            .unwrap_or_else(|| command.default_color())
+    }
+
+    /// The commands `auto_cycle` rotates through, leaving out unknown names.
+    pub fn auto_cycle_commands(&self) -> Vec<Command> {
+        if self.auto_cycle_commands.is_empty() {
+            return Command::ALL.to_vec();
+        }
+        self.auto_cycle_commands
+            .iter()
+            .filter_map(|name| Command::from_name(name))
+            .collect()
+    }
+
+    /// `substeps` limited to the range that keeps at least one step per frame.
+    pub fn substeps(&self) -> u32 {
+        self.substeps.clamp(1, MAX_SUBSTEPS)
+    }
+
+    /// Sides of the polygon every particle is drawn as, 0 for a quad.
+    pub fn particle_segments(&self) -> u32 {
+        if self.particle_segments < 3 {
+            0
+        } else {
+            self.particle_segments.min(MAX_PARTICLE_SEGMENTS)
+        }
+    }
+
+    /// Vertices drawn per particle, 6 for a quad or 3 for each side of its polygon.
+    pub fn vertices_per_particle(&self) -> u32 {
+        match self.particle_segments() {
+            0 => 6,
+            segments => segments * 3,
+        }
+    }
+
+    /// `pulse_amount` limited to 0..=1, 0 if it is not a number.
+    pub fn clamped_pulse_amount(&self) -> f32 {
+        if self.pulse_amount.is_nan() {
+            0.0
+        } else {
+            self.pulse_amount.clamp(0.0, 1.0)
+        }
+    }
+
+    /// Position in the pulse cycle after `elapsed_time` simulated seconds, in [0, 1).
+    /// Computed in f64, an f32 elapsed time loses the fraction on long runs.
+    pub fn pulse_phase(&self, elapsed_time: f64) -> f32 {
+        let phase = (elapsed_time * self.pulse_speed as f64).rem_euclid(1.0) as f32;
+        if phase.is_finite() { phase } else { 0.0 }
+    }
+
+    /// `supersample` limited to its range, 1 if it is not a number.
+    pub fn supersample(&self) -> f32 {
+        if self.supersample.is_nan() {
+            1.0
+        } else {
+            self.supersample.clamp(1.0, MAX_SUPERSAMPLE)
+        }
+    }
+
+    /// `time_scale` limited to its range, 1 if it is not a number.
+    pub fn time_scale(&self) -> f32 {
+        if self.time_scale.is_nan() {
+            1.0
+        } else {
+            self.time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE)
+        }
+    }
+
+    /// `intensity` limited to its range, 1 if it is not a number.
+    pub fn clamped_intensity(&self) -> f32 {
+        if self.intensity.is_nan() {
+            return 1.0;
+        }
+        self.intensity.clamp(MIN_INTENSITY, MAX_INTENSITY)
+    }
+
+    /// Logs a warning for every setting that is likely to misbehave at runtime.
+    pub fn validate(&self) {
+        if self.num_particles == 0 {
+            warn!("num_particles is 0, nothing will be simulated");
+        }
+
+        let buffer_size = self.num_particles as u64 * std::mem::size_of::<Particle>() as u64;
+        if buffer_size > state::MAX_STORAGE_BUFFER_BINDING_SIZE as u64 {
+            warn!(
+                "num_particles = {} needs a {} byte particle buffer, which exceeds the {} byte binding limit",
+                self.num_particles,
+                buffer_size,
+                state::MAX_STORAGE_BUFFER_BINDING_SIZE
+            );
+        }
+
+        if self.quad_size.is_nan() || self.quad_size <= 0.0 {
+            warn!(
+                "quad_size = {} is not positive, particles will be invisible",
+                self.quad_size
+            );
+        } else if self.quad_size > 1.0 {
+            warn!(
+                "quad_size = {} is larger than the whole screen",
+                self.quad_size
+            );
+        }
+
+        if self.window_width == 0 || self.window_height == 0 {
+            warn!(
+                "window size {}x{} is empty, the window manager will pick one",
+                self.window_width, self.window_height
+            );
+        }
+
+        if let Some(aspect) = self.lock_aspect
+            && (aspect.is_nan() || aspect <= 0.0)
+        {
+            warn!("lock_aspect = {} is not positive, ignoring it", aspect);
+        }
+
+        if self.initial_velocity_spread.is_nan() || self.initial_velocity_spread < 0.0 {
+            warn!(
+                "initial_velocity_spread = {} is negative, particles start with exactly initial_velocity_bias",
+                self.initial_velocity_spread
+            );
+        }
+
+        if self.softening.is_nan() || self.softening <= 0.0 {
+            warn!(
+                "softening = {} is not positive, attraction becomes unbounded next to an attractor",
+                self.softening
+            );
+        }
+
+        if self.min_distance.is_nan() || self.min_distance < 0.0 {
+            warn!(
+                "min_distance = {} is negative, particles will be allowed to overlap",
+                self.min_distance
+            );
+        } else if self.min_distance > 0.0 {
+            if self.min_distance < 2.0 / grid::MAX_CELLS_PER_AXIS as f32 {
+                warn!(
+                    "min_distance = {} is smaller than a grid cell, dense regions will be slow",
+                    self.min_distance
+                );
+            }
+            if !(self.min_distance_stiffness > 0.0 && self.min_distance_stiffness <= 0.5) {
+                warn!(
+                    "min_distance_stiffness = {} should be in (0, 0.5], larger values overshoot and jitter",
+                    self.min_distance_stiffness
+                );
+            }
+        }
+
+        if self.fixed_timestep.is_nan() || self.fixed_timestep <= 0.0 {
+            warn!(
+                "fixed_timestep = {} is not positive, fixed-step runs will not advance",
+                self.fixed_timestep
+            );
+        }
+
+        if !(1..=3).contains(&self.max_frame_latency) {
+            warn!(
+                "max_frame_latency = {} is outside 1..=3, using {}",
+                self.max_frame_latency,
+                self.frame_latency()
+            );
+        }
+
+        if self.canvas_mode && (self.canvas_deposit.is_nan() || self.canvas_deposit <= 0.0) {
+            warn!(
+                "canvas_deposit = {} is not positive, nothing will be painted",
+                self.canvas_deposit
+            );
+        }
+
+        if self.trail_length > trails::MAX_TRAIL_LENGTH {
+            warn!(
+                "trail_length = {} exceeds the maximum of {}, using {}",
+                self.trail_length,
+                trails::MAX_TRAIL_LENGTH,
+                trails::MAX_TRAIL_LENGTH
+            );
+        }
+
+        if !matches!(self.initial_acceleration, InitialAcceleration::Zero)
+            && !self.accumulate_acceleration
+        {
+            warn!("initial_acceleration only has an effect with accumulate_acceleration");
+        }
+
+        if self.band_cohesion > 0.0 && self.color_bands < 2 {
+            warn!(
+                "band_cohesion = {} has no effect with color_bands = {}, it needs at least 2 bands",
+                self.band_cohesion, self.color_bands
+            );
+        }
+        if self.band_cohesion > 0.0
+            && (self.band_cohesion_radius.is_nan() || self.band_cohesion_radius <= 0.0)
+        {
+            warn!(
+                "band_cohesion_radius = {} is not positive, band cohesion disabled",
+                self.band_cohesion_radius
+            );
+        }
+
+        if self.particle_segments > MAX_PARTICLE_SEGMENTS {
+            warn!(
+                "particle_segments = {} is above {}, using {}",
+                self.particle_segments, MAX_PARTICLE_SEGMENTS, MAX_PARTICLE_SEGMENTS
+            );
+        }
+        if !self.instanced_rendering
+            && (self.num_particles as u64 * self.vertices_per_particle() as u64) > u32::MAX as u64
+        {
+            warn!(
+                "{} particles of {} vertices are more vertices than one draw can hold, enable instanced_rendering",
+                self.num_particles,
+                self.vertices_per_particle()
+            );
+        }
+        if self.substeps() != self.substeps {
+            warn!(
+                "substeps = {} is outside 1..={}, using {}",
+                self.substeps,
+                MAX_SUBSTEPS,
+                self.substeps()
+            );
+        }
+        if !self.auto_rotate_speed.is_finite() {
+            warn!(
+                "auto_rotate_speed = {} is not a number, the view will not turn",
+                self.auto_rotate_speed
+            );
+        }
+        if self.time_scale() != self.time_scale {
+            warn!(
+                "time_scale = {} is outside {}..={}, using {}",
+                self.time_scale,
+                MIN_TIME_SCALE,
+                MAX_TIME_SCALE,
+                self.time_scale()
+            );
+        }
+
+        if self.clamped_intensity() != self.intensity {
+            warn!(
+                "intensity = {} is outside {}..={}, using {}",
+                self.intensity,
+                MIN_INTENSITY,
+                MAX_INTENSITY,
+                self.clamped_intensity()
+            );
+        }
+
+        if self.exposure.is_nan() || self.exposure <= 0.0 {
+            warn!(
+                "exposure = {} is not positive, the HDR output will be black",
+                self.exposure
+            );
+        }
+
+        if self.keep_on_screen && !(0.0..=1.0).contains(&self.keep_on_screen_margin) {
+            warn!(
+                "keep_on_screen_margin = {} is outside 0..=1",
+                self.keep_on_screen_margin
+            );
+        }
+
+        if self.smooth_render && (self.splat_radius.is_nan() || self.splat_radius <= 0.0) {
+            warn!(
+                "splat_radius = {} is not positive, smooth_render draws nothing",
+                self.splat_radius
+            );
+        }
+        if !(0.0..=1.0).contains(&self.pulse_amount) {
+            warn!(
+                "pulse_amount = {} is outside 0..=1, using {}",
+                self.pulse_amount,
+                self.clamped_pulse_amount()
+            );
+        }
+        if self.supersample() != self.supersample {
+            warn!(
+                "supersample = {} is outside 1..={}, using {}",
+                self.supersample,
+                MAX_SUPERSAMPLE,
+                self.supersample()
+            );
+        }
+        if self.depth_sort && (self.hdr || self.smooth_render) {
+            warn!(
+                "depth_sort has no effect together with hdr or smooth_render, additive blending ignores order"
+            );
+        }
+
+        if self.idle.enabled && (self.idle.fps.is_nan() || self.idle.fps <= 0.0) {
+            warn!(
+                "idle.fps = {} is not positive, idling disabled",
+                self.idle.fps
+            );
+        }
+
+        let quality = &self.dynamic_quality;
+        if quality.enabled && !(0.0 < quality.min_scale && quality.min_scale <= quality.max_scale) {
+            warn!(
+                "dynamic_quality scales should satisfy 0 < min_scale ({}) <= max_scale ({})",
+                quality.min_scale, quality.max_scale
+            );
+        }
+
+        if self.commands.roam.radius.is_nan() || self.commands.roam.radius < 0.0 {
+            warn!(
+                "commands.roam.radius = {} is negative, Roam will not attract particles",
+                self.commands.roam.radius
+            );
+        }
+
+        if self.commands.follow.radius.is_nan() || self.commands.follow.radius <= 0.0 {
+            warn!(
+                "commands.follow.radius = {} is not positive, Follow will not push particles",
+                self.commands.follow.radius
+            );
+        }
+
+        if self.commands.grab.radius.is_nan() || self.commands.grab.radius <= 0.0 {
+            warn!(
+                "commands.grab.radius = {} is not positive, Grab will not pick up particles",
+                self.commands.grab.radius
+            );
+        }
+
+        let emit = &self.commands.emit;
+        if emit.lifetime.is_nan() || emit.lifetime <= 0.0 {
+            warn!(
+                "commands.emit.lifetime = {} is not positive, Emit respawns every particle every step",
+                emit.lifetime
+            );
+        }
+        if emit.max_distance.is_nan() || emit.max_distance <= 0.0 {
+            warn!(
+                "commands.emit.max_distance = {} is not positive, Emit respawns every particle every step",
+                emit.max_distance
+            );
+        }
+
+        if self.commands.spiral.stiffness.is_nan() || self.commands.spiral.stiffness <= 0.0 {
+            warn!(
+                "commands.spiral.stiffness = {} is not positive, Spiral will not move particles",
+                self.commands.spiral.stiffness
+            );
+        }
+
+        for name in self.command_colors.keys() {
+            if Command::from_name(name).is_none() {
+                warn!(
+                    "command_colors has a color for {:?}, which is no command",
+                    name
+                );
+            }
+        }
+        if let Some(interval) = self.auto_cycle {
+            if interval.is_nan() || interval <= 0.0 {
+                warn!(
+                    "auto_cycle = {} is not positive, the command switches every step",
+                    interval
+                );
+            }
+            for name in &self.auto_cycle_commands {
+                if Command::from_name(name).is_none() {
+                    warn!("auto_cycle_commands lists {:?}, which is no command", name);
+                }
+            }
+        }
+
+        let net = &self.net;
+        if net.particle_count() > self.num_particles {
+            warn!(
+                "net of {}x{} particles is larger than num_particles = {}, only the first rows are simulated",
+                net.columns, net.rows, self.num_particles
+            );
+        }
+        if net.particle_count() > 0 && (net.spacing.is_nan() || net.spacing <= 0.0) {
+            warn!(
+                "net.spacing = {} is not positive, the springs pull every particle onto its neighbors",
+                net.spacing
+            );
+        }
+        // explicit integration of a spring oscillates out of control once stiffness * dt^2
+        // reaches 4, and a particle of the net hangs on up to 4 springs along each axis
+        let step = self.fixed_timestep / self.substeps() as f32;
+        if net.particle_count() > 0 && net.stiffness * step * step >= 1.0 {
+            warn!(
+                "net.stiffness = {} is too stiff for steps of {:.4} s, the net will blow up; raise substeps",
+                net.stiffness, step
+            );
+        }
+
+        let tint = &self.tint;
+        if tint.enabled && (tint.radius.is_nan() || tint.radius <= 0.0) {
+            warn!(
+                "tint.radius = {} is not positive, no particle will be tinted",
+                tint.radius
+            );
+        }
+
+        let life = &self.commands.life;
+        if life.radius.is_nan() || life.radius <= 0.0 {
+            warn!(
+                "commands.life.radius = {} is not positive, Life will not move particles",
+                life.radius
+            );
+        }
+        if life.min_neighbors > life.max_neighbors {
+            warn!(
+                "commands.life.min_neighbors = {} is above max_neighbors = {}, particles will be pulled and pushed at once",
+                life.min_neighbors, life.max_neighbors
+            );
+        }
+        // at most MAX_NEIGHBORS_PER_CELL particles of each of the 9 cells are counted
+        let countable = 9 * grid::MAX_NEIGHBORS_PER_CELL;
+        if life.max_neighbors >= countable {
+            warn!(
+                "commands.life.max_neighbors = {} is not below the {} neighbors that are counted at most, crowds will never disperse",
+                life.max_neighbors, countable
+            );
+        }
+
+        let brownian = &self.commands.brownian;
+        if brownian.strength.is_nan() || brownian.strength < 0.0 {
+            warn!(
+                "commands.brownian.strength = {} is negative, Brownian will only drift",
+                brownian.strength
+            );
+        }
+
+        let field_flow = &self.commands.field_flow;
+        if field_flow.steering.is_nan() || field_flow.steering < 0.0 {
+            warn!(
+                "commands.field_flow.steering = {} is negative, FieldFlow will push particles away from the field velocity",
+                field_flow.steering
+            );
+        }
+
+        let chain = &self.commands.chain;
+        if chain.stiffness.is_nan() || chain.stiffness <= 0.0 {
+            warn!(
+                "commands.chain.stiffness = {} is not positive, Chain will not move particles",
+                chain.stiffness
+            );
+        }
+        if chain.link_length.is_nan() || chain.link_length < 0.0 {
+            warn!(
+                "commands.chain.link_length = {} is negative",
+                chain.link_length
+            );
+        }
+
+        if self.commands.collapse.stiffness.is_nan() || self.commands.collapse.stiffness <= 0.0 {
+            warn!(
+                "commands.collapse.stiffness = {} is not positive, Collapse will not move particles",
+                self.commands.collapse.stiffness
+            );
+        }
+    }
+}
+
+/// A regular grid of particles joined by springs, see net.rs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetConfig {
+    /// Particles per row, the net is disabled while this or `rows` is 0.
+    pub columns: u32,
+    pub rows: u32,
+    /// Distance between neighboring particles of the net, the rest length of their springs.
+    pub spacing: f32,
+    /// Acceleration per unit a spring is stretched or compressed.
+    pub stiffness: f32,
+    /// Also join diagonal neighbors, which keeps the cells from shearing flat.
+    pub shear: bool,
+    /// Acceleration against the velocity per unit of speed, settles the swinging.
+    pub damping: f32,
+    /// Constant acceleration on the particles of the net.
+    pub gravity: [f32; 2],
+    /// Pin the top row in place whenever the particles are seeded, so the net hangs from it.
+    pub pin_top_row: bool,
+}
+
+impl NetConfig {
+    /// Particles joined into the net, the first ones of the buffer.
+    pub fn particle_count(&self) -> u32 {
+        self.columns.saturating_mul(self.rows)
+    }
+}
+
+impl Default for NetConfig {
+    fn default() -> Self {
+        Self {
+            columns: 0,
+            rows: 0,
+            spacing: 0.05,
+            stiffness: 1000.0,
+            shear: true,
+            damping: 1.0,
+            gravity: [0.0, -0.5],
+            pin_top_row: true,
+        }
+    }
+}
+
+/// A color per particle written by the compute shader, see tint.rs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TintConfig {
+    /// Keep a tint per particle, 16 bytes of GPU memory per particle while on.
+    pub enabled: bool,
+    /// Particles that come closer than this to the cursor take on `color`.
+    pub radius: f32,
+    /// RGBA tint, alpha is how strongly it replaces the particle's color.
+    pub color: [f32; 4],
+    /// Seconds over which a tint fades to about a third of its strength, 0 for only while
+    /// the cursor touches the particle.
+    pub fade_time: f32,
+}
+
+impl Default for TintConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.05,
+            color: [1.0, 0.6, 0.2, 1.0],
+            fade_time: 2.0,
+        }
+    }
+}
+
+/// Settings of the idle power saving mode.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    pub enabled: bool,
+    /// The scene counts as static while no particle is faster than this.
+    pub speed_threshold: f32,
+    /// Seconds without input before idling starts.
+    pub input_timeout: f32,
+    /// Redraw rate while idle.
+    pub fps: f32,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            speed_threshold: 0.001,
+            input_timeout: 5.0,
+            fps: 5.0,
+        }
+    }
+}
+
+/// Acceleration particles are seeded with. Without `accumulate_acceleration` the
+/// acceleration is rebuilt from the forces before the first step, so it has no effect.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum InitialAcceleration {
+    #[default]
+    Zero,
+    /// The same acceleration for every particle.
+    Constant([f32; 2]),
+    /// Away from the center of the screen with this magnitude, negative values point inwards.
+    Radial(f32),
+    /// Uniformly random on each axis, up to this magnitude.
+    Random(f32),
+}
+
+impl InitialAcceleration {
+    /// Acceleration of a particle spawned at `position`.
+    pub fn sample(self, position: [f32; 2], rng: &mut impl Rng) -> [f32; 2] {
+        match self {
+            InitialAcceleration::Zero => [0.0, 0.0],
+            InitialAcceleration::Constant(acceleration) => acceleration,
+            InitialAcceleration::Radial(magnitude) => {
+                let length = position[0].hypot(position[1]);
+                if length == 0.0 {
+                    // no outward direction at the center
+                    return [0.0, 0.0];
+                }
+                [
+                    position[0] / length * magnitude,
+                    position[1] / length * magnitude,
+                ]
+            }
+            InitialAcceleration::Random(magnitude) => {
+                let magnitude = magnitude.abs();
+                if magnitude == 0.0 {
+                    // gen_range panics on an empty range
+                    return [0.0, 0.0];
+                }
+                [
+                    rng.gen_range(-magnitude..magnitude),
+                    rng.gen_range(-magnitude..magnitude),
+                ]
+            }
+        }
+    }
+}
+
+/// Tonemapping operator of the HDR output, the discriminant is what tonemap.wgsl sees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tonemap {
+    /// Smooth roll-off that never quite reaches white.
+    #[default]
+    Reinhard = 0,
+    /// Filmic curve with more contrast, reaching white at a finite brightness.
+    Aces = 1,
+    /// Clips everything brighter than white.
+    LinearClamp = 2,
+}
+
+/// Background behind the particles, colors are sRGB encoded RGBA.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Background {
+    Solid([f32; 4]),
+    /// Top color, bottom color.
+    VerticalGradient([f32; 4], [f32; 4]),
+    /// Center color, corner color.
+    Radial([f32; 4], [f32; 4]),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid([0.1, 0.1, 0.1, 1.0])
+    }
+}
+
+/// Converts an sRGB encoded channel to linear light.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// An sRGB color from the config in the space the shaders should output for a target.
+///
+/// sRGB targets encode what is written to them, so the color is linearized first;
+/// other targets store the values as they are and the display reads them as sRGB.
+/// Alpha is never encoded.
+pub fn target_color([r, g, b, a]: [f32; 4], srgb_target: bool) -> [f32; 4] {
+    if srgb_target {
+        [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a]
+    } else {
+        [r, g, b, a]
+    }
+}
+
+impl Background {
+    /// The same background with its colors converted by [`target_color`].
+    pub fn for_target(&self, srgb_target: bool) -> Self {
+        let convert = |color| target_color(color, srgb_target);
+        match *self {
+            Background::Solid(color) => Background::Solid(convert(color)),
+            Background::VerticalGradient(top, bottom) => {
+                Background::VerticalGradient(convert(top), convert(bottom))
+            }
+            Background::Radial(center, corner) => {
+                Background::Radial(convert(center), convert(corner))
+            }
+        }
+    }
+
+    /// Color the particle target is cleared to, gradients are drawn over it.
+    pub fn clear_color(&self) -> [f32; 4] {
+        match self {
+            Background::Solid(color)
+            | Background::VerticalGradient(color, _)
+            | Background::Radial(color, _) => *color,
+        }
+    }
+}
+
+/// Per-command tunables, grouped under `commands` in the config file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandsConfig {
+    pub roam: RoamParams,
+    pub shuffle: ShuffleParams,
+    pub collapse: CollapseParams,
+    pub dust: DustParams,
+    pub follow: FollowParams,
+    pub chain: ChainParams,
+    pub grab: GrabParams,
+    pub emit: EmitParams,
+    pub spiral: SpiralParams,
+    pub life: LifeParams,
+    pub brownian: BrownianParams,
+    pub field_flow: FieldFlowParams,
+}
+
+impl CommandsConfig {
+    /// Packs the parameters of `command` in the order compute.wgsl reads them from
+    /// `command.params`.
+    pub fn params(&self, command: Command) -> [f32; 4] {
+        match command {
+            Command::Roam => [
+                self.roam.strength,
+                self.roam.radius,
+                self.roam.falloff as u32 as f32,
+                0.0,
+            ],
+            Command::Shuffle => [self.shuffle.strength, self.shuffle.amount, 0.0, 0.0],
+            Command::Collapse => [self.collapse.stiffness, 0.0, 0.0, 0.0],
+            Command::Dust => [
+                self.dust.strength,
+                self.dust.noise_strength,
+                self.dust.noise_scale,
+                self.dust.noise_speed,
+            ],
+            Command::Follow => [self.follow.strength, self.follow.radius, 0.0, 0.0],
+            Command::Chain => [self.chain.stiffness, self.chain.link_length, 0.0, 0.0],
+            Command::Grab => [self.grab.radius, 0.0, 0.0, 0.0],
+            Command::Emit => [
+                self.emit.speed,
+                self.emit.spread,
+                self.emit.lifetime,
+                self.emit.max_distance,
+            ],
+            Command::Spiral => [self.spiral.stiffness, self.spiral.scale, 0.0, 0.0],
+            Command::Life => [
+                self.life.radius,
+                self.life.strength,
+                self.life.min_neighbors as f32,
+                self.life.max_neighbors as f32,
+            ],
+            Command::Brownian => [
+                self.brownian.strength,
+                self.brownian.drift[0],
+                self.brownian.drift[1],
+                0.0,
+            ],
+            Command::FieldFlow => [self.field_flow.strength, self.field_flow.steering, 0.0, 0.0],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoamParams {
+    /// Multiplier on the cursor attraction.
+    pub strength: f32,
+    /// Distance from an attractor beyond which particles are no longer pulled, in clip space
+    /// units. 0 keeps the attraction unlimited.
+    pub radius: f32,
+    /// How the attraction weakens with the distance to an attractor.
+    pub falloff: Falloff,
+}
+
+impl Default for RoamParams {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            radius: 0.0,
+            falloff: Falloff::default(),
+        }
+    }
+}
+
+/// Distance weighting of the Roam attraction, the discriminant is what field.wgsl sees.
+/// All of them pull equally hard at a distance of 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Falloff {
+    /// The same pull at every distance.
+    Constant = 0,
+    /// Falls off with the distance, a wide and even pull.
+    Linear = 1,
+    /// Falls off with the square of the distance, a sharp pull close to the cursor.
+    #[default]
+    InverseSquare = 2,
+    /// Fades out as a gaussian, a soft pull localized around the cursor.
+    Gaussian = 3,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShuffleParams {
+    /// Multiplier on the cursor attraction, which keeps acting while shuffling.
+    pub strength: f32,
+    /// Largest distance a particle is offset by each step.
+    pub amount: f32,
+}
+
+impl Default for ShuffleParams {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            amount: 0.01,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollapseParams {
+    /// Spring stiffness, the damping is derived from it so the motion is critically damped.
+    pub stiffness: f32,
+}
+
+impl Default for CollapseParams {
+    fn default() -> Self {
+        Self { stiffness: 40.0 }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DustParams {
+    /// Multiplier on the cursor attraction, kept weak so the noise dominates up close.
+    pub strength: f32,
+    /// Multiplier on the curl noise acceleration.
+    pub noise_strength: f32,
+    /// Spatial frequency of the noise, higher values give smaller swirls.
+    pub noise_scale: f32,
+    /// How far the noise field evolves every simulated second.
+    pub noise_speed: f32,
+}
+
+impl Default for DustParams {
+    fn default() -> Self {
+        Self {
+            strength: 0.2,
+            noise_strength: 1.0,
+            noise_scale: 4.0,
+            noise_speed: 0.6,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FollowParams {
+    /// Acceleration per unit of cursor velocity right at the cursor.
+    pub strength: f32,
+    /// Distance from the cursor at which the push has dropped to about a third.
+    pub radius: f32,
+}
+
+impl Default for FollowParams {
+    fn default() -> Self {
+        Self {
+            strength: 2.0,
+            radius: 0.2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainParams {
+    /// Stiffness of the critically damped spring pulling every particle after its leader.
+    pub stiffness: f32,
+    /// Distance a particle keeps from its predecessor, 0 lets the chain pile up on the cursor.
+    pub link_length: f32,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self {
+            stiffness: 200.0,
+            link_length: 0.002,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrabParams {
+    /// Distance from the cursor within which particles are carried while the left button
+    /// is held.
+    pub radius: f32,
+}
+
+impl Default for GrabParams {
+    fn default() -> Self {
+        Self { radius: 0.1 }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmitParams {
+    /// Average speed particles leave the cursor with.
+    pub speed: f32,
+    /// Half angle of the spray around straight up, in radians. Pi sprays in every direction.
+    pub spread: f32,
+    /// Mean time in seconds before a particle respawns at the cursor.
+    pub lifetime: f32,
+    /// Distance from the cursor beyond which particles respawn right away.
+    pub max_distance: f32,
+}
+
+impl Default for EmitParams {
+    fn default() -> Self {
+        Self {
+            speed: 0.5,
+            spread: std::f32::consts::PI,
+            lifetime: 2.0,
+            max_distance: 1.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpiralParams {
+    /// Stiffness of the critically damped spring pulling every particle to its slot.
+    pub stiffness: f32,
+    /// Radius of the spiral, the distance of the outermost slot from the cursor.
+    pub scale: f32,
+}
+
+impl Default for SpiralParams {
+    fn default() -> Self {
+        Self {
+            stiffness: 20.0,
+            scale: 0.8,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifeParams {
+    /// Distance within which other particles count as neighbors. Also sizes the spatial
+    /// grid cells while Life is active.
+    pub radius: f32,
+    /// Acceleration towards or away from the neighbors' center of mass at full strength.
+    pub strength: f32,
+    /// Particles with fewer neighbors are pulled towards them.
+    pub min_neighbors: u32,
+    /// Particles with more neighbors are pushed away from them.
+    pub max_neighbors: u32,
+}
+
+impl Default for LifeParams {
+    fn default() -> Self {
+        Self {
+            radius: 0.01,
+            strength: 2.0,
+            min_neighbors: 6,
+            max_neighbors: 20,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrownianParams {
+    /// Standard deviation of the velocity kicks over one second. A step of `dt` seconds
+    /// kicks with `strength * sqrt(dt)`.
+    pub strength: f32,
+    /// Constant acceleration added to the random walk.
+    pub drift: [f32; 2],
+}
+
+impl Default for BrownianParams {
+    fn default() -> Self {
+        Self {
+            strength: 0.05,
+            drift: [0.0, 0.0],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FieldFlowParams {
+    /// Multiplier on the vectors of `field_texture`.
+    pub strength: f32,
+    /// 0 treats the vectors as accelerations. Above 0 they are velocities, and every
+    /// particle's velocity approaches the one under it at this rate per second.
+    pub steering: f32,
+}
+
+impl Default for FieldFlowParams {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            steering: 0.0,
+        }
+    }
+}
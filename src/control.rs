@@ -0,0 +1,114 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc,
+    thread,
+};
+
+use log::{info, warn};
+use serde::Deserialize;
+
+/// A request read from the control socket. Every request is a single line of JSON naming
+/// the request in `cmd` and its argument, if any, in `value`:
+///
+/// - `{"cmd":"set_command","value":"dust"}`: switch the active command, named like the
+///   keyboard modes (`roam`, `shuffle`, `collapse`, `dust`, `follow`, `chain`, `grab`, `emit`,
+///   `spiral`, `life`, `brownian`, `field_flow`)
+/// - `{"cmd":"set_particle_count","value":50000}`: simulate this many particles, seeding added ones
+/// - `{"cmd":"set_paused","value":true}`: pause or resume the simulation
+/// - `{"cmd":"load_preset","value":"calm"}`: load `presets/calm.json`
+/// - `{"cmd":"quit"}`: close the window
+///
+/// Every request is answered with one line, `{"ok":true}` or
+/// `{"ok":false,"error":"..."}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", content = "value", rename_all = "snake_case")]
+pub enum ControlRequest {
+    SetCommand(String),
+    SetParticleCount(u32),
+    SetPaused(bool),
+    LoadPreset(String),
+    Quit,
+}
+
+/// A request waiting to be applied by the event loop.
+pub struct PendingRequest {
+    pub request: ControlRequest,
+    reply: mpsc::Sender<Result<(), String>>,
+}
+
+impl PendingRequest {
+    /// Sends the outcome back to the client that made the request.
+    pub fn respond(self, result: Result<(), String>) {
+        // the client may have disconnected in the meantime, which is fine
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Listens for control connections on a background thread and hands their requests to the
+/// event loop, which applies them between frames.
+pub struct ControlServer {
+    requests: mpsc::Receiver<PendingRequest>,
+}
+
+impl ControlServer {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        info!("control socket listening on {}", listener.local_addr()?);
+
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        thread::spawn(move || {
+                            if let Err(err) = serve(stream, sender) {
+                                warn!("control connection failed: {}", err);
+                            }
+                        });
+                    }
+                    Err(err) => warn!("failed to accept control connection: {}", err),
+                }
+            }
+        });
+
+        Ok(Self { requests })
+    }
+
+    /// Requests received since the last call, without blocking.
+    pub fn pending(&self) -> impl Iterator<Item = PendingRequest> + '_ {
+        self.requests.try_iter()
+    }
+}
+
+/// Answers the requests of one client until it disconnects or the event loop is gone.
+fn serve(stream: TcpStream, sender: mpsc::Sender<PendingRequest>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = match serde_json::from_str(&line) {
+            Ok(request) => {
+                let (reply, outcome) = mpsc::channel();
+                if sender.send(PendingRequest { request, reply }).is_err() {
+                    return Ok(());
+                }
+                outcome
+                    .recv()
+                    .unwrap_or_else(|_| Err("the request was dropped".to_string()))
+            }
+            Err(err) => Err(format!("invalid request: {}", err)),
+        };
+
+        let response = match result {
+            Ok(()) => serde_json::json!({ "ok": true }),
+            Err(error) => serde_json::json!({ "ok": false, "error": error }),
+        };
+        writeln!(writer, "{}", response)?;
+    }
+    Ok(())
+}
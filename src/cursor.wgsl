@@ -0,0 +1,45 @@
+// Ring marking the cursor, drawn over the finished frame when the OS cursor is hidden
+
+struct Cursor {
+    // cursor position in pixels, y pointing down
+    position: vec2<f32>,
+    radius: f32,
+    thickness: f32,
+};
+
+@group(0) @binding(0) var<uniform> cursor: Cursor;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// A single triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+// Coverage of a line `width` pixels wide at `offset` pixels from its center, with a one
+// pixel soft edge against aliasing
+fn line(offset: f32, width: f32) -> f32 {
+    return 1.0 - smoothstep(0.5 * width - 0.5, 0.5 * width + 0.5, abs(offset));
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let from_cursor = length(input.position.xy - cursor.position);
+
+    // white ring and center dot, on a dark outline so they stay visible on bright particles
+    let ring = line(from_cursor - cursor.radius, cursor.thickness);
+    let center = line(from_cursor, 2.0 * cursor.thickness);
+    let shape = max(ring, center);
+    let outline = max(
+        line(from_cursor - cursor.radius, cursor.thickness + 2.0),
+        line(from_cursor, 2.0 * cursor.thickness + 2.0),
+    );
+    return vec4<f32>(vec3<f32>(shape), max(shape, 0.6 * outline));
+}
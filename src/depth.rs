@@ -0,0 +1,45 @@
+/// Format of the depth buffer used while particles are depth sorted.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Depth buffer of the particle pass, so the particle with the nearest depth key wins
+/// wherever quads overlap instead of whichever comes last in the buffer.
+pub struct DepthTarget {
+    /// Keeps the texture alive.
+    pub view: wgpu::TextureView,
+    /// Pixels of the texture, those of the target the particles are drawn into.
+    pub size: [u32; 2],
+}
+
+impl DepthTarget {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Target"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        Self {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            size: [width, height],
+        }
+    }
+
+    /// Depth state of the pipelines drawing particles into this target.
+    pub fn depth_stencil_state() -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::state::{MAX_WORKGROUPS_PER_DIMENSION, WORKGROUP_SIZE};
+
+/// Side of the square workgroups of 2D kernels, shared with compute.wgsl.
+pub const TILE_SIZE: u32 = 16;
+
+/// Invocation layout of a compute dispatch. Each variant has to match the
+/// `@workgroup_size` of the kernels dispatched with it.
+#[derive(Clone, Copy, Debug)]
+pub enum DispatchConfig {
+    /// `count` invocations in 1D workgroups of [`WORKGROUP_SIZE`]. Counts needing more than
+    /// [`MAX_WORKGROUPS_PER_DIMENSION`] workgroups are spread over a 2D grid, which the
+    /// shader flattens back using the dispatch width (see `particle_index`), and which
+    /// covers at most one partial row more than needed.
+    Linear { count: u32 },
+    /// A `width` x `height` grid of invocations in 2D workgroups of `workgroup_size`,
+    /// addressed by `global_invocation_id.xy`.
+    Grid2d {
+        width: u32,
+        height: u32,
+        workgroup_size: [u32; 2],
+    },
+    /// A single workgroup, for kernels that loop over their data themselves.
+    Single,
+}
+
+impl DispatchConfig {
+    /// Workgroup counts to dispatch along x, y and z.
+    pub fn workgroups(self) -> [u32; 3] {
+        match self {
+            DispatchConfig::Linear { count } => {
+                let workgroups = count.div_ceil(WORKGROUP_SIZE);
+                let workgroups_x = workgroups.clamp(1, MAX_WORKGROUPS_PER_DIMENSION);
+                let workgroups_y = workgroups.div_ceil(workgroups_x);
+                [workgroups_x, workgroups_y, 1]
+            }
+            DispatchConfig::Grid2d {
+                width,
+                height,
+                workgroup_size: [size_x, size_y],
+            } => [
+                width.div_ceil(size_x).max(1),
+                height.div_ceil(size_y).max(1),
+                1,
+            ],
+            DispatchConfig::Single => [1, 1, 1],
+        }
+    }
+}
+
+/// Records a dispatch of `pipeline` laid out as `config`.
+pub fn dispatch<'a>(
+    compute_pass: &mut wgpu::ComputePass<'a>,
+    pipeline: &'a wgpu::ComputePipeline,
+    config: DispatchConfig,
+) {
+    let [workgroups_x, workgroups_y, workgroups_z] = config.workgroups();
+    compute_pass.set_pipeline(pipeline);
+    compute_pass.dispatch_workgroups(workgroups_x, workgroups_y, workgroups_z);
+}
@@ -0,0 +1,105 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bytemuck::{Pod, Zeroable};
+use log::{info, warn};
+
+use crate::{state::State, types::Particle};
+
+/// Written at the start of every dump file.
+const MAGIC: [u8; 4] = *b"HNPD";
+const VERSION: u32 = 1;
+
+/// Estimated write rate above which starting a dump warns, assuming 60 frames per second.
+const WARN_BYTES_PER_SECOND: u64 = 100 << 20;
+const ASSUMED_FPS: u64 = 60;
+
+// Start of every dump file, as large as one particle so the whole file can be viewed as
+// an array of particles with the header in the first row
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+    particle_count: u32,
+    particle_size: u32,
+    frame: u64,
+    elapsed_time: f64,
+}
+
+/// Writes the simulated particles to `frame_NNNNN.bin` files in a directory every
+/// `interval` simulation frames, for analysis outside of the simulator.
+///
+/// Every file is a 32 byte header followed by the particles as `Particle` is declared,
+/// little-endian. Each dump reads the particle buffer back, which blocks until the GPU is
+/// done, so dumping every frame slows the simulation down noticeably.
+pub struct ParticleDump {
+    directory: PathBuf,
+    interval: u64,
+    /// Frame of the last dump, frames that did not step are not dumped again.
+    last_frame: Option<u64>,
+}
+
+impl ParticleDump {
+    /// Creates `directory` if needed, warning if dumping `particle_count` particles every
+    /// `interval` frames writes a lot of data.
+    pub fn create(directory: &Path, interval: u32, particle_count: u32) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        let interval = interval.max(1) as u64;
+        let bytes = file_size(particle_count);
+        info!(
+            "dumping particles to {} every {} frames, {:.1} MB per dump",
+            directory.display(),
+            interval,
+            bytes as f64 / 1e6
+        );
+        let rate = bytes * ASSUMED_FPS / interval;
+        if rate > WARN_BYTES_PER_SECOND {
+            warn!(
+                "the particle dump writes about {:.0} MB per second at {} fps, raise dump_interval to write less",
+                rate as f64 / 1e6,
+                ASSUMED_FPS
+            );
+        }
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            interval,
+            last_frame: None,
+        })
+    }
+
+    /// Writes the particles of `state` if its frame is due and was not dumped yet.
+    pub fn frame_rendered(&mut self, state: &mut State) -> io::Result<()> {
+        let frame = state.frame;
+        if self.last_frame == Some(frame) || !frame.is_multiple_of(self.interval) {
+            return Ok(());
+        }
+        self.last_frame = Some(frame);
+
+        let elapsed_time = state.elapsed_time;
+        let particles = state.copy_particles_to_staging();
+        let header = Header {
+            magic: MAGIC,
+            version: VERSION,
+            particle_count: particles.len() as u32,
+            particle_size: std::mem::size_of::<Particle>() as u32,
+            frame,
+            elapsed_time,
+        };
+
+        let path = self.directory.join(format!("frame_{:05}.bin", frame));
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        writer.write_all(bytemuck::bytes_of(&header))?;
+        writer.write_all(bytemuck::cast_slice(particles))?;
+        writer.flush()
+    }
+}
+
+/// Bytes of a dump of `particle_count` particles.
+fn file_size(particle_count: u32) -> u64 {
+    (std::mem::size_of::<Header>() + particle_count as usize * std::mem::size_of::<Particle>())
+        as u64
+}
@@ -0,0 +1,175 @@
+// Force field of the active command, shared by the simulation in compute.wgsl and the
+// field view in field_view.wgsl. Inserted into both at their $RUST_FIELD markers.
+
+struct MousePosition {
+    position: vec2<f32>,
+    // fixed second attractor, only used while secondary_active is nonzero
+    secondary: vec2<f32>,
+    // x: weight of the cursor, y: weight of the secondary attractor
+    weights: vec2<f32>,
+    secondary_active: u32,
+    // nonzero while the left mouse button is held
+    left_pressed: u32,
+    // cursor velocity in clip space units per second
+    velocity: vec2<f32>,
+    // velocity smoothed over a few steps, what grabbed particles keep when released
+    fling_velocity: vec2<f32>,
+};
+
+struct Simulation {
+    // 0: acceleration is reset every step, 1: forces add onto last step's acceleration
+    accumulate_acceleration: u32,
+    // fraction of velocity lost every step
+    drag: f32,
+    // added to the squared distance of inverse distance forces, see attraction
+    softening: f32,
+    // nonzero to keep the rounding error of positions, see advance in compute.wgsl
+    high_precision: u32,
+    // nonzero to pull particles back that come closer than the margin to the screen edge
+    keep_on_screen: u32,
+    keep_on_screen_margin: f32,
+    keep_on_screen_stiffness: f32,
+    padding: u32,
+};
+
+struct Command {
+    command: u32,
+    padding1: u32,
+    padding2: u32,
+    padding3: u32,
+    // Roam:     x = attraction strength, y = influence radius (0 = unlimited), z = falloff
+    // Shuffle:  x = attraction strength, y = shuffle amount
+    // Collapse: x = spring stiffness
+    // Dust:     x = attraction strength, y = noise strength, z = noise scale, w = noise speed
+    // Follow:   x = push strength, y = falloff radius
+    // Chain:    x = spring stiffness, y = link length
+    // Grab:     x = grab radius
+    // Emit:     x = emission speed, y = spray half angle, z = mean lifetime, w = max distance
+    // Spiral:   x = spring stiffness, y = radius of the outermost slot
+    // Life:     x = neighbor radius, y = strength, z = min neighbors, w = max neighbors
+    // Brownian: x = diffusion strength, yz = drift acceleration
+    // FieldFlow: x = field strength, y = steering rate (0 = the field is an acceleration)
+    params: vec4<f32>,
+};
+
+// Command indices, generated from the Rust Command enum
+// $RUST_COMMANDS
+const COMMAND_ROAM: u32 = 0u;
+const COMMAND_SHUFFLE: u32 = 1u;
+const COMMAND_COLLAPSE: u32 = 2u;
+const COMMAND_DUST: u32 = 3u;
+const COMMAND_FOLLOW: u32 = 4u;
+const COMMAND_CHAIN: u32 = 5u;
+const COMMAND_GRAB: u32 = 6u;
+const COMMAND_EMIT: u32 = 7u;
+const COMMAND_SPIRAL: u32 = 8u;
+const COMMAND_LIFE: u32 = 9u;
+const COMMAND_BROWNIAN: u32 = 10u;
+const COMMAND_FIELD_FLOW: u32 = 11u;
+const COMMAND_COUNT: u32 = 12u;
+// $RUST_COMMANDSEND
+
+// Roam falloff curves, match the Rust Falloff enum
+const FALLOFF_CONSTANT: u32 = 0u;
+const FALLOFF_LINEAR: u32 = 1u;
+const FALLOFF_INVERSE_SQUARE: u32 = 2u;
+const FALLOFF_GAUSSIAN: u32 = 3u;
+
+// Acceleration towards an attractor, strength / (|0.1 * d|^2 + softening).
+//
+// Without softening the pull grows without bound as a particle approaches the attractor.
+// The softening term caps it at strength / softening right at the attractor and leaves it
+// close to the plain inverse square law once |0.1 * d|^2 is much larger than softening, so
+// larger values give a wider, gentler core.
+//
+// Roam can pick another curve in params.z, each matching the unsoftened inverse square law
+// at a distance of 1: the same pull everywhere, the softened inverse distance, or a gaussian of
+// width 0.5 around the attractor.
+fn attraction(cmd: Command, sim: Simulation, position: vec2<f32>, attractor: vec2<f32>) -> vec2<f32> {
+    let direction = attractor - position;
+    let dist_sq = dot(direction, direction);
+    if dist_sq == 0.0 {
+        // no direction to pull in, and normalize would return NaN
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    // Optimized acceleration calculation (combined operations)
+    let unit_size: f32 = 0.1;
+    let unit_sq = unit_size * unit_size;
+    // params.x is the attraction strength for every command using attractors
+    var mag_factor = cmd.params.x / (dist_sq * unit_sq + sim.softening);
+    if cmd.command == COMMAND_ROAM {
+        switch u32(cmd.params.z) {
+            case FALLOFF_CONSTANT: {
+                mag_factor = cmd.params.x / unit_sq;
+            }
+            case FALLOFF_LINEAR: {
+                mag_factor = cmd.params.x / (unit_size * sqrt(dist_sq * unit_sq + sim.softening));
+            }
+            case FALLOFF_GAUSSIAN: {
+                mag_factor = cmd.params.x / unit_sq * exp(4.0 * (1.0 - dist_sq));
+            }
+            default: {
+                // FALLOFF_INVERSE_SQUARE, computed above
+            }
+        }
+    }
+
+    // Directly compute normalized direction * magnitude factor
+    return direction * inverseSqrt(dist_sq) * mag_factor;
+}
+
+// Scales the attraction of an attractor at `distance` by the Roam influence radius, fading
+// out smoothly over the outer quarter of the radius
+fn roam_falloff(cmd: Command, distance: f32) -> f32 {
+    let radius = cmd.params.y;
+    if cmd.command != COMMAND_ROAM || radius == 0.0 {
+        return 1.0;
+    }
+    return 1.0 - smoothstep(0.75 * radius, radius, distance);
+}
+
+// Acceleration the active command applies to a particle resting at `position`
+fn field_acceleration(cmd: Command, mouse: MousePosition, sim: Simulation, position: vec2<f32>) -> vec2<f32> {
+    if cmd.command == COMMAND_COLLAPSE {
+        // spring towards the cursor, the damping part depends on velocity and is left out
+        return -cmd.params.x * (position - mouse.position);
+    }
+
+    if cmd.command == COMMAND_GRAB || cmd.command == COMMAND_EMIT {
+        // particles are moved or respawned directly, and coast otherwise
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    if cmd.command == COMMAND_LIFE {
+        // only the neighbors move particles, see life in compute.wgsl
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    if cmd.command == COMMAND_FIELD_FLOW {
+        // the texture is sampled in compute.wgsl, see field_flow
+        return vec2<f32>(0.0, 0.0);
+    }
+
+    if cmd.command == COMMAND_BROWNIAN {
+        // the same drift everywhere, the random kicks are added in compute.wgsl
+        return cmd.params.yz;
+    }
+
+    if cmd.command == COMMAND_FOLLOW {
+        // push along the cursor's motion, fading out as a gaussian around it
+        let offset = position - mouse.position;
+        let radius = cmd.params.y;
+        let falloff = exp(-dot(offset, offset) / (radius * radius));
+        return cmd.params.x * falloff * mouse.velocity;
+    }
+
+    // Each attractor pulls with its own weight
+    let primary_weight = mouse.weights.x * roam_falloff(cmd, distance(position, mouse.position));
+    var acceleration = primary_weight * attraction(cmd, sim, position, mouse.position);
+    if mouse.secondary_active != 0u {
+        let secondary_weight = mouse.weights.y * roam_falloff(cmd, distance(position, mouse.secondary));
+        acceleration += secondary_weight * attraction(cmd, sim, position, mouse.secondary);
+    }
+    return acceleration;
+}
@@ -0,0 +1,64 @@
+// Full-screen background pass coloring every pixel by the force field of the active command:
+// hue shows the direction of the acceleration, brightness its magnitude
+
+struct Resolution {
+    width: f32,
+    height: f32,
+    view_angle: f32,
+    _padding: u32,
+};
+
+// Turns a screen position back by the angle `view` of shader.wgsl turns the particles by,
+// so the overlay lines up with them
+fn unturn(position: vec2<f32>) -> vec2<f32> {
+    let aspect_ratio = resolution.width / resolution.height;
+    let square = vec2<f32>(position.x * aspect_ratio, position.y);
+    let c = cos(resolution.view_angle);
+    let s = sin(resolution.view_angle);
+    let turned = vec2<f32>(c * square.x + s * square.y, c * square.y - s * square.x);
+    return vec2<f32>(turned.x / aspect_ratio, turned.y);
+}
+
+// MousePosition, Simulation, Command, the command constants and field_acceleration,
+// from field.wgsl
+// $RUST_FIELD
+// $RUST_FIELDEND
+
+@group(0) @binding(0) var<uniform> mouse_position: MousePosition;
+@group(0) @binding(1) var<uniform> command: Command;
+@group(0) @binding(2) var<uniform> resolution: Resolution;
+@group(0) @binding(3) var<uniform> simulation: Simulation;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// A single triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+fn hue_to_rgb(hue: f32) -> vec3<f32> {
+    let h = fract(hue) * 6.0;
+    return clamp(vec3<f32>(abs(h - 3.0) - 1.0, 2.0 - abs(h - 2.0), 2.0 - abs(h - 4.0)), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    // pixel coordinates to the clip space the particles live in, y pointing up
+    let uv = input.position.xy / vec2<f32>(resolution.width, resolution.height);
+    let position = unturn(vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0));
+
+    let acceleration = field_acceleration(command, mouse_position, simulation, position);
+    let magnitude = length(acceleration);
+    let angle = atan2(acceleration.y, acceleration.x);
+
+    // kept dim so the particles drawn on top stay readable
+    let brightness = 0.4 * magnitude / (magnitude + 1.0);
+    return vec4<f32>(hue_to_rgb(angle / 6.2831853 + 0.5) * brightness, 1.0);
+}
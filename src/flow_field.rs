@@ -0,0 +1,145 @@
+use std::{fs, io, path::Path};
+
+/// A 2D vector per texel, loaded from `field_texture` for FieldFlow.
+pub struct FlowImage {
+    pub width: u32,
+    pub height: u32,
+    /// Rows from the bottom of the image to the top, as stored in the file.
+    pub texels: Vec<[f32; 2]>,
+}
+
+/// Reads a color PFM (portable float map) image, keeping the red and green channels as the
+/// x and y of every texel. PFM stores 32-bit floats without compression, can be written by
+/// most image editors and by a few lines of numpy, and lists the rows bottom to top, which
+/// is the direction y grows in clip space.
+pub fn load_pfm(path: &Path) -> io::Result<FlowImage> {
+    let bytes = fs::read(path)?;
+    parse_pfm(&bytes).map_err(|message| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), message),
+        )
+    })
+}
+
+/// Parses the contents of a PFM file as `load_pfm` does, explaining what is wrong with
+/// them if they are not a color PFM.
+pub fn parse_pfm(bytes: &[u8]) -> Result<FlowImage, String> {
+    let mut position = 0;
+    let mut header = [""; 4];
+    for field in &mut header {
+        *field = next_token(bytes, &mut position).ok_or_else(|| "truncated header".to_string())?;
+    }
+    let [kind, width, height, scale] = header;
+    match kind {
+        "PF" => {}
+        "Pf" => {
+            return Err("grayscale PFM has no second channel, save it in color".to_string());
+        }
+        _ => return Err(format!("not a PFM file, starts with {:?}", kind)),
+    }
+    let parse_size = |value: &str| {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|&size| size > 0)
+            .ok_or_else(|| format!("invalid image size {:?}", value))
+    };
+    let (width, height) = (parse_size(width)?, parse_size(height)?);
+    let scale: f32 = scale
+        .parse()
+        .map_err(|_| format!("invalid scale {:?}", scale))?;
+
+    // three channels of 4 bytes per texel, the sign of the scale gives the byte order
+    let data = &bytes[position..];
+    let texel_count = width as usize * height as usize;
+    if data.len() < texel_count * 12 {
+        return Err(format!(
+            "expected {} bytes of pixel data for {}x{}, found {}",
+            texel_count * 12,
+            width,
+            height,
+            data.len()
+        ));
+    }
+    let read = |offset: usize| {
+        let word = [
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ];
+        if scale < 0.0 {
+            f32::from_le_bytes(word)
+        } else {
+            f32::from_be_bytes(word)
+        }
+    };
+    let texels = (0..texel_count)
+        .map(|texel| [read(texel * 12), read(texel * 12 + 4)])
+        .collect();
+
+    Ok(FlowImage {
+        width,
+        height,
+        texels,
+    })
+}
+
+/// The next whitespace separated header field, leaving `position` after the single
+/// whitespace character that ends it.
+fn next_token<'b>(bytes: &'b [u8], position: &mut usize) -> Option<&'b str> {
+    while bytes.get(*position)?.is_ascii_whitespace() {
+        *position += 1;
+    }
+    let start = *position;
+    while !bytes.get(*position)?.is_ascii_whitespace() {
+        *position += 1;
+    }
+    let token = std::str::from_utf8(&bytes[start..*position]).ok()?;
+    *position += 1;
+    Some(token)
+}
+
+/// The flow field texture bound to the simulation, a single zero texel without
+/// `field_texture`. Uses `Rg32Float`, which cannot be filtered without an optional
+/// feature, so compute.wgsl interpolates between the texels itself.
+pub fn create_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: Option<&FlowImage>,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let empty = FlowImage {
+        width: 1,
+        height: 1,
+        texels: vec![[0.0; 2]],
+    };
+    let image = image.unwrap_or(&empty);
+    let size = wgpu::Extent3d {
+        width: image.width,
+        height: image.height,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Flow Field Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rg32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(&image.texels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(image.width * std::mem::size_of::<[f32; 2]>() as u32),
+            rows_per_image: None,
+        },
+        size,
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
@@ -0,0 +1,145 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use log::{error, info, warn};
+
+use crate::state::MAX_STORAGE_BUFFER_BINDING_SIZE;
+
+/// The adapter and device every window renders with. Shared between the states of all
+/// open windows, each of which only owns its surface, buffers and pipelines.
+pub struct Gpu {
+    /// Creates the surfaces of further windows.
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    /// Set by the device lost callback, see `is_lost`.
+    lost: Arc<AtomicBool>,
+    /// Set once the `Gpu` is dropped, so the device lost callback does not report the
+    /// device going away with it.
+    dropping: Arc<AtomicBool>,
+}
+
+impl Gpu {
+    pub fn create_instance() -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        })
+    }
+
+    /// Every adapter of `instance`, in the order `adapter` indexes them.
+    pub fn adapters(instance: &wgpu::Instance) -> Vec<wgpu::Adapter> {
+        instance.enumerate_adapters(wgpu::Backends::all())
+    }
+
+    /// Opens the device on adapter `index` of `adapters`, or on the adapter best suited to
+    /// present to `surface` without one or if that one cannot. `surface` has to come from
+    /// `instance`; without one any adapter will do, for headless use.
+    pub async fn new(
+        instance: wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+        index: Option<usize>,
+    ) -> Self {
+        let chosen = index.and_then(|index| {
+            let Some(adapter) = Self::adapters(&instance).into_iter().nth(index) else {
+                warn!(
+                    "there is no adapter {}, run with --list-adapters to see them",
+                    index
+                );
+                return None;
+            };
+            if surface.is_some_and(|surface| !adapter.is_surface_supported(surface)) {
+                warn!(
+                    "adapter {} ({}) cannot present to the window",
+                    index,
+                    describe(&adapter.get_info())
+                );
+                return None;
+            }
+            Some(adapter)
+        });
+        let adapter = match chosen {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    compatible_surface: surface,
+                    force_fallback_adapter: false,
+                })
+                .await
+                .unwrap(),
+        };
+        info!("using adapter {}", describe(&adapter.get_info()));
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    // the wireframe and point views are only offered where supported
+                    required_features: wgpu::Features::VERTEX_WRITABLE_STORAGE
+                        | (adapter.features()
+                            & (wgpu::Features::POLYGON_MODE_LINE
+                                | wgpu::Features::POLYGON_MODE_POINT)),
+                    required_limits: wgpu::Limits {
+                        // asking for more than the adapter offers fails, as on software
+                        // adapters
+                        max_storage_buffer_binding_size: MAX_STORAGE_BUFFER_BINDING_SIZE
+                            .min(adapter.limits().max_storage_buffer_binding_size),
+                        ..adapter.limits()
+                    },
+                    label: None,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        let lost = Arc::new(AtomicBool::new(false));
+        let dropping = Arc::new(AtomicBool::new(false));
+        let (lost_flag, dropping_flag) = (lost.clone(), dropping.clone());
+        device.set_device_lost_callback(move |reason, message| {
+            // some backends report dropping the device as an unknown loss
+            if dropping_flag.load(Ordering::Relaxed) {
+                return;
+            }
+            if matches!(
+                reason,
+                wgpu::DeviceLostReason::Unknown | wgpu::DeviceLostReason::Destroyed
+            ) {
+                error!("GPU device lost ({:?}): {}", reason, message);
+                lost_flag.store(true, Ordering::Relaxed);
+            }
+        });
+
+        Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            lost,
+            dropping,
+        }
+    }
+
+    /// Whether the device is gone, nothing rendered with it shows up anymore.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Gpu {
+    fn drop(&mut self) {
+        // runs before the fields, and with them the device, are dropped
+        self.dropping.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Name, type, backend and driver of an adapter, on one line.
+pub fn describe(info: &wgpu::AdapterInfo) -> String {
+    format!(
+        "\"{}\" ({:?}, {:?} backend, driver {} {})",
+        info.name, info.device_type, info.backend, info.driver, info.driver_info
+    )
+}
@@ -0,0 +1,144 @@
+use bytemuck::Zeroable;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    config::GameConfiguration,
+    types::{Command, GridUniform},
+};
+
+/// Upper bound on the grid resolution, so tiny neighbor distances cannot allocate an
+/// unbounded number of cells.
+pub const MAX_CELLS_PER_AXIS: u32 = 1024;
+
+/// Neighbors looked at per cell, matches `MAX_NEIGHBORS_PER_CELL` of compute.wgsl.
+pub const MAX_NEIGHBORS_PER_CELL: u32 = 32;
+
+/// Kernels of compute.wgsl that rebuild the grid, in dispatch order, with what each one
+/// runs over.
+pub const GRID_KERNELS: &[(&str, GridDispatch)] = &[
+    ("clear_grid", GridDispatch::Cells),
+    ("count_grid", GridDispatch::Particles),
+    ("scan_grid", GridDispatch::Single),
+    ("scatter_grid", GridDispatch::Particles),
+];
+
+/// How many invocations a grid kernel needs.
+#[derive(Clone, Copy, Debug)]
+pub enum GridDispatch {
+    /// One invocation per cell, in 2D tiles over the cell grid.
+    Cells,
+    /// One invocation per active particle.
+    Particles,
+    /// A single workgroup.
+    Single,
+}
+
+/// Uniform grid over the [-1, 1] square that sorts particle indices by cell every step,
+/// so neighbor queries only visit the 3x3 cells around a particle.
+///
+/// Cells are at least as large as the largest query distance. The grid is rebuilt from
+/// scratch each step with a counting sort: count the particles per cell, prefix sum the
+/// counts into start offsets, then scatter every particle into its cell's range.
+pub struct SpatialGrid {
+    pub uniform_buffer: wgpu::Buffer,
+    /// Particles per cell, also the fill cursor while scattering.
+    pub cell_counts: wgpu::Buffer,
+    /// Offset of each cell's range in the sorted arrays, plus the total at the end.
+    pub cell_starts: wgpu::Buffer,
+    /// Particle indices sorted by cell.
+    pub sorted_indices: wgpu::Buffer,
+    /// Positions matching `sorted_indices`, copied so neighbor reads never race with the
+    /// particle updates.
+    pub sorted_positions: wgpu::Buffer,
+    pub cells_per_axis: u32,
+    /// Particles the sorted arrays have room for.
+    pub capacity: u32,
+}
+
+impl SpatialGrid {
+    /// Whether any enabled feature, or the active command, queries neighbors.
+    pub fn is_needed(config: &GameConfiguration, command: Command) -> bool {
+        config.min_distance > 0.0 || command == Command::Life || config.band_cohesion_enabled()
+    }
+
+    /// Largest distance neighbors are queried at, which the cells have to cover.
+    pub fn query_distance(config: &GameConfiguration, command: Command) -> f32 {
+        let mut distance = config.min_distance.max(0.0);
+        if command == Command::Life {
+            distance = distance.max(config.commands.life.radius);
+        }
+        if config.band_cohesion_enabled() {
+            distance = distance.max(config.band_cohesion_radius);
+        }
+        distance
+    }
+
+    /// Grid resolution whose cells are at least `query_distance` wide.
+    pub fn cells_per_axis(query_distance: f32) -> u32 {
+        if query_distance <= 0.0 || query_distance.is_nan() {
+            return 1;
+        }
+        ((2.0 / query_distance).floor() as u32).clamp(1, MAX_CELLS_PER_AXIS)
+    }
+
+    /// Creates a grid with `cells_per_axis`² cells for up to `capacity` particles. A 1x1
+    /// grid for a single particle stands in while no feature needs the grid, so the
+    /// bindings stay valid without the memory cost.
+    pub fn new(device: &wgpu::Device, cells_per_axis: u32, capacity: u32) -> Self {
+        let cell_count = (cells_per_axis * cells_per_axis) as u64;
+        let capacity = capacity.max(1);
+
+        let storage = |label, size: u64| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grid Buffer"),
+            contents: bytemuck::cast_slice(&[GridUniform::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            uniform_buffer,
+            cell_counts: storage("Grid Cell Counts", cell_count * 4),
+            cell_starts: storage("Grid Cell Starts", (cell_count + 1) * 4),
+            sorted_indices: storage("Grid Sorted Indices", capacity as u64 * 4),
+            sorted_positions: storage("Grid Sorted Positions", capacity as u64 * 8),
+            cells_per_axis,
+            capacity,
+        }
+    }
+
+    /// Bytes held by the grid's buffers.
+    pub fn memory_size(&self) -> u64 {
+        [
+            &self.uniform_buffer,
+            &self.cell_counts,
+            &self.cell_starts,
+            &self.sorted_indices,
+            &self.sorted_positions,
+        ]
+        .iter()
+        .map(|buffer| buffer.size())
+        .sum()
+    }
+
+    pub fn cell_count(&self) -> u32 {
+        self.cells_per_axis * self.cells_per_axis
+    }
+
+    /// Whether this grid can serve `config` and `command` with `particle_count` particles,
+    /// or has to be recreated.
+    pub fn fits(&self, config: &GameConfiguration, command: Command, particle_count: u32) -> bool {
+        if !Self::is_needed(config, command) {
+            return true;
+        }
+        self.cells_per_axis == Self::cells_per_axis(Self::query_distance(config, command))
+            && self.capacity >= particle_count
+    }
+}
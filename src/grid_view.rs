@@ -0,0 +1,128 @@
+use crate::grid::SpatialGrid;
+
+/// Overlay coloring every cell of the spatial grid by its particle count, drawn into the
+/// particle pass before the particles. It reads the grid the compute pass built last step.
+pub struct GridView {
+    pub pipeline: wgpu::RenderPipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl GridView {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        resolution_buffer: &wgpu::Buffer,
+        grid: &SpatialGrid,
+    ) -> Self {
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grid View Bind Group Layout"),
+            entries: &[
+                // Resolution buffer
+                buffer_entry(0, wgpu::BufferBindingType::Uniform),
+                // Grid uniform
+                buffer_entry(1, wgpu::BufferBindingType::Uniform),
+                // Grid cell starts
+                buffer_entry(2, wgpu::BufferBindingType::Storage { read_only: true }),
+            ],
+        });
+
+        let bind_group = create_bind_group(device, &bind_group_layout, resolution_buffer, grid);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Grid View Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("grid_view.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grid View Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grid View Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    // empty cells are transparent and leave the background visible
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// Points the view at a reallocated grid.
+    pub fn rebind(
+        &mut self,
+        device: &wgpu::Device,
+        resolution_buffer: &wgpu::Buffer,
+        grid: &SpatialGrid,
+    ) {
+        self.bind_group =
+            create_bind_group(device, &self.bind_group_layout, resolution_buffer, grid);
+    }
+
+    /// Draws the cells into an already started pass.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    resolution_buffer: &wgpu::Buffer,
+    grid: &SpatialGrid,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Grid View Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: resolution_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: grid.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: grid.cell_starts.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -0,0 +1,90 @@
+// Full-screen overlay tinting every spatial grid cell by how many particles it holds, to
+// check the binning and find crowded cells
+
+struct Resolution {
+    width: f32,
+    height: f32,
+    view_angle: f32,
+    _padding: u32,
+};
+
+// Turns a screen position back by the angle `view` of shader.wgsl turns the particles by,
+// so the overlay lines up with them
+fn unturn(position: vec2<f32>) -> vec2<f32> {
+    let aspect_ratio = resolution.width / resolution.height;
+    let square = vec2<f32>(position.x * aspect_ratio, position.y);
+    let c = cos(resolution.view_angle);
+    let s = sin(resolution.view_angle);
+    let turned = vec2<f32>(c * square.x + s * square.y, c * square.y - s * square.x);
+    return vec2<f32>(turned.x / aspect_ratio, turned.y);
+}
+
+// Same layout as the Grid struct of compute.wgsl
+struct Grid {
+    cells_per_axis: u32,
+    cell_count: u32,
+    cell_size: f32,
+    min_distance: f32,
+    min_distance_stiffness: f32,
+    // particle i belongs to band i % color_bands
+    color_bands: u32,
+    // pull towards the neighbors of the same band within band_cohesion_radius, 0 disables it
+    band_cohesion: f32,
+    band_cohesion_radius: f32,
+};
+
+@group(0) @binding(0) var<uniform> resolution: Resolution;
+@group(0) @binding(1) var<uniform> grid: Grid;
+// offset of every cell's range in the sorted arrays, plus the total at the end
+@group(0) @binding(2) var<storage, read> cell_starts: array<u32>;
+
+// Cells holding more particles than this are only partially searched for neighbors,
+// matches MAX_NEIGHBORS_PER_CELL in compute.wgsl
+const MAX_NEIGHBORS_PER_CELL: f32 = 32.0;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// A single triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+// Dark blue through cyan and yellow for occupied cells up to the neighbor limit, red past it
+fn ramp(t: f32) -> vec3<f32> {
+    if t > 1.0 {
+        return vec3<f32>(1.0, 0.1, 0.1);
+    }
+    let low = mix(vec3<f32>(0.05, 0.1, 0.5), vec3<f32>(0.1, 0.8, 0.9), saturate(t * 2.0));
+    return mix(low, vec3<f32>(1.0, 0.9, 0.2), saturate(t * 2.0 - 1.0));
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    // pixel coordinates to the clip space the grid covers, y pointing up
+    let uv = input.position.xy / vec2<f32>(resolution.width, resolution.height);
+    let position = unturn(vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0));
+    // the grid is not built until the next simulation step after enabling it
+    if grid.cells_per_axis == 0u || any(abs(position) > vec2<f32>(1.0)) {
+        return vec4<f32>(0.0);
+    }
+
+    let coords = min(vec2<u32>((position + 1.0) / grid.cell_size), vec2<u32>(grid.cells_per_axis - 1u));
+    let cell = coords.y * grid.cells_per_axis + coords.x;
+    let count = cell_starts[cell + 1u] - cell_starts[cell];
+    if count == 0u {
+        return vec4<f32>(0.0);
+    }
+
+    // thin darker lines on the cell borders, so single cells can be told apart
+    let in_cell = fract((position + 1.0) / grid.cell_size);
+    let edge = any(in_cell < vec2<f32>(0.04)) || any(in_cell > vec2<f32>(0.96));
+    let alpha = select(0.6, 0.35, edge);
+    return vec4<f32>(ramp(f32(count) / MAX_NEIGHBORS_PER_CELL), alpha);
+}
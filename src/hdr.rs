@@ -0,0 +1,225 @@
+use log::{info, warn};
+
+use crate::{config::GameConfiguration, types::TonemapUniform};
+
+/// Format of the offscreen target particles are accumulated into when HDR is enabled.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Offscreen floating point render target plus the tonemapping pass that resolves it onto the
+/// surface. Particles are blended additively into it, so overlapping particles can become
+/// brighter than white before being compressed back into displayable range.
+pub struct HdrTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    /// Operator and exposure of the tonemapping pass.
+    pub params_buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    pub tonemap_pipeline: wgpu::RenderPipeline,
+}
+
+impl HdrTarget {
+    /// Returns whether the adapter can render to and sample from [`HDR_FORMAT`] with blending.
+    pub fn is_supported(adapter: &wgpu::Adapter) -> bool {
+        let features = adapter.get_texture_format_features(HDR_FORMAT);
+        features
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+            && features
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::BLENDABLE)
+    }
+
+    /// Picks the surface format for HDR output: the float format itself if the display can
+    /// take it, otherwise the regular sRGB choice.
+    pub fn surface_format(
+        surface_caps: &wgpu::SurfaceCapabilities,
+        fallback: wgpu::TextureFormat,
+    ) -> wgpu::TextureFormat {
+        if surface_caps.formats.contains(&HDR_FORMAT) {
+            info!("surface supports {:?}, presenting in HDR", HDR_FORMAT);
+            HDR_FORMAT
+        } else {
+            warn!(
+                "surface does not support {:?}, tonemapping to {:?}",
+                HDR_FORMAT, fallback
+            );
+            fallback
+        }
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (texture, view) = create_texture(device, width, height);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tonemap Bind Group Layout"),
+            entries: &[
+                // HDR texture (read with textureLoad, no sampler)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Operator and exposure
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tonemap Buffer"),
+            size: std::mem::size_of::<TonemapUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = create_bind_group(device, &bind_group_layout, &view, &params_buffer);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Tonemap Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("tonemap.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tonemap Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let tonemap_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tonemap Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            texture,
+            view,
+            params_buffer,
+            bind_group_layout,
+            bind_group,
+            tonemap_pipeline,
+        }
+    }
+
+    /// Recreates the offscreen texture to match a new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (texture, view) = create_texture(device, width, height);
+        self.bind_group =
+            create_bind_group(device, &self.bind_group_layout, &view, &self.params_buffer);
+        self.texture = texture;
+        self.view = view;
+    }
+
+    /// Uploads the operator and exposure of `config`, call before recording the tonemapping
+    /// pass.
+    pub fn prepare(&self, queue: &wgpu::Queue, config: &GameConfiguration) {
+        queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::cast_slice(&[TonemapUniform::from_config(config)]),
+        );
+    }
+
+    /// Records the tonemapping pass resolving the HDR target onto `surface_view`.
+    pub fn tonemap(&self, encoder: &mut wgpu::CommandEncoder, surface_view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tonemap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: surface_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_pipeline(&self.tonemap_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("HDR Target"),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HDR_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    view: &wgpu::TextureView,
+    params_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Tonemap Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: params_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -0,0 +1,72 @@
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::Path,
+};
+
+use crate::types::Particle;
+
+/// Reads particles from a CSV file with one `x,y,vx,vy` row per particle, optionally
+/// followed by a fifth `pinned` column of 0 or 1. Blank lines, lines starting with `#` and
+/// a header row of names are skipped. Accelerations are left at zero for the caller to
+/// seed, phases are random.
+pub fn load_particles(path: &Path) -> io::Result<Vec<Particle>> {
+    let file = io::BufReader::new(fs::File::open(path)?);
+    let mut particles = Vec::new();
+    for (number, line) in file.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} line {}: {}", path.display(), number + 1, message),
+            )
+        };
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if particles.is_empty() && fields.iter().all(|field| field.parse::<f32>().is_err()) {
+            // a header like `x,y,vx,vy`
+            continue;
+        }
+        if fields.len() != 4 && fields.len() != 5 {
+            return Err(invalid(format!(
+                "expected 4 values x,y,vx,vy and optionally pinned, found {}",
+                fields.len()
+            )));
+        }
+
+        let pinned = match fields.get(4) {
+            None | Some(&"0") => 0,
+            Some(&"1") => 1,
+            Some(field) => return Err(invalid(format!("pinned is {:?}, not 0 or 1", field))),
+        };
+        let mut values = [0.0; 4];
+        for (value, field) in values.iter_mut().zip(&fields) {
+            *value = field
+                .parse::<f32>()
+                .map_err(|_| invalid(format!("{:?} is not a number", field)))?;
+            if !value.is_finite() {
+                return Err(invalid(format!("{} is not finite", field)));
+            }
+        }
+        particles.push(Particle {
+            position: [values[0], values[1]],
+            velocity: [values[2], values[3]],
+            acceleration: [0.0, 0.0],
+            pinned,
+            // seeded along with the acceleration
+            phase: 0.0,
+        });
+    }
+
+    if particles.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} holds no particles", path.display()),
+        ));
+    }
+    Ok(particles)
+}
@@ -0,0 +1,264 @@
+use crate::types::Particle;
+
+/// How the particles are arranged in the particle buffer, chosen at build time with the
+/// `soa` feature. The whole buffer is one storage binding either way, and the shaders only
+/// reach the particles through the accessors of `shader_section`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParticleLayout {
+    /// Position, velocity and acceleration of every particle next to each other, the way
+    /// `Particle` is declared.
+    ArrayOfStructs,
+    /// All positions, then all velocities, then all accelerations, then the flags, each
+    /// section as long as the buffer's capacity. Kernels that only read positions, like the
+    /// grid build and the trails, touch a quarter of the memory, and neighboring invocations
+    /// read neighboring words.
+    StructOfArrays,
+}
+
+/// Position, velocity, acceleration and flags, the `vec2<f32>` sections of a struct of
+/// arrays. The flags section holds whether the particle is pinned as 0 or 1 in `x` and its
+/// phase in `y`.
+const COMPONENTS: usize = 4;
+const COMPONENT_SIZE: u64 = std::mem::size_of::<[f32; 2]>() as u64;
+
+impl ParticleLayout {
+    /// The layout of this build.
+    pub const CURRENT: Self = if cfg!(feature = "soa") {
+        Self::StructOfArrays
+    } else {
+        Self::ArrayOfStructs
+    };
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ArrayOfStructs => "array of structs",
+            Self::StructOfArrays => "struct of arrays",
+        }
+    }
+
+    /// Contents of a buffer holding exactly `particles`.
+    pub fn pack(self, particles: &[Particle]) -> Vec<u8> {
+        match self {
+            Self::ArrayOfStructs => bytemuck::cast_slice(particles).to_vec(),
+            Self::StructOfArrays => (0..COMPONENTS)
+                .flat_map(|component| particles.iter().map(move |p| component_of(p, component)))
+                .flat_map(bytemuck::cast::<[f32; 2], [u8; 8]>)
+                .collect(),
+        }
+    }
+
+    /// The particles of a whole buffer read back from the GPU, the inverse of `pack`.
+    pub fn unpack(self, bytes: &[u8]) -> Vec<Particle> {
+        let mut particles = Vec::new();
+        self.unpack_into(bytes, &mut particles);
+        particles
+    }
+
+    /// Like `unpack`, but replaces the contents of `particles`, reusing its allocation.
+    pub fn unpack_into(self, bytes: &[u8], particles: &mut Vec<Particle>) {
+        particles.clear();
+        // the mapped bytes carry no alignment guarantee for Particle
+        match self {
+            Self::ArrayOfStructs => particles.extend(
+                bytes
+                    .chunks_exact(std::mem::size_of::<Particle>())
+                    .map(bytemuck::pod_read_unaligned::<Particle>),
+            ),
+            Self::StructOfArrays => {
+                let capacity = bytes.len() / std::mem::size_of::<Particle>();
+                let read = |component: usize, index: usize| -> [f32; 2] {
+                    let offset = (component * capacity + index) * COMPONENT_SIZE as usize;
+                    bytemuck::pod_read_unaligned(&bytes[offset..offset + COMPONENT_SIZE as usize])
+                };
+                particles.extend((0..capacity).map(|index| Particle {
+                    position: read(0, index),
+                    velocity: read(1, index),
+                    acceleration: read(2, index),
+                    pinned: read(3, index)[0] as u32,
+                    phase: read(3, index)[1],
+                }));
+            }
+        }
+    }
+
+    /// The byte ranges of a buffer with room for `capacity` particles that hold particle
+    /// `index`. Read back one after the other, they form a one particle buffer for `unpack`.
+    pub fn particle_ranges(self, capacity: u32, index: u32) -> Vec<(u64, u64)> {
+        match self {
+            Self::ArrayOfStructs => {
+                let size = std::mem::size_of::<Particle>() as u64;
+                vec![(index as u64 * size, size)]
+            }
+            Self::StructOfArrays => (0..COMPONENTS as u64)
+                .map(|component| {
+                    let offset = (component * capacity as u64 + index as u64) * COMPONENT_SIZE;
+                    (offset, COMPONENT_SIZE)
+                })
+                .collect(),
+        }
+    }
+
+    /// Overwrites the particles from index `start` on in `buffer`, which has room for
+    /// `capacity` particles.
+    pub fn write(
+        self,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        capacity: u32,
+        start: u32,
+        particles: &[Particle],
+    ) {
+        match self {
+            Self::ArrayOfStructs => queue.write_buffer(
+                buffer,
+                start as u64 * std::mem::size_of::<Particle>() as u64,
+                bytemuck::cast_slice(particles),
+            ),
+            Self::StructOfArrays => {
+                for component in 0..COMPONENTS {
+                    let values: Vec<[f32; 2]> = particles
+                        .iter()
+                        .map(|p| component_of(p, component))
+                        .collect();
+                    let offset =
+                        (component as u64 * capacity as u64 + start as u64) * COMPONENT_SIZE;
+                    queue.write_buffer(buffer, offset, bytemuck::cast_slice(&values));
+                }
+            }
+        }
+    }
+
+    /// Copies the first `count` particles of `source` into `destination`, buffers with room
+    /// for `source_capacity` and `destination_capacity` particles.
+    pub fn copy_prefix(
+        self,
+        encoder: &mut wgpu::CommandEncoder,
+        (source, source_capacity): (&wgpu::Buffer, u32),
+        (destination, destination_capacity): (&wgpu::Buffer, u32),
+        count: u32,
+    ) {
+        match self {
+            Self::ArrayOfStructs => {
+                let size = count as u64 * std::mem::size_of::<Particle>() as u64;
+                encoder.copy_buffer_to_buffer(source, 0, destination, 0, size);
+            }
+            Self::StructOfArrays => {
+                // every section starts at a multiple of its capacity, which differs
+                for component in 0..COMPONENTS as u64 {
+                    encoder.copy_buffer_to_buffer(
+                        source,
+                        component * source_capacity as u64 * COMPONENT_SIZE,
+                        destination,
+                        component * destination_capacity as u64 * COMPONENT_SIZE,
+                        count as u64 * COMPONENT_SIZE,
+                    );
+                }
+            }
+        }
+    }
+
+    /// The particle storage binding and its accessors for a shader's `$RUST_PARTICLES`
+    /// section. Only shaders that may write the buffer get `store_particle`,
+    /// `set_particle_position` and `set_particle_velocity`.
+    pub fn shader_section(self, writable: bool) -> String {
+        let access = if writable { "read_write" } else { "read" };
+        let (declaration, read, write) = match self {
+            Self::ArrayOfStructs => ("array<Particle>", AOS_READ, AOS_WRITE),
+            Self::StructOfArrays => ("array<vec2<f32>>", SOA_READ, SOA_WRITE),
+        };
+        let mut section = format!(
+            "\n@group(0) @binding(1) var<storage, {}> particles: {};\n{}",
+            access, declaration, read
+        );
+        if writable {
+            section.push_str(write);
+        }
+        section
+    }
+}
+
+fn component_of(particle: &Particle, component: usize) -> [f32; 2] {
+    let flags = [(particle.pinned != 0) as u32 as f32, particle.phase];
+    [
+        particle.position,
+        particle.velocity,
+        particle.acceleration,
+        flags,
+    ][component]
+}
+
+const AOS_READ: &str = "
+fn load_particle(index: u32) -> Particle {
+    return particles[index];
+}
+
+fn particle_position(index: u32) -> vec2<f32> {
+    return particles[index].position;
+}
+
+fn particle_velocity(index: u32) -> vec2<f32> {
+    return particles[index].velocity;
+}
+
+fn particle_pinned(index: u32) -> bool {
+    return particles[index].pinned != 0u;
+}
+";
+
+const AOS_WRITE: &str = "
+fn store_particle(index: u32, particle: Particle) {
+    particles[index] = particle;
+}
+
+fn set_particle_position(index: u32, position: vec2<f32>) {
+    particles[index].position = position;
+}
+
+fn set_particle_velocity(index: u32, velocity: vec2<f32>) {
+    particles[index].velocity = velocity;
+}
+";
+
+const SOA_READ: &str = "
+// positions, then velocities, then accelerations, then flags, each section as long as the
+// capacity
+fn particle_capacity() -> u32 {
+    return arrayLength(&particles) / 4u;
+}
+
+fn load_particle(index: u32) -> Particle {
+    let capacity = particle_capacity();
+    let flags = particles[3u * capacity + index];
+    return Particle(particles[index], particles[capacity + index], particles[2u * capacity + index], u32(flags.x != 0.0), flags.y);
+}
+
+fn particle_position(index: u32) -> vec2<f32> {
+    return particles[index];
+}
+
+fn particle_velocity(index: u32) -> vec2<f32> {
+    return particles[particle_capacity() + index];
+}
+
+fn particle_pinned(index: u32) -> bool {
+    return particles[3u * particle_capacity() + index].x != 0.0;
+}
+";
+
+const SOA_WRITE: &str = "
+fn store_particle(index: u32, particle: Particle) {
+    let capacity = particle_capacity();
+    particles[index] = particle.position;
+    particles[capacity + index] = particle.velocity;
+    particles[2u * capacity + index] = particle.acceleration;
+    particles[3u * capacity + index] = vec2<f32>(f32(particle.pinned != 0u), particle.phase);
+}
+
+fn set_particle_position(index: u32, position: vec2<f32>) {
+    particles[index] = position;
+}
+
+fn set_particle_velocity(index: u32, velocity: vec2<f32>) {
+    particles[particle_capacity() + index] = velocity;
+}
+";
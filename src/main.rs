@@ -1,115 +1,686 @@
-use std::{fs, io, path::Path};
+use std::{
+    path::Path,
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use serde::{Deserialize, Serialize};
+use bench::{BenchProgress, Benchmark};
+use clap::Parser;
+use cli::{Cli, CliCommand};
+use config::GameConfiguration;
+use control::{ControlRequest, ControlServer};
+use dump::ParticleDump;
+use gpu::Gpu;
+use log::{debug, info, warn};
+use replay::{Player, RecordedEvent, RecordedEventKind, Recorder};
 use state::State;
+use timeline::Timeline;
 use types::ResolutionUniform;
 use winit::{
-    event::{Event, WindowEvent},
-    event_loop::EventLoop,
-    window::WindowBuilder,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    window::{Window, WindowBuilder},
 };
 
+mod background;
+mod bench;
+mod canvas;
+mod chaos;
+mod cli;
+mod config;
+mod control;
+mod cursor;
+mod depth;
+mod dispatch;
+mod dump;
+mod field;
+mod flow_field;
+mod gpu;
+mod grid;
+mod grid_view;
+mod hdr;
+mod import;
+mod layout;
+mod memory;
+mod net;
+mod overlay;
+mod perf_graph;
+mod preset;
+mod quality;
+mod readback;
+mod replay;
+mod selftest;
 mod state;
+mod stats;
+mod supersample;
+mod timeline;
+mod tint;
+mod trails;
 mod types;
+mod upload;
+
+/// Returns the size closest to `size` that has the given width / height ratio, keeping the
+/// width and adjusting the height.
+fn aspect_corrected_size(size: PhysicalSize<u32>, aspect: f32) -> PhysicalSize<u32> {
+    let height = (size.width as f32 / aspect).round().max(1.0) as u32;
+    PhysicalSize::new(size.width, height)
+}
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct GameConfiguration {
-    pub num_particles: u32,
-    pub quad_size: f32,
+/// Asks the window to take on the locked aspect ratio, if one is configured.
+///
+/// Sizes within a pixel of the target are accepted as-is, so the resize event produced by
+/// our own request does not trigger another request.
+fn enforce_aspect(window: &Window, config: &GameConfiguration, size: PhysicalSize<u32>) {
+    let Some(aspect) = config.lock_aspect.filter(|aspect| *aspect > 0.0) else {
+        return;
+    };
+    if size.width == 0 || size.height == 0 {
+        return;
+    }
+
+    let corrected = aspect_corrected_size(size, aspect);
+    if corrected.height.abs_diff(size.height) > 1 {
+        debug!(
+            "resizing {}x{} to {}x{} to keep aspect ratio {}",
+            size.width, size.height, corrected.width, corrected.height, aspect
+        );
+        // the window manager may refuse or adjust the request, in which case a Resized
+        // event with the actual size follows
+        let _ = window.request_inner_size(corrected);
+    }
 }
 
-impl Default for GameConfiguration {
-    fn default() -> Self {
-        Self {
-            num_particles: 1000,
-            quad_size: 0.001,
+/// Stores the current window size and position in the configuration file, so the next run
+/// opens where this one was closed. The rest of the file is left as it is on disk, settings
+/// changed while running are not saved.
+fn save_window_geometry(window: &Window, path: &Path) {
+    let size = window.inner_size();
+    if size.width == 0 || size.height == 0 {
+        // minimized, keep the last real size
+        return;
+    }
+
+    let mut config = match GameConfiguration::load(path) {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("failed to save window geometry: {}", err);
+            return;
         }
+    };
+    config.window_width = size.width;
+    config.window_height = size.height;
+    config.window_position = window
+        .outer_position()
+        .ok()
+        .map(|position| [position.x, position.y]);
+
+    match config.save(path) {
+        Ok(()) => debug!("saved window geometry to {}", path.display()),
+        Err(err) => warn!("failed to save window geometry: {}", err),
+    }
+}
+
+/// Appends an event to the recording, stamped with the frame it will be applied before.
+fn record(recorder: &mut Recorder, state: &State, kind: RecordedEventKind) {
+    let event = RecordedEvent {
+        frame: state.frame + 1,
+        time: state.elapsed_time as f32,
+        kind,
+    };
+    if let Err(err) = recorder.record(&event) {
+        warn!("failed to record input event: {}", err);
     }
 }
 
-impl GameConfiguration {
-    pub fn from_path(path: &Path) -> io::Result<Self> {
-        // read from the path, or create it if it doesnt exist with default.
-        if path.exists() {
-            let file = fs::File::open(path)?;
-            let config: GameConfiguration = serde_json::from_reader(file)?;
-            Ok(config)
-        } else {
-            let default_config = GameConfiguration::default();
-            let file = fs::File::create(path)?;
-            serde_json::to_writer_pretty(file, &default_config)?;
-            Ok(default_config)
+/// A second window opened with W, running an independent simulation on the device of the
+/// main window, e.g. to compare two commands or integrators side by side.
+struct SecondWindow {
+    window: Arc<Window>,
+    state: State<'static>,
+}
+
+impl SecondWindow {
+    /// Opens the window at the size of `main`, starting from a copy of its configuration.
+    fn open(elwt: &EventLoopWindowTarget<()>, main: &State) -> Self {
+        let window = Arc::new(
+            WindowBuilder::new()
+                .with_title(format!("{} (2)", main.game_config.window_title))
+                .with_inner_size(main.size)
+                .build(elwt)
+                .unwrap(),
+        );
+        let mut state =
+            State::new_shared(main.gpu.clone(), window.clone(), main.game_config.clone());
+        prepare_state(&mut state, &window);
+        state.use_fixed_timestep = main.use_fixed_timestep;
+        info!("opened a second window");
+        Self { window, state }
+    }
+
+    /// Handles an event of this window, returning false once it was closed.
+    fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        let state = &mut self.state;
+        if state.input(&self.window, event) {
+            return true;
+        }
+        match event {
+            WindowEvent::CloseRequested => return false,
+            WindowEvent::Resized(physical_size) => {
+                state.resize(*physical_size);
+                state.current_resolution =
+                    ResolutionUniform::new(physical_size.width, physical_size.height);
+            }
+            WindowEvent::CursorMoved { position, .. } => state.mouse_moved(*position),
+            WindowEvent::MouseInput {
+                state: button_state,
+                button,
+                ..
+            } => state.mouse_input(*button, *button_state),
+            WindowEvent::KeyboardInput {
+                device_id,
+                event,
+                is_synthetic,
+            } => {
+                state.keyboard_input(*device_id, event, *is_synthetic, &self.window);
+                // there is only one second window
+                state.second_window_requested = false;
+            }
+            WindowEvent::RedrawRequested => {
+                state.update();
+                match state.render(&self.window) {
+                    Ok(_) => {}
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        state.resize(state.size)
+                    }
+                    Err(wgpu::SurfaceError::OutOfMemory) => {
+                        log::error!("surface of the second window is out of memory, closing it");
+                        return false;
+                    }
+                    Err(wgpu::SurfaceError::Timeout) => log::warn!("surface timed out"),
+                }
+            }
+            _ => {}
         }
+        true
     }
 }
 
-fn main() {
-    let event_loop = EventLoop::new().unwrap();
-    let window = WindowBuilder::new()
-        .with_title("Red Triangle")
-        .build(&event_loop)
-        .unwrap();
+/// How the window session is driven, selected by the subcommand.
+enum Mode {
+    /// Interactive use, optionally capturing input.
+    Interactive(Option<Recorder>),
+    Replay(Player),
+    Bench(Benchmark),
+}
 
-    let config = GameConfiguration::from_path(Path::new("config.json")).unwrap();
+fn main() -> ExitCode {
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("warn,hashnet_compute_shader=info"),
+    )
+    .init();
 
-    let mut state = pollster::block_on(State::new(&window, config));
-    state.current_resolution = ResolutionUniform {
-        width: window.inner_size().width as f32,
-        height: window.inner_size().height as f32,
+    let cli = Cli::parse();
+    if cli.list_adapters {
+        for (index, adapter) in Gpu::adapters(&Gpu::create_instance()).iter().enumerate() {
+            println!("{}: {}", index, gpu::describe(&adapter.get_info()));
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let mut config = GameConfiguration::from_path(&cli.config).unwrap();
+    config.adapter_override = cli.adapter;
+
+    let mut control = None;
+    let mut timeline = None;
+    let mut dump_directory = None;
+    let command = cli.command.unwrap_or(CliCommand::Run {
+        record: None,
+        control_socket: None,
+        timeline: None,
+        dump_particles: None,
+    });
+    let mode = match command {
+        CliCommand::Run {
+            record,
+            control_socket,
+            timeline: timeline_path,
+            dump_particles,
+        } => {
+            dump_directory = dump_particles;
+            control = control_socket.map(|addr| ControlServer::bind(addr).unwrap());
+            timeline = timeline_path.map(|path| {
+                info!("following the timeline in {}", path.display());
+                Timeline::load(&path).unwrap()
+            });
+            Mode::Interactive(record.map(|path| {
+                info!("recording input to {}", path.display());
+                // the replay has to seed the same particles
+                let seed = *config.seed.get_or_insert_with(rand::random);
+                Recorder::create(&path, seed).unwrap()
+            }))
+        }
+        CliCommand::Replay {
+            file,
+            dump_particles,
+        } => {
+            dump_directory = dump_particles;
+            info!("replaying input from {}", file.display());
+            let player = Player::load(&file).unwrap();
+            match player.seed() {
+                Some(seed) => config.seed = Some(seed),
+                None => warn!(
+                    "{} does not store the seed of the recorded particles, the replay starts from different ones",
+                    file.display()
+                ),
+            }
+            Mode::Replay(player)
+        }
+        CliCommand::Bench { frames, particles } => {
+            let count = particles.unwrap_or(config.num_particles);
+            Mode::Bench(Benchmark::new(vec![count], frames))
+        }
+        CliCommand::Sweep { counts, frames } => Mode::Bench(Benchmark::new(counts, frames)),
+        CliCommand::Compare {
+            comparison,
+            frames,
+            particles,
+        } => {
+            config.num_particles = particles.unwrap_or(config.num_particles);
+            // measure the configured count, not whatever the quality controller settles on
+            config.dynamic_quality.enabled = false;
+            config.validate();
+            let instance = Gpu::create_instance();
+            let gpu = Arc::new(pollster::block_on(Gpu::new(
+                instance,
+                None,
+                config.adapter(),
+            )));
+            bench::compare(&gpu, comparison, &config, frames);
+            return ExitCode::SUCCESS;
+        }
+        CliCommand::Selftest => {
+            let config = headless_config(cli.adapter);
+            let instance = Gpu::create_instance();
+            let gpu = Arc::new(pollster::block_on(Gpu::new(
+                instance,
+                None,
+                config.adapter(),
+            )));
+            let passed = selftest::run(&gpu, &config);
+            return exit_code(passed);
+        }
+        CliCommand::Chaos { seed, iterations } => {
+            let seed = seed.unwrap_or_else(rand::random);
+            let mut state = headless_state(cli.adapter);
+            let passed = chaos::run(&mut state, seed, iterations);
+            return exit_code(passed);
+        }
     };
-    state.resize(state.size);
+
+    if let Mode::Bench(bench) = &mode {
+        // the buffer is allocated once for the largest count, smaller counts use a prefix
+        config.num_particles = bench.max_count();
+        // measure the requested counts, not whatever the quality controller settles on
+        config.dynamic_quality.enabled = false;
+    }
+    config.validate();
+    let dump = dump_directory.map(|directory| {
+        ParticleDump::create(&directory, config.dump_interval, config.num_particles).unwrap()
+    });
+
+    if let Some(duration) = cli.duration {
+        info!("exiting after {:.1} s", duration.as_secs_f64());
+    }
+    run(
+        config,
+        &cli.config,
+        mode,
+        control,
+        timeline,
+        dump,
+        cli.duration,
+    );
+    ExitCode::SUCCESS
+}
+
+/// The exit code of a run that checks something, failing unless everything `passed`.
+fn exit_code(passed: bool) -> ExitCode {
+    if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// The configuration of the self-test and chaos runs: the self-test one, ignoring the
+/// configuration file so the results do not depend on it, rendering with adapter `adapter`
+/// if given.
+fn headless_config(adapter: Option<usize>) -> GameConfiguration {
+    let mut config = selftest::config();
+    config.adapter_override = adapter;
+    config
+}
+
+/// A state without a window for the chaos run, so it also runs on machines without a
+/// display.
+fn headless_state(adapter: Option<usize>) -> State<'static> {
+    pollster::block_on(State::new_headless(headless_config(adapter)))
+}
+
+/// Opens the window and runs the event loop until it is closed, the mode finishes or
+/// `duration` passed.
+fn run(
+    config: GameConfiguration,
+    config_path: &Path,
+    mut mode: Mode,
+    control: Option<ControlServer>,
+    timeline: Option<Timeline>,
+    mut dump: Option<ParticleDump>,
+    duration: Option<Duration>,
+) {
+    let event_loop = EventLoop::new().unwrap();
+    // sizes larger than the monitor are clamped by winit / the window manager
+    let mut window_builder = WindowBuilder::new()
+        .with_title(&config.window_title)
+        .with_inner_size(PhysicalSize::new(config.window_width, config.window_height));
+    if let Some([x, y]) = config.window_position {
+        window_builder = window_builder.with_position(PhysicalPosition::new(x, y));
+    }
+    let window = window_builder.build(&event_loop).unwrap();
+
+    let mut state = create_state(&window, config, &mode);
+    state.timeline = timeline;
+    if let Mode::Bench(bench) = &mode {
+        state.game_config.num_particles = bench.first_count();
+    }
+    // only empty while a lost device is being replaced
+    let mut state_slot = Some(state);
+    let mut second_window: Option<SecondWindow> = None;
+
+    let mut last_redraw = Instant::now();
+    // counted from here, so creating the state and the warm-up do not use up the duration
+    let deadline = duration.map(|duration| Instant::now() + duration);
 
     event_loop
-        .run(|event, elwt| match event {
-            Event::WindowEvent {
-                ref event,
-                window_id,
-            } if window_id == window.id() => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested => elwt.exit(),
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                            state.current_resolution = ResolutionUniform {
-                                width: physical_size.width as f32,
-                                height: physical_size.height as f32,
+        .run(|event, elwt| {
+            if second_window.is_some() && state_slot.as_ref().is_some_and(State::is_device_lost) {
+                // the replacement device is only set up for the main window
+                warn!("closing the second window, its device was lost");
+                second_window = None;
+            }
+            recover_lost_device(&mut state_slot, &window, &mode);
+            let state = state_slot.as_mut().unwrap();
+            match event {
+                Event::WindowEvent {
+                    ref event,
+                    window_id,
+                } if second_window
+                    .as_ref()
+                    .is_some_and(|second| second.window.id() == window_id)
+                    && !second_window.as_mut().unwrap().handle_event(event) =>
+                {
+                    info!("closed the second window");
+                    second_window = None;
+                }
+                Event::WindowEvent {
+                    ref event,
+                    window_id,
+                } if window_id == window.id() && !state.input(&window, event) => match event {
+                    WindowEvent::CloseRequested => {
+                        shut_down(&mut mode, &window, config_path);
+                        elwt.exit()
+                    }
+                    WindowEvent::Resized(physical_size) => {
+                        enforce_aspect(&window, &state.game_config, *physical_size);
+                        state.resize(*physical_size);
+                        state.current_resolution =
+                            ResolutionUniform::new(physical_size.width, physical_size.height);
+                    }
+
+                    WindowEvent::CursorMoved { position, .. } => {
+                        if let Mode::Interactive(Some(recorder)) = &mut mode {
+                            let kind = RecordedEventKind::cursor_moved(*position, state.size);
+                            record(recorder, state, kind);
+                        }
+                        state.mouse_moved(*position);
+                    }
+
+                    WindowEvent::MouseInput {
+                        state: button_state,
+                        button,
+                        ..
+                    } => {
+                        if let Mode::Interactive(Some(recorder)) = &mut mode {
+                            let kind = RecordedEventKind::MouseInput {
+                                button: *button,
+                                pressed: button_state.is_pressed(),
+                            };
+                            record(recorder, state, kind);
+                        }
+                        state.mouse_input(*button, *button_state);
+                    }
+
+                    WindowEvent::KeyboardInput {
+                        device_id,
+                        event,
+                        is_synthetic,
+                    } => {
+                        if let Mode::Interactive(Some(recorder)) = &mut mode
+                            && event.state == ElementState::Pressed
+                            && !is_synthetic
+                        {
+                            let kind = RecordedEventKind::KeyPressed {
+                                key: event.logical_key.clone(),
                             };
+                            record(recorder, state, kind);
                         }
+                        state.keyboard_input(*device_id, event, *is_synthetic, &window);
+                    }
 
-                        WindowEvent::CursorMoved {
-                            device_id,
-                            position,
-                        } => {
-                            state.mouse_moved(*device_id, *position);
+                    WindowEvent::RedrawRequested => {
+                        last_redraw = Instant::now();
+                        state.update();
+                        match state.render(&window) {
+                            Ok(_) => {
+                                if let Some(particle_dump) = &mut dump
+                                    && let Err(err) = particle_dump.frame_rendered(state)
+                                {
+                                    warn!("stopped dumping particles: {}", err);
+                                    dump = None;
+                                }
+                                if let Mode::Bench(bench) = &mut mode {
+                                    match bench.frame_rendered() {
+                                        BenchProgress::Continue => {}
+                                        BenchProgress::Next(count) => {
+                                            state.game_config.num_particles = count
+                                        }
+                                        BenchProgress::Finished => elwt.exit(),
+                                    }
+                                }
+                            }
+                            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                                state.resize(state.size)
+                            }
+                            Err(wgpu::SurfaceError::OutOfMemory) => {
+                                log::error!("surface is out of memory, exiting");
+                                elwt.exit()
+                            }
+                            Err(wgpu::SurfaceError::Timeout) => log::warn!("surface timed out"),
                         }
+                    }
+                    _ => {}
+                },
+                Event::AboutToWait => {
+                    if state.quit_requested
+                        || second_window
+                            .as_ref()
+                            .is_some_and(|second| second.state.quit_requested)
+                    {
+                        info!("quit key pressed, exiting");
+                        shut_down(&mut mode, &window, config_path);
+                        elwt.exit();
+                        return;
+                    }
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        // particle dumps are written and flushed as each frame renders, the
+                        // recording is flushed by shut_down
+                        info!("duration elapsed after {} frames, exiting", state.frame);
+                        shut_down(&mut mode, &window, config_path);
+                        elwt.exit();
+                        return;
+                    }
 
-                        WindowEvent::KeyboardInput {
-                            device_id,
-                            event,
-                            is_synthetic,
-                        } => {
-                            state.keyboard_input(*device_id, event, *is_synthetic, &window);
+                    if let Some(control) = &control {
+                        for pending in control.pending() {
+                            let result = match pending.request {
+                                ControlRequest::Quit => {
+                                    info!("quit requested over the control socket, exiting");
+                                    shut_down(&mut mode, &window, config_path);
+                                    elwt.exit();
+                                    Ok(())
+                                }
+                                ref request => state.apply_control(request),
+                            };
+                            pending.respond(result);
                         }
+                    }
 
-                        WindowEvent::RedrawRequested => {
-                            state.update();
-                            match state.render() {
-                                Ok(_) => {}
-                                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                                    state.resize(state.size)
+                    if let Mode::Replay(player) = &mut mode {
+                        let finished = player.is_finished();
+                        for event in player.due(state.frame + 1) {
+                            match &event.kind {
+                                RecordedEventKind::CursorMoved { x, y } => {
+                                    state.mouse_moved(PhysicalPosition::new(
+                                        x * state.size.width as f64,
+                                        y * state.size.height as f64,
+                                    ));
+                                }
+                                RecordedEventKind::KeyPressed { key } => {
+                                    state.key_pressed(key, Some(&window));
+                                }
+                                RecordedEventKind::MouseInput { button, pressed } => {
+                                    let button_state = if *pressed {
+                                        ElementState::Pressed
+                                    } else {
+                                        ElementState::Released
+                                    };
+                                    state.mouse_input(*button, button_state);
                                 }
-                                Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
-                                Err(wgpu::SurfaceError::Timeout) => {}
                             }
                         }
-                        _ => {}
+                        if !finished && player.is_finished() {
+                            info!("replay finished at frame {}", state.frame);
+                        }
+                    }
+
+                    if std::mem::take(&mut state.second_window_requested)
+                        && matches!(mode, Mode::Interactive(_))
+                    {
+                        if second_window.is_none() {
+                            second_window = Some(SecondWindow::open(elwt, state));
+                        } else {
+                            info!("a second window is already open");
+                        }
+                    }
+
+                    // a static scene is redrawn at the idle rate, sleeping in between
+                    let idle = state.is_idle()
+                        && second_window
+                            .as_ref()
+                            .is_none_or(|second| second.state.is_idle());
+                    if let Some(second) = &second_window {
+                        second.window.request_redraw();
+                    }
+                    if state.minimized
+                        && second_window.is_none()
+                        && matches!(mode, Mode::Interactive(_))
+                    {
+                        // nothing to draw, the Resized event of restoring wakes the loop
+                        elwt.set_control_flow(
+                            deadline.map_or(ControlFlow::Wait, ControlFlow::WaitUntil),
+                        );
+                    } else if matches!(mode, Mode::Interactive(_)) && idle {
+                        let next_redraw =
+                            last_redraw + Duration::from_secs_f32(1.0 / state.game_config.idle.fps);
+                        if Instant::now() >= next_redraw {
+                            window.request_redraw();
+                        }
+                        let wake =
+                            deadline.map_or(next_redraw, |deadline| next_redraw.min(deadline));
+                        elwt.set_control_flow(ControlFlow::WaitUntil(wake));
+                    } else {
+                        elwt.set_control_flow(ControlFlow::Poll);
+                        window.request_redraw();
                     }
                 }
+                _ => {}
             }
-            Event::AboutToWait => {
-                window.request_redraw();
-            }
-            _ => {}
         })
         .unwrap();
 }
+
+/// Finishes the recording and saves the window geometry before the event loop exits.
+fn shut_down(mode: &mut Mode, window: &Window, config_path: &Path) {
+    match mode {
+        Mode::Interactive(recorder) => {
+            if let Some(recorder) = recorder {
+                recorder.flush().unwrap();
+            }
+            save_window_geometry(window, config_path);
+        }
+        Mode::Replay(_) => save_window_geometry(window, config_path),
+        // benchmarks leave the configuration file alone
+        Mode::Bench(_) => {}
+    }
+}
+
+/// Creates the GPU state for `window` and prepares it for `mode`.
+fn create_state<'a>(window: &'a Window, config: GameConfiguration, mode: &Mode) -> State<'a> {
+    let mut state = pollster::block_on(State::new(window, config));
+    // recordings are only reproducible if every frame advances by the same amount, and
+    // benchmarks should simulate the same motion regardless of their frame rate
+    state.use_fixed_timestep = !matches!(mode, Mode::Interactive(None));
+    prepare_state(&mut state, window);
+    state
+}
+
+/// Fits a newly created state to its window and runs the warm-up steps.
+fn prepare_state(state: &mut State, window: &Window) {
+    debug!(
+        "created particle buffer for {} particles ({} bytes)",
+        state.game_config.num_particles,
+        state.particle_buffer.size()
+    );
+    state.current_resolution =
+        ResolutionUniform::new(window.inner_size().width, window.inner_size().height);
+    state.resize(state.size);
+    enforce_aspect(window, &state.game_config, state.size);
+    info!("{}", state.memory_report());
+    state.warm_up();
+}
+
+/// Replaces the state once its device was lost (driver reset, GPU hang). Everything on the
+/// GPU is rebuilt and the particles are seeded anew; the configuration, the active command
+/// and the frame counter carry over so recordings and replays stay in step.
+fn recover_lost_device<'a>(state_slot: &mut Option<State<'a>>, window: &'a Window, mode: &Mode) {
+    if !state_slot.as_ref().is_some_and(State::is_device_lost) {
+        return;
+    }
+
+    let mut lost = state_slot.take().unwrap();
+    let config = lost.game_config.clone();
+    let (frame, command, paused) = (lost.frame, lost.current_command, lost.paused);
+    let timeline = lost.timeline.take();
+    // the window can only be presented to by one surface at a time
+    drop(lost);
+
+    warn!("recreating the GPU state after the device was lost, particles are re-seeded");
+    let mut state = create_state(window, config, mode);
+    state.frame = frame;
+    state.current_command = command;
+    state.paused = paused;
+    state.timeline = timeline;
+    *state_slot = Some(state);
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+
+/// Estimate of the GPU memory a [`State`](crate::state::State) holds, broken down by what
+/// it is used for. Only counts the buffers and textures the program creates itself, the
+/// driver's own overhead and alignment padding come on top.
+pub struct MemoryReport {
+    pub entries: Vec<(&'static str, u64)>,
+}
+
+impl MemoryReport {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds `bytes` under `name`. Entries without any memory are left out of the report.
+    pub fn add(&mut self, name: &'static str, bytes: u64) {
+        if bytes > 0 {
+            self.entries.push((name, bytes));
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.entries.iter().map(|(_, bytes)| bytes).sum()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GPU memory estimate: {} total",
+            format_bytes(self.total())
+        )?;
+        for (name, bytes) in &self.entries {
+            write!(f, "\n  {:<20} {:>10}", name, format_bytes(*bytes))?;
+        }
+        Ok(())
+    }
+}
+
+/// Size of a `width` x `height` texture of `format`, 4 bytes per texel for formats without
+/// a fixed block size.
+pub fn texture_bytes(format: wgpu::TextureFormat, width: u32, height: u32) -> u64 {
+    let texel = format.block_copy_size(None).unwrap_or(4) as u64;
+    width as u64 * height as u64 * texel
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,302 @@
+use std::f32::consts::SQRT_2;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    config::NetConfig,
+    dispatch::{self, DispatchConfig},
+    state::create_compute_pipeline,
+    types::Particle,
+    upload::Uploads,
+};
+
+/// Spring slots of every particle of the net: the 4 neighbors along the rows and columns,
+/// then the 4 diagonal ones.
+pub const SPRINGS_PER_PARTICLE: u32 = 8;
+
+/// Marks an unused slot, matches NO_SPRING of compute.wgsl.
+const NO_SPRING: u32 = u32::MAX;
+
+// One spring of a particle, matches the Spring struct of compute.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct Spring {
+    pub other: u32, // Index of the particle at the other end, NO_SPRING if the slot is unused
+    pub rest_length: f32,
+    pub stiffness: f32,
+    pub _padding: u32,
+}
+
+impl Spring {
+    const NONE: Self = Self {
+        other: NO_SPRING,
+        rest_length: 0.0,
+        stiffness: 0.0,
+        _padding: 0,
+    };
+}
+
+// Settings of apply_springs, matches the Net struct of compute.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct NetUniform {
+    gravity: [f32; 2],
+    damping: f32,
+    particle_count: u32, // Particles of the net that are simulated this step
+}
+
+/// Springs connecting the first particles into a regular grid, a cloth-like net.
+/// `apply_springs` of compute.wgsl turns them into velocity changes before the update moves
+/// the particles, reading every particle's springs from a fixed number of slots.
+///
+/// Like the trails, the springs run with a bind group of their own, so the update kernels
+/// do not need another storage buffer. They are only allocated at full size while the net
+/// is enabled.
+pub struct SpringNet {
+    /// `SPRINGS_PER_PARTICLE` slots for every particle of the net.
+    pub springs: wgpu::Buffer,
+    /// The settings `springs` was generated from, none until `prepare` first ran.
+    built_for: Option<NetConfig>,
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    /// Particles `record` dispatches, set by `prepare`.
+    particle_count: u32,
+}
+
+impl SpringNet {
+    /// `module` is the compute shader, which holds the `apply_springs` kernel.
+    pub fn new(
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+        time_buffer: &wgpu::Buffer,
+        particle_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        // the same binding numbers as the main compute layout, compute.wgsl declares both
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Net Bind Group Layout"),
+            entries: &[
+                // Time buffer
+                entry(0, wgpu::BufferBindingType::Uniform),
+                // Particle buffer
+                entry(1, wgpu::BufferBindingType::Storage { read_only: false }),
+                // Springs
+                entry(15, wgpu::BufferBindingType::Storage { read_only: true }),
+                // Net uniform
+                entry(16, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Net Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_compute_pipeline(device, &layout, module, "apply_springs");
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Net Buffer"),
+            size: std::mem::size_of::<NetUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let springs = create_springs(device, &[Spring::NONE]);
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            time_buffer,
+            particle_buffer,
+            &springs,
+            &uniform_buffer,
+        );
+
+        Self {
+            springs,
+            built_for: None,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            particle_count: 0,
+        }
+    }
+
+    /// Regenerates the springs if `config` changed and records the upload of the
+    /// settings for a step of `particle_count` particles into `encoder`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        uploads: &mut Uploads,
+        config: &NetConfig,
+        particle_count: u32,
+        time_buffer: &wgpu::Buffer,
+        particle_buffer: &wgpu::Buffer,
+    ) {
+        if self.built_for.as_ref() != Some(config) {
+            let springs = springs(config);
+            self.springs = create_springs(device, &springs);
+            self.built_for = Some(config.clone());
+            self.rebind(device, time_buffer, particle_buffer);
+        }
+
+        self.particle_count = config.particle_count().min(particle_count);
+        let uniform = NetUniform {
+            gravity: config.gravity,
+            damping: config.damping,
+            particle_count: self.particle_count,
+        };
+        uploads.write(device, encoder, &self.uniform_buffer, &uniform);
+    }
+
+    /// Records the spring forces, after `prepare`. Changes the pass's bind group, callers
+    /// have to set their own again afterwards.
+    pub fn record<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
+        if self.particle_count == 0 {
+            return;
+        }
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        dispatch::dispatch(
+            pass,
+            &self.pipeline,
+            DispatchConfig::Linear {
+                count: self.particle_count,
+            },
+        );
+    }
+
+    /// Points the bind group at a replaced particle buffer.
+    pub fn rebind(
+        &mut self,
+        device: &wgpu::Device,
+        time_buffer: &wgpu::Buffer,
+        particle_buffer: &wgpu::Buffer,
+    ) {
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            time_buffer,
+            particle_buffer,
+            &self.springs,
+            &self.uniform_buffer,
+        );
+    }
+}
+
+/// The spring slots of every particle of the net, particle `row * columns + column` at
+/// `slot` in `index * SPRINGS_PER_PARTICLE + slot`. Diagonal slots stay unused without
+/// `shear`.
+pub fn springs(config: &NetConfig) -> Vec<Spring> {
+    let count = config.particle_count();
+    let mut springs = vec![Spring::NONE; count.max(1) as usize * SPRINGS_PER_PARTICLE as usize];
+    let (columns, rows) = (config.columns as i64, config.rows as i64);
+
+    for index in 0..count as i64 {
+        let (column, row) = (index % columns, index / columns);
+        let straight = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+        let diagonal = [(1, 1), (-1, 1), (1, -1), (-1, -1)];
+        let neighbors = straight
+            .iter()
+            .map(|offset| (offset, config.spacing))
+            .chain(
+                diagonal
+                    .iter()
+                    .filter(|_| config.shear)
+                    .map(|offset| (offset, config.spacing * SQRT_2)),
+            );
+
+        let slots = &mut springs[index as usize * SPRINGS_PER_PARTICLE as usize..];
+        for (slot, (&(dx, dy), rest_length)) in slots.iter_mut().zip(neighbors) {
+            let (x, y) = (column + dx, row + dy);
+            if (0..columns).contains(&x) && (0..rows).contains(&y) {
+                *slot = Spring {
+                    other: (y * columns + x) as u32,
+                    rest_length,
+                    stiffness: config.stiffness,
+                    _padding: 0,
+                };
+            }
+        }
+    }
+    springs
+}
+
+/// Places the first particles on the nodes of the net, centered on the screen and at rest,
+/// pinning the top row if configured to.
+pub fn seed(config: &NetConfig, particles: &mut [Particle]) {
+    let columns = config.columns.max(1);
+    let width = (columns - 1) as f32 * config.spacing;
+    let height = config.rows.saturating_sub(1) as f32 * config.spacing;
+
+    for (index, particle) in particles
+        .iter_mut()
+        .take(config.particle_count() as usize)
+        .enumerate()
+    {
+        let (column, row) = (index as u32 % columns, index as u32 / columns);
+        *particle = Particle {
+            position: [
+                column as f32 * config.spacing - width / 2.0,
+                height / 2.0 - row as f32 * config.spacing,
+            ],
+            velocity: [0.0, 0.0],
+            acceleration: [0.0, 0.0],
+            pinned: (config.pin_top_row && row == 0) as u32,
+            // keeps the phase it was seeded with
+            phase: particle.phase,
+        };
+    }
+}
+
+fn create_springs(device: &wgpu::Device, springs: &[Spring]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Springs Buffer"),
+        contents: bytemuck::cast_slice(springs),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    time_buffer: &wgpu::Buffer,
+    particle_buffer: &wgpu::Buffer,
+    springs: &wgpu::Buffer,
+    uniform_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Net Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: time_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: particle_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 15,
+                resource: springs.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 16,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
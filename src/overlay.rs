@@ -0,0 +1,258 @@
+use winit::{event::WindowEvent, window::Window};
+
+use crate::{
+    config::{
+        Background, Falloff, GameConfiguration, MAX_INTENSITY, MAX_QUAD_SIZE, MIN_INTENSITY,
+        MIN_QUAD_SIZE,
+    },
+    types::Particle,
+};
+
+/// In-app egui overlay for tweaking parameters while the simulation runs.
+pub struct EguiState {
+    pub context: egui::Context,
+    pub winit_state: egui_winit::State,
+    pub renderer: egui_wgpu::Renderer,
+    pub visible: bool,
+}
+
+impl EguiState {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, window: &Window) -> Self {
+        let context = egui::Context::default();
+        let winit_state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            None,
+        );
+        let renderer = egui_wgpu::Renderer::new(device, format, None, 1);
+
+        Self {
+            context,
+            winit_state,
+            renderer,
+            visible: false,
+        }
+    }
+
+    /// Feeds a window event to egui, returns whether egui consumed it.
+    pub fn input(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        self.winit_state.on_window_event(window, event).consumed
+    }
+
+    /// Runs `ui` and records a pass drawing the result on top of `view`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        window: &Window,
+        size_in_pixels: [u32; 2],
+        ui: impl FnMut(&egui::Context),
+    ) {
+        let raw_input = self.winit_state.take_egui_input(window);
+        let full_output = self.context.run(raw_input, ui);
+        self.winit_state
+            .handle_platform_output(window, full_output.platform_output);
+
+        let paint_jobs = self
+            .context
+            .tessellate(full_output.shapes, full_output.pixels_per_point);
+        let screen_descriptor = egui_wgpu::ScreenDescriptor {
+            size_in_pixels,
+            pixels_per_point: full_output.pixels_per_point,
+        };
+
+        for (id, delta) in &full_output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let user_buffers =
+            self.renderer
+                .update_buffers(device, queue, encoder, &paint_jobs, &screen_descriptor);
+        queue.submit(user_buffers);
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Egui Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.renderer
+                .render(&mut render_pass, &paint_jobs, &screen_descriptor);
+        }
+
+        for id in &full_output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+/// Read-only runtime figures shown above the parameters.
+pub struct HudInfo {
+    pub quality_scale: f32,
+    pub active_particles: u32,
+    /// Current kinetic energy and the one the current command started with, while shown.
+    pub kinetic_energy: Option<(f32, f32)>,
+    /// Mean position of the particles from the latest statistics readback.
+    pub center_of_mass: Option<[f32; 2]>,
+    /// Index and fields of the particle selected with the middle mouse button.
+    pub selection: Option<(u32, Particle)>,
+}
+
+/// Picker for an sRGB config color. Only writes back on change, so editing through the
+/// 8-bit picker does not round colors that were left alone.
+fn color_button(ui: &mut egui::Ui, color: &mut [f32; 4]) {
+    let mut srgba = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+    if ui
+        .color_edit_button_srgba_unmultiplied(&mut srgba)
+        .changed()
+    {
+        *color = srgba.map(|channel| channel as f32 / 255.0);
+    }
+}
+
+/// Changes made in the overlay that need more than the new configuration values.
+#[derive(Default)]
+pub struct OverlayResponse {
+    /// The configuration should be saved as a preset called `preset_name`.
+    pub save_preset: bool,
+}
+
+/// Sliders for the live-tweakable parts of the configuration, plus saving them as a preset
+/// named `preset_name`.
+pub fn parameters_window(
+    context: &egui::Context,
+    config: &mut GameConfiguration,
+    preset_name: &mut String,
+    hud: &HudInfo,
+) -> OverlayResponse {
+    let mut response = OverlayResponse::default();
+
+    egui::Window::new("Parameters").show(context, |ui| {
+        ui.label(format!(
+            "{} / {} particles active",
+            hud.active_particles, config.num_particles
+        ));
+        if config.dynamic_quality.enabled {
+            ui.label(format!("quality scale {:.2}", hud.quality_scale));
+        }
+        ui.label(format!(
+            "{} substeps, time scale {:.2}",
+            config.substeps(),
+            config.time_scale()
+        ));
+        if let Some([x, y]) = hud.center_of_mass {
+            ui.label(format!("center of mass ({:.3}, {:.3})", x, y));
+        }
+        if let Some((index, particle)) = hud.selection {
+            let [x, y] = particle.position;
+            let [vx, vy] = particle.velocity;
+            let [ax, ay] = particle.acceleration;
+            ui.label(format!(
+                "particle {}{}",
+                index,
+                if particle.pinned != 0 { ", pinned" } else { "" }
+            ));
+            ui.label(format!("  position ({:.4}, {:.4})", x, y));
+            ui.label(format!("  velocity ({:.4}, {:.4})", vx, vy));
+            ui.label(format!("  acceleration ({:.4}, {:.4})", ax, ay));
+        }
+        if let Some((energy, baseline)) = hud.kinetic_energy {
+            let drift = if baseline > 0.0 {
+                format!("{:+.2}%", (energy / baseline - 1.0) * 100.0)
+            } else {
+                "n/a".to_string()
+            };
+            ui.label(format!(
+                "kinetic energy {:.4} ({} since start)",
+                energy, drift
+            ));
+        }
+        ui.separator();
+
+        ui.add(
+            egui::Slider::new(&mut config.quad_size, MIN_QUAD_SIZE..=MAX_QUAD_SIZE)
+                .logarithmic(true)
+                .text("quad size"),
+        );
+        ui.add(
+            egui::Slider::new(&mut config.intensity, MIN_INTENSITY..=MAX_INTENSITY)
+                .logarithmic(true)
+                .text("intensity"),
+        );
+        ui.add(
+            egui::Slider::new(&mut config.drag, 0.0..=0.1)
+                .logarithmic(true)
+                .text("drag"),
+        );
+        ui.add(
+            egui::Slider::new(&mut config.commands.roam.strength, 0.0..=10.0).text("roam strength"),
+        );
+        ui.add(
+            egui::Slider::new(&mut config.commands.roam.radius, 0.0..=3.0)
+                .text("roam radius (0 = unlimited)"),
+        );
+        ui.horizontal(|ui| {
+            ui.label("roam falloff");
+            let falloff = &mut config.commands.roam.falloff;
+            ui.selectable_value(falloff, Falloff::InverseSquare, "inverse square");
+            ui.selectable_value(falloff, Falloff::Linear, "linear");
+            ui.selectable_value(falloff, Falloff::Constant, "constant");
+            ui.selectable_value(falloff, Falloff::Gaussian, "gaussian");
+        });
+        ui.add(
+            egui::Slider::new(&mut config.commands.shuffle.amount, 0.0..=0.1)
+                .text("shuffle amount"),
+        );
+        ui.add(
+            egui::Slider::new(&mut config.commands.collapse.stiffness, 0.1..=500.0)
+                .logarithmic(true)
+                .text("collapse stiffness"),
+        );
+        ui.add(
+            egui::Slider::new(&mut config.commands.dust.noise_strength, 0.0..=10.0)
+                .text("dust noise strength"),
+        );
+        ui.add(
+            egui::Slider::new(&mut config.commands.dust.noise_scale, 0.1..=50.0)
+                .logarithmic(true)
+                .text("dust noise scale"),
+        );
+
+        ui.horizontal(|ui| {
+            ui.label("background");
+            match &mut config.background {
+                Background::Solid(color) => color_button(ui, color),
+                Background::VerticalGradient(first, second) | Background::Radial(first, second) => {
+                    color_button(ui, first);
+                    color_button(ui, second);
+                }
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("preset");
+            ui.text_edit_singleline(preset_name);
+            response.save_preset = ui.button("save").clicked();
+        });
+    });
+
+    response
+}
@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+
+use crate::{quality::DynamicQuality, types::GraphVertex};
+
+/// Size and distance from the bottom left corner of the window of the graph, in pixels.
+/// Every recorded frame gets a bar one pixel wide.
+const WIDTH: f32 = DynamicQuality::HISTORY_LEN as f32;
+const HEIGHT: f32 = 80.0;
+const MARGIN: f32 = 10.0;
+
+/// The budget line sits at this fraction of the height, slower frames rise above it.
+const BUDGET_HEIGHT: f32 = 0.5;
+
+/// Panel, one bar per frame and the budget line, two triangles each.
+const MAX_VERTICES: usize = (DynamicQuality::HISTORY_LEN + 2) * 6;
+
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const FAST_COLOR: [f32; 4] = [0.2, 0.8, 0.3, 0.9];
+const SLOW_COLOR: [f32; 4] = [0.9, 0.2, 0.2, 0.9];
+const BUDGET_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+
+/// Bar chart of the recent frame times with a line at the frame budget, drawn in a pass
+/// of its own over the finished frame. The bars are rebuilt from the history every frame.
+pub struct PerfGraph {
+    pub pipeline: wgpu::RenderPipeline,
+    pub vertex_buffer: wgpu::Buffer,
+}
+
+impl PerfGraph {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Perf Graph Vertex Buffer"),
+            size: (MAX_VERTICES * std::mem::size_of::<GraphVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Perf Graph Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("perf_graph.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Perf Graph Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Perf Graph Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<GraphVertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    // the panel is translucent, so the particles show through
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            vertex_buffer,
+        }
+    }
+
+    /// Draws the graph of `frame_times` against a budget of `target_fps` onto `view` of
+    /// `size` pixels, which keeps its contents.
+    pub fn draw(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: [u32; 2],
+        frame_times: &VecDeque<f32>,
+        target_fps: f32,
+    ) {
+        let vertices = build_vertices(size, frame_times, target_fps);
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Perf Graph Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
+
+/// Lays out the panel, bars and budget line in clip space, newest frame on the right.
+fn build_vertices(
+    size: [u32; 2],
+    frame_times: &VecDeque<f32>,
+    target_fps: f32,
+) -> Vec<GraphVertex> {
+    let mut vertices = Vec::with_capacity(MAX_VERTICES);
+    let [width, height] = size.map(|extent| extent.max(1) as f32);
+
+    // a rectangle given in pixels from the bottom left corner of the graph
+    let mut rect = |left: f32, bottom: f32, right: f32, top: f32, color| {
+        let x = |px: f32| (MARGIN + px) / width * 2.0 - 1.0;
+        let y = |px: f32| (MARGIN + px) / height * 2.0 - 1.0;
+        let corners = [
+            [x(left), y(bottom)],
+            [x(right), y(bottom)],
+            [x(right), y(top)],
+            [x(left), y(bottom)],
+            [x(right), y(top)],
+            [x(left), y(top)],
+        ];
+        vertices.extend(corners.map(|position| GraphVertex { position, color }));
+    };
+
+    rect(0.0, 0.0, WIDTH, HEIGHT, PANEL_COLOR);
+
+    // a target of 0 or less has no budget, scale to the slowest frame instead
+    let budget = if target_fps > 0.0 {
+        1.0 / target_fps
+    } else {
+        frame_times.iter().copied().fold(f32::EPSILON, f32::max) * BUDGET_HEIGHT
+    };
+    let pixels_per_second = BUDGET_HEIGHT * HEIGHT / budget;
+
+    let start = WIDTH - frame_times.len() as f32;
+    for (i, &frame_time) in frame_times.iter().enumerate() {
+        let left = start + i as f32;
+        let top = (frame_time * pixels_per_second).min(HEIGHT);
+        let color = if frame_time > budget {
+            SLOW_COLOR
+        } else {
+            FAST_COLOR
+        };
+        rect(left, 0.0, left + 1.0, top, color);
+    }
+
+    let line = BUDGET_HEIGHT * HEIGHT;
+    rect(0.0, line - 0.5, WIDTH, line + 0.5, BUDGET_COLOR);
+
+    vertices
+}
@@ -0,0 +1,44 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Directory named presets are stored in, relative to the working directory.
+pub const PRESET_DIR: &str = "presets";
+
+/// Path of the preset called `name`.
+pub fn preset_path(name: &str) -> PathBuf {
+    Path::new(PRESET_DIR).join(format!("{}.json", name))
+}
+
+/// Returns whether `name` can be used as a preset file name without escaping the preset
+/// directory.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Names of every saved preset, sorted. A missing preset directory means no presets.
+pub fn list_presets() -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(PRESET_DIR) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut names = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+            && let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+        {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// Settings of the automatic quality controller.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DynamicQualityConfig {
+    pub enabled: bool,
+    /// Frame rate the controller tries to hold.
+    pub target_fps: f32,
+    /// Smallest fraction of the particles that is kept simulated and drawn.
+    pub min_scale: f32,
+    /// Largest fraction of the particles that is simulated and drawn.
+    pub max_scale: f32,
+    /// Relative distance from the target the frame rate has to move before the scale is
+    /// changed, so the controller does not oscillate around the target.
+    pub hysteresis: f32,
+    /// Number of frames averaged before each decision.
+    pub window: usize,
+}
+
+impl Default for DynamicQualityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_fps: 60.0,
+            min_scale: 0.1,
+            max_scale: 1.0,
+            hysteresis: 0.15,
+            window: 30,
+        }
+    }
+}
+
+/// Scales the number of active particles down when frames take too long and back up when
+/// there is headroom.
+pub struct DynamicQuality {
+    /// Recent frame times in seconds, oldest first.
+    pub frame_times: VecDeque<f32>,
+    /// Fraction of the configured particles that is currently active.
+    pub scale: f32,
+    frames_since_change: usize,
+}
+
+impl DynamicQuality {
+    /// Frame times kept for display, independent of the decision window.
+    pub const HISTORY_LEN: usize = 240;
+
+    pub fn new(config: &DynamicQualityConfig) -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(Self::HISTORY_LEN),
+            scale: config.max_scale.clamp(0.0, 1.0),
+            frames_since_change: 0,
+        }
+    }
+
+    /// Records the duration of the last frame and adjusts the scale once enough frames since
+    /// the last adjustment have been seen.
+    pub fn record_frame(&mut self, frame_time: f32, config: &DynamicQualityConfig) {
+        if self.frame_times.len() == Self::HISTORY_LEN {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+
+        if !config.enabled {
+            return;
+        }
+
+        let window = config.window.clamp(1, Self::HISTORY_LEN);
+        if self.frames_since_change < window {
+            self.frames_since_change += 1;
+            return;
+        }
+
+        let average = self.frame_times.iter().rev().take(window).sum::<f32>() / window as f32;
+        let fps = 1.0 / average.max(f32::EPSILON);
+        let min_scale = config.min_scale.clamp(0.0, 1.0);
+        let max_scale = config.max_scale.clamp(min_scale, 1.0);
+
+        let scale = if fps < config.target_fps * (1.0 - config.hysteresis) {
+            (self.scale * 0.8).max(min_scale)
+        } else if fps > config.target_fps * (1.0 + config.hysteresis) {
+            (self.scale * 1.1).min(max_scale)
+        } else {
+            self.scale
+        };
+
+        if scale != self.scale {
+            debug!(
+                "{:.1} fps against a target of {:.1}, quality scale {:.2} -> {:.2}",
+                fps, config.target_fps, self.scale, scale
+            );
+            self.scale = scale;
+            // judge the new scale only by frames rendered with it
+            self.frames_since_change = 0;
+        }
+    }
+}
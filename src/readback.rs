@@ -0,0 +1,90 @@
+use std::sync::mpsc;
+
+/// A staging buffer for copying GPU buffers back to the CPU, kept between readbacks and
+/// only replaced when a readback needs more room than it has.
+///
+/// Every read submits its own command buffer and blocks until the GPU has finished all
+/// work submitted before it, so it is meant for tooling and diagnostics, not for every
+/// frame.
+#[derive(Default)]
+pub struct Staging {
+    buffer: Option<wgpu::Buffer>,
+}
+
+impl Staging {
+    /// Bytes held by the staging buffer, 0 before the first readback.
+    pub fn memory_size(&self) -> u64 {
+        self.buffer.as_ref().map_or(0, wgpu::Buffer::size)
+    }
+
+    /// Copies the first `size` bytes of `buffer` back and hands them to `read` while they
+    /// are mapped, returning what it returns. `buffer` needs `COPY_SRC` usage.
+    pub fn read_buffer<R>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        size: u64,
+        read: impl FnOnce(&[u8]) -> R,
+    ) -> R {
+        self.read_ranges(device, queue, buffer, &[(0, size)], read)
+    }
+
+    /// Like `read_buffer`, but hands over the `(offset, size)` ranges of `buffer` one after
+    /// the other. Offsets and sizes have to be multiples of 4.
+    pub fn read_ranges<R>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        ranges: &[(u64, u64)],
+        read: impl FnOnce(&[u8]) -> R,
+    ) -> R {
+        let total = ranges.iter().map(|&(_, size)| size).sum();
+        let staging_buffer = self.reserve(device, total);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        let mut staging_offset = 0;
+        for &(offset, size) in ranges {
+            encoder.copy_buffer_to_buffer(buffer, offset, staging_buffer, staging_offset, size);
+            staging_offset += size;
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        // only the part this readback wrote, the buffer may be larger
+        let slice = staging_buffer.slice(..total);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            // the receiver only goes away once we stopped waiting
+            let _ = sender.send(result);
+        });
+        // the map callback runs from inside poll once the copy is done
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("map callback dropped without running")
+            .expect("failed to map readback buffer");
+
+        // the mapped view has to be gone before unmapping
+        let result = read(&slice.get_mapped_range());
+        staging_buffer.unmap();
+        result
+    }
+
+    /// The staging buffer, replaced by a new one first if it holds fewer than `size` bytes.
+    fn reserve(&mut self, device: &wgpu::Device, size: u64) -> &wgpu::Buffer {
+        if self.memory_size() < size {
+            self.buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Readback Staging Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        self.buffer
+            .as_ref()
+            .expect("a staging buffer of at least size bytes exists")
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use winit::{dpi::PhysicalSize, event::MouseButton, keyboard::Key};
+
+/// An input event captured during a recording, stored one JSON object per line.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Simulation frame the event arrived before.
+    pub frame: u64,
+    /// Simulated time at that frame, in seconds, for reference.
+    pub time: f32,
+    pub kind: RecordedEventKind,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum RecordedEventKind {
+    /// Cursor position as a fraction of the window size, so replays work at any window size.
+    CursorMoved {
+        x: f64,
+        y: f64,
+    },
+    KeyPressed {
+        key: Key,
+    },
+    MouseInput {
+        button: MouseButton,
+        pressed: bool,
+    },
+}
+
+impl RecordedEventKind {
+    pub fn cursor_moved(
+        position: winit::dpi::PhysicalPosition<f64>,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        Self::CursorMoved {
+            x: position.x / size.width.max(1) as f64,
+            y: position.y / size.height.max(1) as f64,
+        }
+    }
+}
+
+/// First line of a recording, what a replay needs besides the events to start where the
+/// recorded run started.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    /// `seed` the recorded run seeded its particles with.
+    pub seed: u64,
+}
+
+/// Appends input events to a recording file as they happen.
+pub struct Recorder {
+    writer: BufWriter<fs::File>,
+}
+
+impl Recorder {
+    /// Starts a recording of a run whose particles are seeded with `seed`.
+    pub fn create(path: &Path, seed: u64) -> io::Result<Self> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer(&mut writer, &RecordingHeader { seed })?;
+        writer.write_all(b"\n")?;
+        Ok(Self { writer })
+    }
+
+    pub fn record(&mut self, event: &RecordedEvent) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Feeds recorded events back in frame order.
+pub struct Player {
+    header: Option<RecordingHeader>,
+    events: Vec<RecordedEvent>,
+    next: usize,
+}
+
+impl Player {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        let mut header = None;
+        let mut events = Vec::new();
+        for line in file.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // recordings made before the header existed start with an event
+            if header.is_none()
+                && events.is_empty()
+                && let Ok(parsed) = serde_json::from_str::<RecordingHeader>(&line)
+            {
+                header = Some(parsed);
+                continue;
+            }
+            events.push(serde_json::from_str::<RecordedEvent>(&line)?);
+        }
+        // events are recorded in order, but keep hand-edited files working
+        events.sort_by_key(|event| event.frame);
+
+        Ok(Self {
+            header,
+            events,
+            next: 0,
+        })
+    }
+
+    /// Seed the recorded run seeded its particles with, if the recording stores it.
+    pub fn seed(&self) -> Option<u64> {
+        self.header.as_ref().map(|header| header.seed)
+    }
+
+    /// Returns the events that should be applied before simulating `frame`.
+    pub fn due(&mut self, frame: u64) -> &[RecordedEvent] {
+        let start = self.next;
+        while self.next < self.events.len() && self.events[self.next].frame <= frame {
+            self.next += 1;
+        }
+        &self.events[start..self.next]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+}
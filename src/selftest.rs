@@ -0,0 +1,837 @@
+use std::{fmt, fs, sync::Arc};
+
+use crate::{
+    config::{GameConfiguration, srgb_to_linear},
+    dispatch::DispatchConfig,
+    flow_field,
+    gpu::Gpu,
+    state::{MAX_WORKGROUPS_PER_DIMENSION, State, WORKGROUP_SIZE},
+    types::Command,
+    types::Particle,
+};
+
+/// Particles simulated by every check, few enough to read back and verify on the CPU.
+pub const PARTICLES: u32 = 1024;
+
+/// Where the cursor rests during the checks.
+const CURSOR: [f32; 2] = [0.0, 0.0];
+
+/// Red, green and blue of a 2x2 flow field, bottom row first: the bottom row points
+/// right and the top row left.
+const FIELD: [[f32; 3]; 4] = [
+    [1.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+];
+
+/// Compares the particles before and after a check's steps, explaining what is wrong if
+/// the invariant does not hold.
+type Verify = fn(&[Particle], &[Particle], &GameConfiguration) -> Result<(), String>;
+
+/// A short simulation of one command plus the invariant its result has to satisfy.
+///
+/// Particles start at random positions, so the invariants only compare a run against its
+/// own starting point and leave generous margins for float differences between GPUs.
+struct Check {
+    name: &'static str,
+    command: Command,
+    steps: u32,
+    left_pressed: bool,
+    verify: Verify,
+}
+
+const CHECKS: &[Check] = &[
+    Check {
+        name: "roam pulls particles towards the cursor",
+        command: Command::Roam,
+        steps: 10,
+        left_pressed: false,
+        verify: |before, after, _| {
+            let (start, end) = (mean_distance(before), mean_distance(after));
+            if end < start {
+                Ok(())
+            } else {
+                Err(format!("mean distance went from {} to {}", start, end))
+            }
+        },
+    },
+    Check {
+        name: "shuffle moves particles",
+        command: Command::Shuffle,
+        steps: 1,
+        left_pressed: false,
+        verify: |before, after, _| {
+            let moved = moved_count(before, after, 0.0);
+            if moved > before.len() / 2 {
+                Ok(())
+            } else {
+                Err(format!(
+                    "only {} of {} particles moved",
+                    moved,
+                    before.len()
+                ))
+            }
+        },
+    },
+    Check {
+        name: "collapse converges onto the cursor",
+        command: Command::Collapse,
+        steps: 120,
+        left_pressed: false,
+        verify: |_, after, _| {
+            let end = mean_distance(after);
+            if end < 0.01 {
+                Ok(())
+            } else {
+                Err(format!("mean distance is still {}", end))
+            }
+        },
+    },
+    Check {
+        name: "dust moves particles",
+        command: Command::Dust,
+        steps: 10,
+        left_pressed: false,
+        verify: |before, after, _| {
+            let moved = moved_count(before, after, 0.0);
+            if moved > before.len() / 2 {
+                Ok(())
+            } else {
+                Err(format!(
+                    "only {} of {} particles moved",
+                    moved,
+                    before.len()
+                ))
+            }
+        },
+    },
+    Check {
+        name: "follow leaves particles alone while the cursor rests",
+        command: Command::Follow,
+        steps: 10,
+        left_pressed: false,
+        verify: |before, after, _| {
+            approx_eq_particles(before, after, 0.0, 1e-4).map_err(|mismatch| mismatch.to_string())
+        },
+    },
+    Check {
+        name: "chain pulls its head onto the cursor",
+        command: Command::Chain,
+        steps: 60,
+        left_pressed: false,
+        verify: |_, after, _| {
+            let head = distance(after[0].position, CURSOR);
+            if head < 0.01 {
+                Ok(())
+            } else {
+                Err(format!("head is {} from the cursor", head))
+            }
+        },
+    },
+    Check {
+        name: "grab holds particles under a resting cursor",
+        command: Command::Grab,
+        steps: 10,
+        left_pressed: true,
+        verify: |before, after, config| {
+            let radius = config.commands.grab.radius;
+            let (grabbed_before, grabbed_after): (Vec<Particle>, Vec<Particle>) = before
+                .iter()
+                .zip(after)
+                .filter(|(b, _)| distance(b.position, CURSOR) < 0.9 * radius)
+                .unzip();
+            approx_eq_particles(&grabbed_before, &grabbed_after, 0.0, 1e-4)
+                .map_err(|mismatch| format!("a grabbed particle drifted: {}", mismatch))
+        },
+    },
+    Check {
+        name: "emit respawns particles that fly too far",
+        command: Command::Emit,
+        steps: 10,
+        left_pressed: false,
+        verify: |_, after, config| {
+            // a particle may leave the range by one step's travel before it respawns
+            let limit = config.commands.emit.max_distance + 0.05;
+            let farthest = after
+                .iter()
+                .map(|p| distance(p.position, CURSOR))
+                .fold(0.0, f32::max);
+            if farthest <= limit {
+                Ok(())
+            } else {
+                Err(format!("a particle is {} from the cursor", farthest))
+            }
+        },
+    },
+    Check {
+        name: "life moves particles in crowded neighborhoods",
+        command: Command::Life,
+        steps: 10,
+        left_pressed: false,
+        verify: |before, after, _| {
+            let moved = moved_count(before, after, 0.0);
+            if moved > before.len() / 2 {
+                Ok(())
+            } else {
+                Err(format!(
+                    "only {} of {} particles moved",
+                    moved,
+                    before.len()
+                ))
+            }
+        },
+    },
+    Check {
+        name: "brownian moves resting particles",
+        command: Command::Brownian,
+        steps: 10,
+        left_pressed: false,
+        verify: |before, after, _| {
+            let moved = moved_count(before, after, 0.0);
+            if moved == before.len() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "only {} of {} particles moved",
+                    moved,
+                    before.len()
+                ))
+            }
+        },
+    },
+    Check {
+        name: "spiral places every particle on its slot",
+        command: Command::Spiral,
+        steps: 180,
+        left_pressed: false,
+        verify: |_, after, config| {
+            let scale = config.commands.spiral.scale;
+            let worst = after
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    let slot = scale * (i as f32 / after.len() as f32).sqrt();
+                    (distance(p.position, CURSOR) - slot).abs()
+                })
+                .fold(0.0, f32::max);
+            if worst < 0.01 {
+                Ok(())
+            } else {
+                Err(format!("a particle is {} off its slot radius", worst))
+            }
+        },
+    },
+];
+
+/// A check that sets up its own scenario instead of running one command, with its name.
+/// Checks that only test CPU code ignore the state.
+type Standalone = (&'static str, fn(&mut State) -> Result<(), String>);
+
+const STANDALONE_CHECKS: &[Standalone] = &[
+    (
+        "elapsed time advances and freezes while paused",
+        elapsed_time_advances,
+    ),
+    (
+        "particles round-trip through the readback",
+        readback_round_trips,
+    ),
+    (
+        "changing the particle count keeps the remaining particles",
+        particle_count_keeps_prefix,
+    ),
+    (
+        "resetting replaces every particle",
+        reset_replaces_particles,
+    ),
+    ("the command table is indexed by discriminant", |_| {
+        command_table_is_consistent()
+    }),
+    ("sRGB channels convert to linear light", |_| {
+        srgb_converts_to_linear()
+    }),
+    ("linear dispatches cover every index once", |_| {
+        linear_dispatch_covers_indices()
+    }),
+    (
+        "field flow accelerates particles along the field",
+        field_flow_follows_image,
+    ),
+    (
+        "PFM files are read in either byte order and rejected when unusable",
+        |_| pfm_parser_checks_files(),
+    ),
+    (
+        "accumulated acceleration builds up over the steps",
+        acceleration_accumulates,
+    ),
+];
+
+/// Configuration the checks run with. The defaults keep the results independent of the
+/// user's configuration file; particles start at rest, so only the commands move them.
+pub fn config() -> GameConfiguration {
+    let mut config = GameConfiguration {
+        num_particles: PARTICLES,
+        window_width: 256,
+        window_height: 256,
+        initial_velocity_bias: [0.0, 0.0],
+        initial_velocity_spread: 0.0,
+        ..Default::default()
+    };
+    config.dynamic_quality.enabled = false;
+    // closer than the initial spread, so respawning is actually exercised
+    config.commands.emit.max_distance = 0.5;
+    // wide enough that the few particles have more than max_neighbors each
+    config.commands.life.radius = 0.2;
+    config
+}
+
+/// Runs every check on a headless state of its own, created on `gpu` from `config`,
+/// printing PASS or FAIL per check. Returns whether all of them passed.
+pub fn run(gpu: &Arc<Gpu>, config: &GameConfiguration) -> bool {
+    let fresh_state = || {
+        let mut state = State::new_headless_shared(gpu.clone(), config.clone());
+        state.mouse_position = CURSOR;
+        state.previous_mouse_position = CURSOR;
+        state
+    };
+
+    let mut failed = 0;
+    for check in CHECKS {
+        let state = &mut fresh_state();
+        state.current_command = check.command;
+        state.left_pressed = check.left_pressed;
+        let before = state.read_particles();
+        state.step(check.steps);
+        let after = state.read_particles();
+
+        let result =
+            all_finite(&after).and_then(|()| (check.verify)(&before, &after, &state.game_config));
+        failed += !report(check.name, result) as usize;
+    }
+    for (name, check) in STANDALONE_CHECKS {
+        failed += !report(name, check(&mut fresh_state())) as usize;
+    }
+
+    let total = CHECKS.len() + STANDALONE_CHECKS.len();
+    println!("{} of {} checks passed", total - failed, total);
+    failed == 0
+}
+
+/// Prints PASS or FAIL for one check, returns whether it passed.
+fn report(name: &str, result: Result<(), String>) -> bool {
+    match &result {
+        Ok(()) => println!("PASS {}", name),
+        Err(reason) => println!("FAIL {}: {}", name, reason),
+    }
+    result.is_ok()
+}
+
+/// Distinct particles, some of them pinned, to recognize after a round trip.
+fn marked_particles(count: u32) -> Vec<Particle> {
+    (0..count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            Particle {
+                position: [t * 2.0 - 1.0, 1.0 - t],
+                velocity: [t, -t * 0.5],
+                acceleration: [-t, t * 3.0],
+                pinned: i % 3 / 2,
+                phase: 1.0 - t,
+            }
+        })
+        .collect()
+}
+
+/// Writes marked particles and reads them back twice through the reused staging buffer,
+/// expecting the exact values both times.
+fn readback_round_trips(state: &mut State) -> Result<(), String> {
+    state.recreate_particles();
+    let written = marked_particles(state.active_particles());
+    state.write_particle_range(0, &written);
+
+    for read in ["first", "second"] {
+        let particles = state.copy_particles_to_staging();
+        approx_eq_particles(&written, particles, 0.0, 0.0)
+            .map_err(|mismatch| format!("{} read: {}", read, mismatch))?;
+    }
+    Ok(())
+}
+
+/// Shrinks the particle count to half and grows it to twice the original, which
+/// reallocates the buffer. The first half has to come through unchanged, the rest has to
+/// be seeded with finite values.
+fn particle_count_keeps_prefix(state: &mut State) -> Result<(), String> {
+    state.recreate_particles();
+    let written = marked_particles(PARTICLES);
+    state.write_particle_range(0, &written);
+
+    state.set_particle_count(PARTICLES / 2);
+    state.set_particle_count(PARTICLES * 2);
+    let particles = state.read_particles();
+    state.set_particle_count(PARTICLES);
+
+    let kept = PARTICLES as usize / 2;
+    if particles.len() < PARTICLES as usize * 2 {
+        return Err(format!(
+            "{} particles read back after growing to {}",
+            particles.len(),
+            PARTICLES * 2
+        ));
+    }
+    approx_eq_particles(&written[..kept], &particles[..kept], 0.0, 0.0)
+        .map_err(|mismatch| format!("kept particles changed: {}", mismatch))?;
+    all_finite(&particles)
+}
+
+/// Resets after writing marked particles, expecting none of them to survive.
+fn reset_replaces_particles(state: &mut State) -> Result<(), String> {
+    let written = marked_particles(state.active_particles());
+    state.write_particle_range(0, &written);
+    state.recreate_particles();
+    let particles = state.read_particles();
+
+    all_finite(&particles)?;
+    let survivors = written
+        .iter()
+        .zip(&particles)
+        .filter(|(written, read)| written.position == read.position)
+        .count();
+    if survivors == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} particles kept their position", survivors))
+    }
+}
+
+/// Steps a few times, expecting `elapsed_time` to grow with every step, then runs paused
+/// updates, expecting it to stand still.
+fn elapsed_time_advances(state: &mut State) -> Result<(), String> {
+    let mut previous = state.elapsed_time;
+    for step in 0..5 {
+        state.step(1);
+        if state.elapsed_time <= previous {
+            return Err(format!(
+                "step {} went from {} to {}",
+                step, previous, state.elapsed_time
+            ));
+        }
+        previous = state.elapsed_time;
+    }
+
+    let paused = state.paused;
+    state.paused = true;
+    for _ in 0..3 {
+        state.update();
+    }
+    state.paused = paused;
+    if state.elapsed_time != previous {
+        return Err(format!(
+            "paused updates went from {} to {}",
+            previous, state.elapsed_time
+        ));
+    }
+    Ok(())
+}
+
+/// Expects `Command::ALL` to hold every command at the index of its discriminant, every
+/// name to lead back to its command and the placeholder `COMMAND_COUNT` of field.wgsl to
+/// match the generated one.
+fn command_table_is_consistent() -> Result<(), String> {
+    for (index, command) in Command::ALL.into_iter().enumerate() {
+        if command.as_u32() as usize != index {
+            return Err(format!(
+                "{:?} is at index {} but has discriminant {}",
+                command,
+                index,
+                command.as_u32()
+            ));
+        }
+        if Command::from_name(command.name()) != Some(command) {
+            return Err(format!(
+                "the name {:?} of {:?} does not lead back to it",
+                command.name(),
+                command
+            ));
+        }
+    }
+    if Command::COUNT as usize != Command::ALL.len() {
+        return Err(format!(
+            "COUNT is {} for {} commands",
+            Command::COUNT,
+            Command::ALL.len()
+        ));
+    }
+    let count = format!("const COMMAND_COUNT: u32 = {}u;", Command::COUNT);
+    if !include_str!("field.wgsl").contains(&count) {
+        return Err(format!("field.wgsl does not declare {}", count));
+    }
+    Ok(())
+}
+
+/// Compares `srgb_to_linear` with values of the sRGB transfer function: the endpoints, the
+/// linear segment near black and the curve above it.
+fn srgb_converts_to_linear() -> Result<(), String> {
+    let expected = [
+        (0.0, 0.0),
+        (0.02, 0.02 / 12.92),
+        (0.04045, 0.04045 / 12.92),
+        (0.5, 0.214_041),
+        (1.0, 1.0),
+    ];
+    for (srgb, linear) in expected {
+        let converted = srgb_to_linear(srgb);
+        if (converted - linear).abs() > 1e-5 {
+            return Err(format!(
+                "{} converted to {} instead of {}",
+                srgb, converted, linear
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Lays out linear dispatches around the workgroup size and past the point where they
+/// spread over a second dimension, flattening invocations the way `particle_index` of
+/// compute.wgsl does. Each row of the dispatch covers a contiguous range of indices, so
+/// the rows have to line up end to start from 0 and reach the count for every index below
+/// it to come up exactly once, without a whole row to spare.
+fn linear_dispatch_covers_indices() -> Result<(), String> {
+    let split = MAX_WORKGROUPS_PER_DIMENSION * WORKGROUP_SIZE;
+    let counts = [
+        1,
+        WORKGROUP_SIZE - 1,
+        WORKGROUP_SIZE,
+        WORKGROUP_SIZE + 1,
+        split,
+        split + 1,
+        2 * split + WORKGROUP_SIZE + 1,
+    ];
+    for count in counts {
+        let [x, y, z] = DispatchConfig::Linear { count }.workgroups();
+        if [x, y, z].iter().any(|&n| n > MAX_WORKGROUPS_PER_DIMENSION) {
+            return Err(format!("{} invocations dispatch {:?}", count, [x, y, z]));
+        }
+
+        let row = x * WORKGROUP_SIZE;
+        let mut covered = 0u64;
+        for row_y in 0..y * z {
+            // the first and one past the last index of the row
+            let (first, last) = (row_y as u64 * row as u64, (row_y + 1) as u64 * row as u64);
+            if first != covered {
+                return Err(format!(
+                    "row {} of {} invocations starts at {} instead of {} with {:?}",
+                    row_y,
+                    count,
+                    first,
+                    covered,
+                    [x, y, z]
+                ));
+            }
+            covered = last;
+        }
+        if covered < count as u64 {
+            return Err(format!(
+                "only {} of {} indices are covered with {:?}",
+                covered,
+                count,
+                [x, y, z]
+            ));
+        }
+        if covered - count as u64 >= row as u64 {
+            return Err(format!(
+                "{} invocations dispatch a whole unused row with {:?}",
+                count,
+                [x, y, z]
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Runs Roam from the same particles once with the acceleration rebuilt every step and
+/// once with it accumulating. Over `STEPS` steps of roughly the same pull the accumulated
+/// acceleration has to grow to several times the rebuilt one, and carry the particles
+/// further.
+fn acceleration_accumulates(state: &mut State) -> Result<(), String> {
+    const STEPS: u32 = 10;
+    state.current_command = Command::Roam;
+    state.left_pressed = false;
+    state.recreate_particles();
+    let before = state.read_particles();
+
+    let mut run = |accumulate: bool| {
+        state.game_config.accumulate_acceleration = accumulate;
+        state.write_particle_range(0, &before);
+        state.step(STEPS);
+        state.read_particles()
+    };
+    let (rebuilt, accumulated) = (run(false), run(true));
+    state.game_config.accumulate_acceleration = false;
+    all_finite(&rebuilt)?;
+    all_finite(&accumulated)?;
+
+    let total_acceleration = |particles: &[Particle]| -> f32 {
+        particles
+            .iter()
+            .map(|p| p.acceleration[0].hypot(p.acceleration[1]))
+            .sum()
+    };
+    let total_travel = |particles: &[Particle]| -> f32 {
+        before
+            .iter()
+            .zip(particles)
+            .map(|(b, a)| distance(a.position, b.position))
+            .sum()
+    };
+    let (rebuilt_acceleration, accumulated_acceleration) = (
+        total_acceleration(&rebuilt),
+        total_acceleration(&accumulated),
+    );
+    if accumulated_acceleration < 0.5 * STEPS as f32 * rebuilt_acceleration {
+        return Err(format!(
+            "the accumulated acceleration is {} against {} rebuilt every step",
+            accumulated_acceleration, rebuilt_acceleration
+        ));
+    }
+    let (rebuilt_travel, accumulated_travel) = (total_travel(&rebuilt), total_travel(&accumulated));
+    if accumulated_travel <= rebuilt_travel {
+        return Err(format!(
+            "the particles travelled {} accumulating and {} rebuilding",
+            accumulated_travel, rebuilt_travel
+        ));
+    }
+    Ok(())
+}
+
+/// Loads `FIELD` from a PFM file and runs FieldFlow on it, expecting the particles in the
+/// bottom rows to speed up to the right and the ones in the top rows to the left. The
+/// field is removed again afterwards, the file right after loading it.
+fn field_flow_follows_image(state: &mut State) -> Result<(), String> {
+    let path = std::env::temp_dir().join(format!("selftest-{}.pfm", std::process::id()));
+    fs::write(&path, pfm_bytes("PF", 2, 2, -1.0, &FIELD)).map_err(|err| err.to_string())?;
+    let loaded = state.set_field_texture(Some(path.clone()));
+    let _ = fs::remove_file(&path);
+    loaded.map_err(|err| err.to_string())?;
+
+    state.current_command = Command::FieldFlow;
+    state.left_pressed = false;
+    state.recreate_particles();
+    let before = state.read_particles();
+    state.step(10);
+    let after = state.read_particles();
+    state
+        .set_field_texture(None)
+        .map_err(|err| err.to_string())?;
+
+    all_finite(&after)?;
+    let wrong = before
+        .iter()
+        .zip(&after)
+        .filter(|(b, a)| {
+            // between the rows the field blends over, and next to the side walls the
+            // particles may have bounced off them
+            let direction = match b.position {
+                [x, _] if x.abs() > 0.9 => return false,
+                [_, y] if y < -0.6 => 1.0,
+                [_, y] if y > 0.6 => -1.0,
+                _ => return false,
+            };
+            a.velocity[0] * direction <= 0.0 || a.velocity[1].abs() > 0.1 * a.velocity[0].abs()
+        })
+        .count();
+    if wrong == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} particles did not follow the field", wrong))
+    }
+}
+
+/// Parses the same texels written little and big endian, then a grayscale file and a
+/// file that ends early, which both have to be rejected.
+fn pfm_parser_checks_files() -> Result<(), String> {
+    let texels = [[0.25, -2.0, 9.0], [1.5, 3.0, 9.0]];
+    for scale in [-1.0, 1.0] {
+        let image = flow_field::parse_pfm(&pfm_bytes("PF", 2, 1, scale, &texels))
+            .map_err(|err| format!("scale {}: {}", scale, err))?;
+        if image.texels != [[0.25, -2.0], [1.5, 3.0]] {
+            return Err(format!("scale {} read {:?}", scale, image.texels));
+        }
+    }
+    if flow_field::parse_pfm(&pfm_bytes("Pf", 2, 1, -1.0, &texels)).is_ok() {
+        return Err("a grayscale file was accepted".to_string());
+    }
+    let bytes = pfm_bytes("PF", 2, 1, -1.0, &texels);
+    if flow_field::parse_pfm(&bytes[..bytes.len() - 1]).is_ok() {
+        return Err("a truncated file was accepted".to_string());
+    }
+    Ok(())
+}
+
+/// A PFM file of `texels`, rows bottom to top, in the byte order the sign of `scale`
+/// selects: little endian below 0.
+fn pfm_bytes(kind: &str, width: u32, height: u32, scale: f32, texels: &[[f32; 3]]) -> Vec<u8> {
+    let mut bytes = format!("{}\n{} {}\n{}\n", kind, width, height, scale).into_bytes();
+    for value in texels.iter().flatten() {
+        if scale < 0.0 {
+            bytes.extend(value.to_le_bytes());
+        } else {
+            bytes.extend(value.to_be_bytes());
+        }
+    }
+    bytes
+}
+
+/// Explains how many particles have a NaN or infinite position or velocity, if any do.
+pub fn all_finite(particles: &[Particle]) -> Result<(), String> {
+    let non_finite = particles
+        .iter()
+        .filter(|p| !p.position.iter().chain(&p.velocity).all(|v| v.is_finite()))
+        .count();
+    if non_finite == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} particles are not finite", non_finite))
+    }
+}
+
+/// The worst difference `approx_eq_particles` found.
+#[derive(Debug)]
+pub enum Mismatch {
+    /// The sets do not even hold the same number of particles.
+    Count { expected: usize, actual: usize },
+    /// The component of `field` of particle `index` that is furthest out of tolerance,
+    /// `excess` times the allowed difference away from the expected value. Infinite when
+    /// no difference is allowed at all, as for NaN against a number or a pinned flag.
+    Field {
+        index: usize,
+        field: &'static str,
+        expected: [f32; 2],
+        actual: [f32; 2],
+        excess: f32,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Count { expected, actual } => {
+                write!(f, "expected {} particles, got {}", expected, actual)
+            }
+            Mismatch::Field {
+                index,
+                field,
+                expected,
+                actual,
+                excess,
+            } => write!(
+                f,
+                "particle {} has {} {:?} instead of {:?}, {:.1} times the tolerance off",
+                index, field, actual, expected, excess
+            ),
+        }
+    }
+}
+
+/// Compares particles read back from the GPU with the expected ones, allowing every
+/// component to differ by `abs + rel * |expected|`, so results stay comparable between
+/// GPUs that round differently. Position, velocity, acceleration and phase are checked
+/// separately and the pinned flags have to match exactly. NaN only matches NaN and an
+/// infinity only the same infinity. Reports the component that is furthest out of
+/// tolerance, relative to its own tolerance.
+pub fn approx_eq_particles(
+    expected: &[Particle],
+    actual: &[Particle],
+    rel: f32,
+    abs: f32,
+) -> Result<(), Mismatch> {
+    if expected.len() != actual.len() {
+        return Err(Mismatch::Count {
+            expected: expected.len(),
+            actual: actual.len(),
+        });
+    }
+
+    let mut worst: Option<Mismatch> = None;
+    let mut worst_excess = 1.0;
+    for (index, (e, a)) in expected.iter().zip(actual).enumerate() {
+        let pinned = |p: &Particle| [p.pinned as f32, 0.0];
+        let fields = [
+            ("position", e.position, a.position),
+            ("velocity", e.velocity, a.velocity),
+            ("acceleration", e.acceleration, a.acceleration),
+            ("pinned", pinned(e), pinned(a)),
+            ("phase", [e.phase, 0.0], [a.phase, 0.0]),
+        ];
+        for (field, expected, actual) in fields {
+            let tolerance = |value: f32| {
+                if field == "pinned" {
+                    0.0
+                } else {
+                    abs + rel * value.abs()
+                }
+            };
+            let excess = expected
+                .iter()
+                .zip(&actual)
+                .map(|(&e, &a)| component_excess(e, a, tolerance(e)))
+                .fold(0.0, f32::max);
+            if excess > worst_excess {
+                worst_excess = excess;
+                worst = Some(Mismatch::Field {
+                    index,
+                    field,
+                    expected,
+                    actual,
+                    excess,
+                });
+            }
+        }
+    }
+
+    match worst {
+        Some(mismatch) => Err(mismatch),
+        None => Ok(()),
+    }
+}
+
+/// How many times `tolerance` `actual` is away from `expected`: at most 1 within the
+/// tolerance, infinite for values that cannot match.
+fn component_excess(expected: f32, actual: f32, tolerance: f32) -> f32 {
+    if expected.is_nan() || actual.is_nan() {
+        return if expected.is_nan() && actual.is_nan() {
+            0.0
+        } else {
+            f32::INFINITY
+        };
+    }
+    if expected == actual {
+        // also covers equal infinities, whose difference would be NaN
+        return 0.0;
+    }
+    let difference = (expected - actual).abs();
+    if tolerance > 0.0 {
+        difference / tolerance
+    } else {
+        f32::INFINITY
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+fn mean_distance(particles: &[Particle]) -> f32 {
+    let sum: f32 = particles.iter().map(|p| distance(p.position, CURSOR)).sum();
+    sum / particles.len().max(1) as f32
+}
+
+/// Number of particles that moved by more than `threshold`.
+fn moved_count(before: &[Particle], after: &[Particle], threshold: f32) -> usize {
+    before
+        .iter()
+        .zip(after)
+        .filter(|(b, a)| distance(a.position, b.position) > threshold)
+        .count()
+}
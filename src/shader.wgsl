@@ -2,6 +2,9 @@ struct Particle {
     position: vec2<f32>,
     velocity: vec2<f32>,
     acceleration: vec2<f32>,
+    pinned: u32,
+    // random offset in [0, 1) of per-particle effects like the pulse
+    phase: f32,
 };
 
 
@@ -10,65 +13,287 @@ struct Resolution {
     height: f32,
 };
 
+// The particle storage and its accessors, generated for the particle layout of the build,
+// see layout.rs
+// $RUST_PARTICLES
 @group(0) @binding(1) var<storage, read> particles: array<Particle>;
+
+fn load_particle(index: u32) -> Particle {
+    return particles[index];
+}
+
+fn particle_position(index: u32) -> vec2<f32> {
+    return particles[index].position;
+}
+
+fn particle_velocity(index: u32) -> vec2<f32> {
+    return particles[index].velocity;
+}
+
+fn particle_pinned(index: u32) -> bool {
+    return particles[index].pinned != 0u;
+}
+// $RUST_PARTICLESEND
+
 @group(0) @binding(2) var<uniform> resolution: Resolution;
+@group(0) @binding(3) var<uniform> render_params: RenderParams;
+// the last trail_length positions of every particle, most recent first, see trails.rs
+@group(0) @binding(4) var<storage, read> trail_history: array<vec2<f32>>;
+// an RGBA tint per particle written by update_tints of compute.wgsl, see tint.rs
+@group(0) @binding(5) var<storage, read> tints: array<vec4<f32>>;
+
+struct RenderParams {
+    velocity_vector_scale: f32,
+    // positions per particle in trail_history, 0 while trails are off
+    trail_length: u32,
+    // multiplier of every particle's color
+    intensity: f32,
+    // nonzero to color particle i by band i % color_bands instead of by velocity
+    color_bands: u32,
+    // tint of the active command, alpha is how strongly it applies
+    command_color: vec4<f32>,
+    // particle selected for inspection, drawn larger and in yellow, or no index at all
+    selected_particle: u32,
+    // radians the view is turned by counterclockwise around the screen center
+    view_angle: f32,
+    // radius of the particle splats in pixels, 0 while particles are drawn as quads
+    splat_radius: f32,
+    // swing of the particle size and brightness, 0 while pulsing is off
+    pulse_amount: f32,
+    // position in the shared pulse cycle, every particle is offset by its own phase
+    pulse_phase: f32,
+    // half the side of a particle quad in clip space units
+    quad_size: f32,
+    // nonzero while tints holds a tint for every particle
+    tinted: u32,
+    // sides of the polygon every particle is drawn as, below 3 for a quad
+    segments: u32,
+};
+
+// how much larger the selected particle's quad is
+const SELECTED_SCALE: f32 = 4.0;
+
+const PI: f32 = 3.14159265;
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    // opacity of trail segments, unused by the other passes
+    @location(1) fade: f32,
+    // position within the particle's quad, from -1 to 1 on both axes, for the splats
+    @location(2) local: vec2<f32>,
 };
 
-// Define the size of our quads
-// $RUST_REPLACEME
-const QUAD_SIZE: f32 = 0.001;
-// $RUST_REPLACEMEEND
-
 @vertex
 fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
-    // Determine which particle this vertex belongs to
-    let particle_index = vertex_index / 6u;
-    let particle = particles[particle_index];
-    
-    // Determine which vertex of the quad we're drawing
-    let vertex_in_quad = vertex_index % 6u;
-    
-    // Define offsets for each vertex of the quad
+    // Every particle owns vertices_per_particle() consecutive vertices
+    let vertices = vertices_per_particle();
+    return particle_vertex(vertex_index / vertices, vertex_index % vertices);
+}
+
+// Instanced variant, drawn with vertices_per_particle() vertices per instance and one
+// instance per particle
+@vertex
+fn vs_main_instanced(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    return particle_vertex(instance_index, vertex_index);
+}
+
+// Vertices every particle is drawn with, 6 for a quad or 3 for each side of its polygon
+fn vertices_per_particle() -> u32 {
+    if render_params.segments >= 3u {
+        return render_params.segments * 3u;
+    }
+    return 6u;
+}
+
+// Position of one of a particle's vertices around its center, within [-1, 1] on both axes.
+// Polygons are a fan of one triangle per side, with their corners on the unit circle.
+fn particle_corner(vertex: u32) -> vec2<f32> {
+    if render_params.segments >= 3u {
+        let side = vertex / 3u;
+        let corner = vertex % 3u;
+        if corner == 0u {
+            return vec2<f32>(0.0, 0.0);
+        }
+        let angle = 2.0 * PI * f32(side + corner - 1u) / f32(render_params.segments);
+        return vec2<f32>(cos(angle), sin(angle));
+    }
+
+    // Define the corners of the quad
     // We need 6 vertices to form 2 triangles:
     // 0, 1, 2 for first triangle and 2, 3, 0 for second triangle
     // (or any similar arrangement)
-    var offset = vec2<f32>(0.0, 0.0);
-
-    switch vertex_in_quad {
-        case 0u: { offset = vec2<f32>(-QUAD_SIZE, -QUAD_SIZE); } // Bottom-left
-        case 1u: { offset = vec2<f32>(QUAD_SIZE, -QUAD_SIZE); }  // Bottom-right
-        case 2u: { offset = vec2<f32>(QUAD_SIZE, QUAD_SIZE); }   // Top-right
-        case 3u: { offset = vec2<f32>(QUAD_SIZE, QUAD_SIZE); }   // Top-right (duplicate)
-        case 4u: { offset = vec2<f32>(-QUAD_SIZE, QUAD_SIZE); }  // Top-left
-        case 5u: { offset = vec2<f32>(-QUAD_SIZE, -QUAD_SIZE); } // Bottom-left (duplicate)
-        default: { offset = vec2<f32>(0.0, 0.0); }
+    var corner = vec2<f32>(0.0, 0.0);
+
+    switch vertex {
+        case 0u: { corner = vec2<f32>(-1.0, -1.0); } // Bottom-left
+        case 1u: { corner = vec2<f32>(1.0, -1.0); }  // Bottom-right
+        case 2u: { corner = vec2<f32>(1.0, 1.0); }   // Top-right
+        case 3u: { corner = vec2<f32>(1.0, 1.0); }   // Top-right (duplicate)
+        case 4u: { corner = vec2<f32>(-1.0, 1.0); }  // Top-left
+        case 5u: { corner = vec2<f32>(-1.0, -1.0); } // Bottom-left (duplicate)
+        default: { corner = vec2<f32>(0.0, 0.0); }
     }
+    return corner;
+}
+
+fn particle_vertex(particle_index: u32, vertex: u32) -> VertexOutput {
+    let particle = load_particle(particle_index);
+    let corner = particle_corner(vertex);
 
-    // Calculate the aspect ratio using the resolution uniform
-    let aspect_ratio = resolution.height / resolution.width;
-    // Scale x-offset based on the aspect ratio
-    offset.x = offset.x * aspect_ratio;
+    var offset = corner * render_params.quad_size;
+    if render_params.splat_radius > 0.0 {
+        // splats cover the same number of pixels at any window size
+        offset = corner * render_params.splat_radius * 2.0 / vec2<f32>(resolution.width, resolution.height);
+    } else {
+        // Calculate the aspect ratio using the resolution uniform
+        let aspect_ratio = resolution.height / resolution.width;
+        // Scale x-offset based on the aspect ratio
+        offset.x = offset.x * aspect_ratio;
+    }
+
+    if particle_index == render_params.selected_particle {
+        offset *= SELECTED_SCALE;
+    }
+    let pulse = particle_pulse(particle);
+    offset *= pulse;
 
     var output: VertexOutput;
     // Add offset to particle position to form the quad
-    output.position = vec4<f32>(particle.position + offset, 0.0, 1.0);
+    output.position = vec4<f32>(view(particle.position) + offset, particle_depth(particle), 1.0);
     
-    // Color based on velocity (red/blue for horizontal, green for vertical)
-    let speed = length(particle.velocity);
-    output.color = vec3<f32>(
-        0.5 + particle.velocity.x,
-        0.5 + particle.velocity.y,
-        1.0 - speed
-    );
+    output.color = particle_color(particle_index, particle) * pulse;
+    output.local = corner;
 
     return output;
 }
 
+// Turns a position by view_angle around the screen center. The rotation happens in pixel
+// proportions, so shapes keep their look on windows that are not square.
+fn view(position: vec2<f32>) -> vec2<f32> {
+    let aspect_ratio = resolution.width / resolution.height;
+    let square = vec2<f32>(position.x * aspect_ratio, position.y);
+    let c = cos(render_params.view_angle);
+    let s = sin(render_params.view_angle);
+    let turned = vec2<f32>(c * square.x - s * square.y, s * square.x + c * square.y);
+    return vec2<f32>(turned.x / aspect_ratio, turned.y);
+}
+
+// Factor of the particle's size and brightness, swinging around 1 by pulse_amount. Exactly
+// 1 while pulsing is off.
+fn particle_pulse(particle: Particle) -> f32 {
+    return 1.0 + render_params.pulse_amount * sin(2.0 * PI * (render_params.pulse_phase + particle.phase));
+}
+
+// Depth key, only tested when particles are depth sorted: faster particles are nearer and
+// end up on top. Stays inside (0, 1], so nothing is clipped.
+fn particle_depth(particle: Particle) -> f32 {
+    return 1.0 / (1.0 + length(particle.velocity));
+}
+
+fn hue_to_rgb(hue: f32) -> vec3<f32> {
+    let h = fract(hue) * 6.0;
+    return clamp(vec3<f32>(abs(h - 3.0) - 1.0, 2.0 - abs(h - 2.0), 2.0 - abs(h - 4.0)), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Color based on velocity (red/blue for horizontal, green for vertical), or the hue of
+// the particle's band, spread evenly around the color wheel, blended towards its tint.
+// Pinned particles are white, the selected one is yellow.
+fn particle_color(index: u32, particle: Particle) -> vec3<f32> {
+    if index == render_params.selected_particle {
+        return vec3<f32>(1.0, 1.0, 0.0);
+    }
+    if particle.pinned != 0u {
+        return vec3<f32>(1.0, 1.0, 1.0);
+    }
+    var color: vec3<f32>;
+    if render_params.color_bands != 0u {
+        let bands = render_params.color_bands;
+        color = hue_to_rgb(f32(index % bands) / f32(bands));
+    } else {
+        let speed = length(particle.velocity);
+        color = vec3<f32>(
+            0.5 + particle.velocity.x,
+            0.5 + particle.velocity.y,
+            1.0 - speed
+        );
+    }
+    if render_params.tinted != 0u && index < arrayLength(&tints) {
+        let tint = tints[index];
+        color = mix(color, tint.rgb, clamp(tint.a, 0.0, 1.0));
+    }
+    return color;
+}
+
+// Draws a line from each particle along its velocity, scaled by velocity_vector_scale
+@vertex
+fn vs_velocity(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let particle_index = vertex_index / 2u;
+    let particle = load_particle(particle_index);
+
+    var position = particle.position;
+    if vertex_index % 2u == 1u {
+        position += particle.velocity * render_params.velocity_vector_scale;
+    }
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(view(position), particle_depth(particle), 1.0);
+    output.color = particle_color(particle_index, particle);
+    return output;
+}
+
+// Draws a line from each particle through its recorded positions, as trail_length
+// segments per particle fading out towards the oldest position
+@vertex
+fn vs_trail(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let length = render_params.trail_length;
+    let particle_index = vertex_index / (2u * length);
+    let particle = load_particle(particle_index);
+
+    // point 0 is the particle itself, point k the k-th recorded position
+    let point = (vertex_index % (2u * length) + 1u) / 2u;
+    var position = particle.position;
+    if point > 0u {
+        position = trail_history[particle_index * length + point - 1u];
+    }
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(view(position), particle_depth(particle), 1.0);
+    output.color = particle_color(particle_index, particle);
+    output.fade = 1.0 - f32(point) / f32(length);
+    return output;
+}
+
+// The color of the particle tinted by the active command and scaled by the intensity
+fn shade(color: vec3<f32>) -> vec3<f32> {
+    let tint = mix(vec3<f32>(1.0), render_params.command_color.rgb, render_params.command_color.a);
+    return color * tint * render_params.intensity;
+}
+
+@fragment
+fn fs_trail(input: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(shade(input.color), input.fade);
+}
+
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(input.color, 1.0);
+    return vec4<f32>(shade(input.color), 1.0);
+}
+
+// Gaussian splat for smooth_render, blended additively into the HDR target. The weights
+// are normalized by the Gaussian's area in pixels, so a particle adds about one pixel's
+// worth of color whatever the splat radius and the brightness follows the density.
+@fragment
+fn fs_splat(input: VertexOutput) -> @location(0) vec4<f32> {
+    let distance_sq = dot(input.local, input.local);
+    if distance_sq > 1.0 {
+        discard;
+    }
+    // a standard deviation of half the radius
+    let sigma = 0.5 * render_params.splat_radius;
+    let weight = exp(-2.0 * distance_sq) / (2.0 * PI * sigma * sigma);
+    return vec4<f32>(shade(input.color) * weight, 1.0);
 }
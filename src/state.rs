@@ -1,122 +1,375 @@
-use std::time::Instant;
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use rand::Rng;
+use bytemuck::Zeroable;
+use log::{debug, info, trace, warn};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use wgpu::util::DeviceExt;
 use winit::{
-    event::{DeviceId, KeyEvent, WindowEvent},
+    event::{DeviceId, ElementState, KeyEvent, MouseButton, WindowEvent},
     keyboard::{Key, NamedKey},
     window::Window,
 };
 
 use crate::{
-    GameConfiguration,
-    types::{Command, CommandUniform, MouseUniform, Particle, ResolutionUniform, TimeUniform},
+    background::BackgroundPass,
+    canvas::{CANVAS_FORMAT, Canvas},
+    config::{
+        GameConfiguration, MAX_INTENSITY, MAX_QUAD_SIZE, MAX_SUBSTEPS, MAX_TIME_SCALE,
+        MIN_INTENSITY, MIN_QUAD_SIZE, MIN_TIME_SCALE,
+    },
+    control::ControlRequest,
+    cursor::CursorPass,
+    depth::{DEPTH_FORMAT, DepthTarget},
+    dispatch::{self, DispatchConfig, TILE_SIZE},
+    field::FieldView,
+    flow_field,
+    gpu::Gpu,
+    grid::{GRID_KERNELS, GridDispatch, SpatialGrid},
+    grid_view::GridView,
+    hdr::{HDR_FORMAT, HdrTarget},
+    import,
+    layout::ParticleLayout,
+    memory::{MemoryReport, texture_bytes},
+    net::{self, SpringNet},
+    overlay::{self, EguiState, HudInfo, OverlayResponse},
+    perf_graph::PerfGraph,
+    preset,
+    quality::DynamicQuality,
+    readback,
+    stats::ParticleStats,
+    supersample::{self, Supersample},
+    timeline::{self, Timeline},
+    tint::Tints,
+    trails::Trails,
+    types::{
+        Command, CommandUniform, GridUniform, MouseUniform, NO_SELECTION, Particle,
+        RenderParamsUniform, ResolutionUniform, SimulationUniform, StatsData, TimeUniform,
+    },
+    upload::Uploads,
 };
 
+/// Entry points of compute.wgsl that can be selected at runtime, the first one is active
+/// on startup.
+pub const COMPUTE_KERNELS: &[&str] = &["update_particles", "integrate_particles"];
+
+/// Invocations per compute workgroup, shared with compute.wgsl.
+pub const WORKGROUP_SIZE: u32 = 1024;
+
+/// Most workgroups a single dispatch dimension may have.
+pub const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+
+/// Seconds over which the cursor velocity is averaged for flinging grabbed particles.
+const FLING_SMOOTHING: f32 = 0.05;
+
+/// Time within which the quit key has to be pressed again when `confirm_quit` is set.
+const QUIT_CONFIRM_WINDOW: Duration = Duration::from_secs(2);
+
+/// Factor the exposure and the intensity change by per key press, a quarter stop.
+const EXPOSURE_STEP: f32 = 1.189_207_1;
+
+/// Factor the quad size changes by per key press.
+const QUAD_SIZE_STEP: f32 = 1.25;
+
+/// How often the overlay reads the selected particle back again.
+const SELECTION_REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A particle picked for inspection and its fields as last read back.
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    pub index: u32,
+    pub particle: Particle,
+    read_at: Instant,
+}
+
+/// Largest storage buffer binding requested from the device, in bytes.
+pub const MAX_STORAGE_BUFFER_BINDING_SIZE: u32 = 2 << 30;
+
 pub struct State<'a> {
-    pub surface: wgpu::Surface<'a>,
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
+    /// None for headless states, see `new_headless`.
+    pub surface: Option<wgpu::Surface<'a>>,
+    /// Device and queue, shared with the states of other windows.
+    pub gpu: Arc<Gpu>,
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
     pub render_pipeline: wgpu::RenderPipeline,
-    pub compute_pipeline: wgpu::ComputePipeline,
+    pub render_pipeline_layout: wgpu::PipelineLayout,
+    pub velocity_pipeline: wgpu::RenderPipeline,
+    pub show_velocity_vectors: bool,
+    pub trails: Trails,
+    /// Springs of the net, which run before the update.
+    pub net: SpringNet,
+    /// Per-particle colors, which update after the particles moved.
+    pub tints: Tints,
+    pub trail_pipeline: wgpu::RenderPipeline,
+    pub background: BackgroundPass,
+    pub cursor: CursorPass,
+    pub field_view: FieldView,
+    pub show_field: bool,
+    pub grid_view: GridView,
+    pub show_grid: bool,
+    pub perf_graph: PerfGraph,
+    pub show_perf_graph: bool,
+    /// How the particle quads are rasterized, filled or as wireframe or corner points.
+    pub polygon_mode: wgpu::PolygonMode,
+    pub compute_pipelines: Vec<wgpu::ComputePipeline>,
+    pub active_compute: usize,
     pub particle_buffer: wgpu::Buffer,
     pub time_buffer: wgpu::Buffer,
     pub mouse_buffer: wgpu::Buffer,
     pub resolution_buffer: wgpu::Buffer,
+    pub render_params_buffer: wgpu::Buffer,
     pub command_buffer: wgpu::Buffer,
+    pub simulation_buffer: wgpu::Buffer,
+    pub grid: SpatialGrid,
+    /// Kernels rebuilding the grid, matching `GRID_KERNELS`.
+    pub grid_pipelines: Vec<wgpu::ComputePipeline>,
+    /// Particle positions at the start of the step, read by Chain instead of the particle
+    /// buffer that is being updated. Only allocated at full size while Chain is active.
+    pub chain_positions: wgpu::Buffer,
+    /// Low halves of the high precision positions, only allocated at full size while
+    /// `high_precision` is on.
+    pub position_low: wgpu::Buffer,
+    /// Vectors FieldFlow steers by, from `field_texture`.
+    pub flow_field: wgpu::Texture,
+    pub flow_field_view: wgpu::TextureView,
+    pub snapshot_positions_pipeline: wgpu::ComputePipeline,
+    pub stats: ParticleStats,
+    /// Uniforms of the simulation steps, recorded between their passes.
+    uploads: Uploads,
+    pub clear_stats_pipeline: wgpu::ComputePipeline,
+    pub reduce_stats_pipeline: wgpu::ComputePipeline,
+    /// Kinetic energy when the current command and particle count were first measured, see
+    /// `update_energy_baseline`.
+    pub energy_baseline: Option<(Command, u32, f32)>,
+    /// Time of the last user input, for idle detection.
+    pub last_input: Instant,
+    pub compute_bind_group_layout: wgpu::BindGroupLayout,
     pub compute_bind_group: wgpu::BindGroup,
+    pub render_bind_group_layout: wgpu::BindGroupLayout,
     pub render_bind_group: wgpu::BindGroup,
+    pub hdr_target: Option<HdrTarget>,
+    /// Allocated the first time particles are drawn depth sorted.
+    pub depth_target: Option<DepthTarget>,
+    /// What `render_offscreen` draws into, created by its first call.
+    offscreen: Option<wgpu::Texture>,
+    /// Target of the trails and particles while `supersample` is above 1, created on
+    /// demand at the size the factor and the window call for.
+    pub supersample: Option<Supersample>,
+    /// Allocated the first time `canvas_mode` paints, kept while it is off.
+    pub canvas: Option<Canvas>,
+    /// The F1 overlay, none for headless states.
+    pub egui: Option<EguiState>,
+    pub quality: DynamicQuality,
     pub last_update: Instant,
+    /// Frame the particle statistics were last reduced in.
+    last_stats_frame: u64,
+    /// Number of simulated frames so far, each of `substeps` steps.
+    pub frame: u64,
+    /// Number of simulation steps run so far, every substep counting, which seeds the
+    /// randomness of each step.
+    steps: u64,
+    /// Simulated seconds since the start, the sum of every step's `delta_time`. Stands
+    /// still while paused and follows `time_scale`.
+    pub elapsed_time: f64,
+    /// Step by `game_config.fixed_timestep` instead of wall-clock time, for reproducible runs.
+    pub use_fixed_timestep: bool,
+    /// Skip simulation updates, rendering continues.
+    pub paused: bool,
+    /// The window was resized to zero, which is how minimizing shows up. Nothing is
+    /// simulated or drawn until a real size arrives.
+    pub minimized: bool,
+    /// Run exactly one update while paused.
+    pub step_once: bool,
     pub mouse_position: [f32; 2],
+    /// Cursor position at the previous simulation step, in simulation space, to derive its
+    /// velocity.
+    pub previous_mouse_position: [f32; 2],
+    /// Cursor velocity smoothed over about `FLING_SMOOTHING` seconds.
+    pub fling_velocity: [f32; 2],
+    pub left_pressed: bool,
+    /// Fixed second attractor placed with the right mouse button.
+    pub secondary_attractor: Option<[f32; 2]>,
+    /// Particle picked with the middle mouse button, highlighted and shown in the overlay.
+    pub selection: Option<Selection>,
+    /// Staging buffer of the particle readbacks, reused from one to the next.
+    staging: readback::Staging,
+    /// The particles of the latest `copy_particles_to_staging`.
+    read_back_particles: Vec<Particle>,
+    /// Radians the drawn particles are turned by, advanced while `auto_rotate` is on and
+    /// kept when it is turned off.
+    pub view_angle: f32,
     pub current_resolution: ResolutionUniform,
     pub current_command: Command,
+    /// Names of the saved presets, cycled with Page Up / Page Down.
+    pub presets: Vec<String>,
+    pub current_preset: Option<usize>,
+    /// Name typed into the overlay for the next saved preset.
+    pub preset_name: String,
     pub game_config: GameConfiguration,
+    /// Scripted command switches, applied as simulated time passes.
+    pub timeline: Option<Timeline>,
+    /// Set by the W key, the event loop opens a second window sharing this device.
+    pub second_window_requested: bool,
+    /// Set by the quit key, the event loop then exits as if the window was closed.
+    pub quit_requested: bool,
+    /// When the quit key was first pressed, while `confirm_quit` waits for the second press.
+    quit_pressed_at: Option<Instant>,
+    /// Simulated seconds since a key last switched the command, for `command_cooldown_ms`.
+    /// Counts simulated time like `auto_cycle_since_switch`, so replays accept the same
+    /// switches as the recorded run.
+    command_since_switch: f32,
+    /// Simulated seconds since the last input and since `auto_cycle` last switched.
+    auto_cycle_since_input: f32,
+    auto_cycle_since_switch: f32,
+    /// Particles read from `initial_particles`, which resets start from again.
+    imported_particles: Option<Vec<Particle>>,
+    /// Draws every seeded particle, from `seed` if configured, so the same seed seeds the
+    /// same particles.
+    rng: StdRng,
 }
 
 impl<'a> State<'a> {
-    pub async fn new(window: &'a winit::window::Window, game_config: GameConfiguration) -> Self {
-        let size = window.inner_size();
-
-        // The instance is a handle to our GPU
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            ..Default::default()
-        });
-
+    /// Opens a device for `window` and creates the state rendering to it.
+    pub async fn new(window: &'a Window, game_config: GameConfiguration) -> Self {
+        let instance = Gpu::create_instance();
         // Create a surface from the window
         let surface = instance.create_surface(window).unwrap();
+        let gpu = Arc::new(Gpu::new(instance, Some(&surface), game_config.adapter()).await);
+        Self::with_surface(gpu, Some(surface), Some(window), game_config)
+    }
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::VERTEX_WRITABLE_STORAGE,
-                    required_limits: wgpu::Limits {
-                        max_storage_buffer_binding_size: 2 << 30,
-                        ..adapter.limits()
-                    },
-                    label: None,
-                },
-                None,
-            )
-            .await
-            .unwrap();
+    /// Opens a device and creates a state without a window or surface, for tests and
+    /// tooling that only simulate and read the particles back. It has no overlay and
+    /// `render` draws nothing; the pipelines are still built, for an sRGB target of the
+    /// configured window size, which `render_offscreen` draws into.
+    pub async fn new_headless(game_config: GameConfiguration) -> State<'static> {
+        let instance = Gpu::create_instance();
+        let gpu = Arc::new(Gpu::new(instance, None, game_config.adapter()).await);
+        State::new_headless_shared(gpu, game_config)
+    }
+
+    /// Creates a headless state like `new_headless`, with the device of an existing one.
+    pub fn new_headless_shared(gpu: Arc<Gpu>, game_config: GameConfiguration) -> State<'static> {
+        State::with_surface(gpu, None, None, game_config)
+    }
+
+    /// Creates a state for another window, rendering with the device of an existing one.
+    /// The simulations are independent, only the device is shared.
+    pub fn new_shared(
+        gpu: Arc<Gpu>,
+        window: Arc<Window>,
+        game_config: GameConfiguration,
+    ) -> State<'static> {
+        let surface = gpu.instance.create_surface(window.clone()).unwrap();
+        State::with_surface(gpu, Some(surface), Some(&window), game_config)
+    }
 
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps
+    /// Creates the state rendering to `surface` of `window`, or a headless one without
+    /// either.
+    fn with_surface(
+        gpu: Arc<Gpu>,
+        surface: Option<wgpu::Surface<'a>>,
+        window: Option<&Window>,
+        mut game_config: GameConfiguration,
+    ) -> Self {
+        let size = window.map_or(
+            winit::dpi::PhysicalSize::new(game_config.window_width, game_config.window_height),
+            Window::inner_size,
+        );
+        let adapter = &gpu.adapter;
+        let device = &gpu.device;
+
+        let surface_caps = surface.as_ref().map_or_else(
+            || wgpu::SurfaceCapabilities {
+                formats: vec![wgpu::TextureFormat::Rgba8UnormSrgb],
+                present_modes: vec![wgpu::PresentMode::Fifo],
+                alpha_modes: vec![wgpu::CompositeAlphaMode::Opaque],
+                usages: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            },
+            |surface| surface.get_capabilities(adapter),
+        );
+        let srgb_format = surface_caps
             .formats
             .iter()
             .copied()
             .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
+            .unwrap_or_else(|| {
+                warn!(
+                    "surface offers no sRGB format, falling back to {:?}; colors will look darker",
+                    surface_caps.formats[0]
+                );
+                surface_caps.formats[0]
+            });
+
+        // splats are accumulated in the HDR target as well
+        let wants_hdr = game_config.hdr || game_config.smooth_render;
+        let hdr = wants_hdr && HdrTarget::is_supported(adapter);
+        if wants_hdr && !hdr {
+            warn!("adapter cannot render to {:?}, disabling HDR", HDR_FORMAT);
+        }
+
+        let surface_format = if hdr {
+            HdrTarget::surface_format(&surface_caps, srgb_format)
+        } else {
+            srgb_format
+        };
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::AutoNoVsync,
+            present_mode: resolve_present_mode(&surface_caps),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
-            desired_maximum_frame_latency: 1,
+            desired_maximum_frame_latency: game_config.frame_latency(),
         };
-        surface.configure(&device, &config);
+        if let Some(surface) = &surface {
+            surface.configure(device, &config);
+        }
 
-        // Initialize particles with random positions and velocities
-        let mut particles = Vec::with_capacity(game_config.num_particles as usize);
-        let mut rng = rand::thread_rng();
+        let hdr_target =
+            hdr.then(|| HdrTarget::new(device, config.format, size.width, size.height));
 
-        for _ in 0..game_config.num_particles {
-            particles.push(Particle {
-                position: [rng.gen_range(-0.9..0.9), rng.gen_range(-0.9..0.9)],
-                velocity: [rng.gen_range(-0.1..0.1), rng.gen_range(-0.1..0.1)],
-                acceleration: [0.0, 0.0],
-            });
+        let imported_particles = game_config.initial_particles.as_deref().map(|path| {
+            let particles = import::load_particles(path)
+                .unwrap_or_else(|err| panic!("failed to import particles: {}", err));
+            info!(
+                "imported {} particles from {}",
+                particles.len(),
+                path.display()
+            );
+            particles
+        });
+        if let Some(imported) = &imported_particles
+            && imported.len() as u32 != game_config.num_particles
+        {
+            info!(
+                "num_particles = {} is replaced by the {} imported particles",
+                game_config.num_particles,
+                imported.len()
+            );
+            game_config.num_particles = imported.len() as u32;
         }
+        let seed = game_config.seed.unwrap_or_else(rand::random);
+        debug!("seeding particles with {}", seed);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let particles = seed_particles(
+            &game_config,
+            imported_particles.as_deref(),
+            game_config.num_particles,
+            &mut rng,
+        );
 
         // Create particle buffer
-        let particle_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Particle Buffer"),
-            contents: bytemuck::cast_slice(&particles),
-            usage: wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::VERTEX
-                | wgpu::BufferUsages::COPY_DST,
-        });
+        let particle_buffer = create_particle_buffer(device, &particles);
 
-        let resolution = ResolutionUniform {
-            width: size.width as f32,
-            height: size.height as f32,
-        };
+        let resolution = ResolutionUniform::new(size.width, size.height);
 
         let resolution_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Resolution Buffer"),
@@ -124,11 +377,20 @@ impl<'a> State<'a> {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let render_params = RenderParamsUniform::from_config(&game_config, Command::Roam);
+
+        let render_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Render Params Buffer"),
+            contents: bytemuck::cast_slice(&[render_params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         // Time uniform buffer
         let time_data = TimeUniform {
             delta_time: 0.016, // default to 16ms
             particle_count: game_config.num_particles,
-            _padding1: [0.0; 2],
+            frame: 0,
+            elapsed_time: 0.0,
             _padding2: [0.0; 4],
         };
 
@@ -139,9 +401,7 @@ impl<'a> State<'a> {
         });
 
         // Mouse position buffer
-        let mouse_position = MouseUniform {
-            mouse_position: [0.0, 0.0],
-        };
+        let mouse_position = MouseUniform::zeroed();
 
         let mouse_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Mouse Position Buffer"),
@@ -149,7 +409,7 @@ impl<'a> State<'a> {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let command = CommandUniform { command: 0 };
+        let command = CommandUniform::from_command(Command::Roam, &game_config);
 
         let command_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Command Buffer"),
@@ -157,6 +417,55 @@ impl<'a> State<'a> {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let simulation = SimulationUniform::from_config(&game_config);
+
+        let simulation_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Simulation Buffer"),
+            contents: bytemuck::cast_slice(&[simulation]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // Neighbor lookup, only allocated at full size while a feature needs it
+        let grid = create_grid(device, &game_config, Command::Roam);
+
+        let chain_positions = create_vec2_buffer(device, "Chain Positions Buffer", 1);
+        let position_low = create_vec2_buffer(device, "Position Low Buffer", 1);
+
+        let flow_image = game_config.field_texture.as_deref().map(|path| {
+            let image = flow_field::load_pfm(path)
+                .unwrap_or_else(|err| panic!("failed to load the field texture: {}", err));
+            let max_dimension = device.limits().max_texture_dimension_2d;
+            assert!(
+                image.width <= max_dimension && image.height <= max_dimension,
+                "the field texture is {}x{}, the GPU supports at most {} on each side",
+                image.width,
+                image.height,
+                max_dimension
+            );
+            info!(
+                "loaded a {}x{} flow field from {}",
+                image.width,
+                image.height,
+                path.display()
+            );
+            image
+        });
+        let (flow_field, flow_field_view) =
+            flow_field::create_texture(device, &gpu.queue, flow_image.as_ref());
+
+        let stats = ParticleStats::new(device);
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
         // Create compute bind group layout
         let compute_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -206,6 +515,51 @@ impl<'a> State<'a> {
                         },
                         count: None,
                     },
+                    // Simulation settings buffer (read-only for compute)
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Spatial grid settings (read-only for compute)
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Spatial grid cell counts, cell starts, sorted indices and sorted
+                    // positions (read-write for compute)
+                    storage_entry(6),
+                    storage_entry(7),
+                    storage_entry(8),
+                    storage_entry(9),
+                    // Reduced particle statistics (read-write for compute)
+                    storage_entry(10),
+                    // Positions snapshot for Chain (read-write for compute)
+                    storage_entry(11),
+                    // Low halves of high precision positions (read-write for compute)
+                    storage_entry(12),
+                    // Flow field texture (read with textureLoad, no sampler)
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 17,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
@@ -236,73 +590,138 @@ impl<'a> State<'a> {
                         },
                         count: None,
                     },
+                    // Render params buffer (read-only for vertex and fragment)
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Trail history (read-only for vertex)
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Tints (read-only for vertex)
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
         // Create bind groups
-        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Compute Bind Group"),
-            layout: &compute_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: time_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: particle_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: mouse_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: command_buffer.as_entire_binding(),
-                },
-            ],
-        });
-
-        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Render Bind Group"),
-            layout: &render_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: particle_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: resolution_buffer.as_entire_binding(),
-                },
-            ],
-        });
+        let compute_bind_group = create_compute_bind_group(
+            device,
+            &compute_bind_group_layout,
+            &time_buffer,
+            &particle_buffer,
+            &mouse_buffer,
+            &command_buffer,
+            &simulation_buffer,
+            &grid,
+            &stats,
+            &chain_positions,
+            &position_low,
+            &flow_field_view,
+        );
 
         // Create compute shader
         let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Compute Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("compute.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(get_compute_shader(&game_config).into()),
         });
 
-        // Create compute pipeline
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Compute Pipeline"),
-            layout: Some(
-                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Compute Pipeline Layout"),
-                    bind_group_layouts: &[&compute_bind_group_layout],
-                    push_constant_ranges: &[],
-                }),
-            ),
-            module: &compute_shader,
-            entry_point: "update_particles",
-        });
+        // Position history, only allocated at full size while trails are drawn
+        let trails = Trails::new(device, &compute_shader, &time_buffer, &particle_buffer);
+        // Springs of the net, only allocated at full size while the net is enabled
+        let net = SpringNet::new(device, &compute_shader, &time_buffer, &particle_buffer);
+        // Per-particle colors, only allocated at full size while tints are enabled
+        let tints = Tints::new(
+            device,
+            &compute_shader,
+            &time_buffer,
+            &particle_buffer,
+            &mouse_buffer,
+        );
 
-        // Create render shader
-        let render_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Render Shader"),
-            source: wgpu::ShaderSource::Wgsl(get_shader(&game_config).into()),
-        });
+        let render_bind_group = create_render_bind_group(
+            device,
+            &render_bind_group_layout,
+            &particle_buffer,
+            &resolution_buffer,
+            &render_params_buffer,
+            &trails.history,
+            &tints.buffer,
+        );
+
+        // Create one compute pipeline per kernel
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Compute Pipeline Layout"),
+                bind_group_layouts: &[&compute_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let grid_pipelines = GRID_KERNELS
+            .iter()
+            .map(|(entry_point, _)| {
+                create_compute_pipeline(
+                    device,
+                    &compute_pipeline_layout,
+                    &compute_shader,
+                    entry_point,
+                )
+            })
+            .collect();
+
+        let snapshot_positions_pipeline = create_compute_pipeline(
+            device,
+            &compute_pipeline_layout,
+            &compute_shader,
+            "snapshot_positions",
+        );
+
+        let clear_stats_pipeline = create_compute_pipeline(
+            device,
+            &compute_pipeline_layout,
+            &compute_shader,
+            "clear_stats",
+        );
+        let reduce_stats_pipeline = create_compute_pipeline(
+            device,
+            &compute_pipeline_layout,
+            &compute_shader,
+            "reduce_stats",
+        );
+
+        let compute_pipelines = COMPUTE_KERNELS
+            .iter()
+            .map(|entry_point| {
+                create_compute_pipeline(
+                    device,
+                    &compute_pipeline_layout,
+                    &compute_shader,
+                    entry_point,
+                )
+            })
+            .collect();
 
         // Create render pipeline
         let render_pipeline_layout =
@@ -312,180 +731,1508 @@ impl<'a> State<'a> {
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &render_shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &render_shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
+        let depth_sorted = uses_depth_sort(&game_config, hdr_target.is_some());
+        let render_pipeline = create_render_pipeline(
+            device,
+            &render_pipeline_layout,
+            particle_color_target(hdr_target.is_some(), config.format),
+            particle_entry_point(&game_config),
+            particle_fragment_entry_point(&game_config, hdr_target.is_some()),
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            depth_sorted,
+        );
+
+        // Debug view drawing each particle's velocity as a line segment
+        let velocity_pipeline = create_render_pipeline(
+            device,
+            &render_pipeline_layout,
+            particle_color_target(hdr_target.is_some(), config.format),
+            "vs_velocity",
+            "fs_main",
+            wgpu::PrimitiveTopology::LineList,
+            wgpu::PolygonMode::Fill,
+            depth_sorted,
+        );
+
+        // Fading line through each particle's recent positions, drawn under the particles
+        let trail_pipeline = create_render_pipeline(
+            device,
+            &render_pipeline_layout,
+            trail_color_target(hdr_target.is_some(), config.format),
+            "vs_trail",
+            "fs_trail",
+            wgpu::PrimitiveTopology::LineList,
+            wgpu::PolygonMode::Fill,
+            false,
+        );
+
+        let background = BackgroundPass::new(
+            device,
+            particle_color_target(hdr_target.is_some(), config.format).format,
+            &resolution_buffer,
+        );
+
+        // Drawn on the surface after tonemapping, so it keeps its color at any exposure
+        let cursor = CursorPass::new(device, config.format);
+        let perf_graph = PerfGraph::new(device, config.format);
+
+        // Background visualizing the force field the compute pass applies
+        let field_view = FieldView::new(
+            device,
+            particle_color_target(hdr_target.is_some(), config.format).format,
+            get_field_view_shader(),
+            &mouse_buffer,
+            &command_buffer,
+            &resolution_buffer,
+            &simulation_buffer,
+        );
+
+        // Diagnostic overlay of the spatial grid occupancy
+        let grid_view = GridView::new(
+            device,
+            particle_color_target(hdr_target.is_some(), config.format).format,
+            &resolution_buffer,
+            &grid,
+        );
+
+        let egui = window.map(|window| EguiState::new(device, config.format, window));
+
+        let presets = preset::list_presets().unwrap_or_else(|err| {
+            warn!("failed to list presets: {}", err);
+            Vec::new()
         });
+        if presets.is_empty() {
+            info!("no presets in {}/", preset::PRESET_DIR);
+        } else {
+            info!("presets: {}", presets.join(", "));
+        }
 
-        Self {
+        let state = Self {
             surface,
-            device,
-            queue,
+            gpu,
             config,
             size,
             render_pipeline,
-            compute_pipeline,
+            render_pipeline_layout,
+            velocity_pipeline,
+            show_velocity_vectors: false,
+            trails,
+            net,
+            tints,
+            trail_pipeline,
+            background,
+            cursor,
+            field_view,
+            show_field: false,
+            grid_view,
+            show_grid: false,
+            perf_graph,
+            show_perf_graph: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            compute_pipelines,
+            active_compute: 0,
             particle_buffer,
             time_buffer,
             mouse_buffer,
             resolution_buffer,
+            render_params_buffer,
             command_buffer,
+            simulation_buffer,
+            grid,
+            grid_pipelines,
+            chain_positions,
+            position_low,
+            flow_field,
+            flow_field_view,
+            snapshot_positions_pipeline,
+            stats,
+            uploads: Uploads::new(),
+            clear_stats_pipeline,
+            reduce_stats_pipeline,
+            energy_baseline: None,
+            last_input: Instant::now(),
+            compute_bind_group_layout,
             compute_bind_group,
+            render_bind_group_layout,
             render_bind_group,
+            hdr_target,
+            canvas: None,
+            depth_target: None,
+            offscreen: None,
+            supersample: None,
+            quality: DynamicQuality::new(&game_config.dynamic_quality),
+            egui,
             last_update: Instant::now(),
+            last_stats_frame: 0,
+            steps: 0,
+            frame: 0,
+            elapsed_time: 0.0,
+            use_fixed_timestep: false,
+            paused: false,
+            minimized: false,
+            step_once: false,
             mouse_position: [0.0, 0.0],
+            previous_mouse_position: [0.0, 0.0],
+            fling_velocity: [0.0, 0.0],
+            left_pressed: false,
+            secondary_attractor: None,
+            selection: None,
+            staging: readback::Staging::default(),
+            read_back_particles: Vec::new(),
+            view_angle: 0.0,
             current_resolution: resolution,
             current_command: Command::Roam,
+            presets,
+            current_preset: None,
+            preset_name: String::new(),
             game_config,
+            timeline: None,
+            second_window_requested: false,
+            quit_requested: false,
+            quit_pressed_at: None,
+            command_since_switch: f32::INFINITY,
+            auto_cycle_since_input: 0.0,
+            auto_cycle_since_switch: 0.0,
+            imported_particles,
+            rng,
+        };
+        info!("{}", state.describe_surface());
+        state
+    }
+
+    /// Summary of the negotiated surface configuration, to tell apart machines where the
+    /// preferred format or present mode was not available.
+    pub fn describe_surface(&self) -> String {
+        format!(
+            "surface {}x{}: format {:?} ({}), present mode {:?}, alpha mode {:?}, frame latency {}, HDR target {}",
+            self.config.width,
+            self.config.height,
+            self.config.format,
+            if self.config.format.is_srgb() {
+                "sRGB"
+            } else {
+                "not sRGB"
+            },
+            self.config.present_mode,
+            self.config.alpha_mode,
+            self.config.desired_maximum_frame_latency,
+            if self.hdr_target.is_some() {
+                "on"
+            } else {
+                "off"
+            },
+        )
+    }
+
+    /// Estimates the GPU memory held by the buffers and textures of this state, so large
+    /// particle counts can be planned against the adapter's memory.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport::new();
+        report.add("particles", self.particle_buffer.size());
+        report.add("chain positions", self.chain_positions.size());
+        report.add("position low halves", self.position_low.size());
+        report.add("trail history", self.trails.history.size());
+        report.add("net springs", self.net.springs.size());
+        report.add("tints", self.tints.buffer.size());
+        report.add("spatial grid", self.grid.memory_size());
+        report.add("stats", self.stats.memory_size());
+        report.add(
+            "particle readback",
+            self.staging.memory_size()
+                + std::mem::size_of_val(self.read_back_particles.as_slice()) as u64,
+        );
+        report.add(
+            "uniforms",
+            [
+                &self.time_buffer,
+                &self.mouse_buffer,
+                &self.resolution_buffer,
+                &self.render_params_buffer,
+                &self.command_buffer,
+                &self.simulation_buffer,
+                &self.background.uniform_buffer,
+            ]
+            .iter()
+            .map(|buffer| buffer.size())
+            .sum(),
+        );
+
+        // one texture per frame that can be in flight, plus the one being presented
+        let swapchain_textures = self.config.desired_maximum_frame_latency as u64 + 1;
+        report.add(
+            "surface",
+            swapchain_textures
+                * texture_bytes(self.config.format, self.config.width, self.config.height),
+        );
+        let size = self.flow_field.size();
+        report.add(
+            "flow field",
+            texture_bytes(self.flow_field.format(), size.width, size.height),
+        );
+        if let Some(hdr_target) = &self.hdr_target {
+            let size = hdr_target.texture.size();
+            report.add(
+                "HDR target",
+                texture_bytes(hdr_target.texture.format(), size.width, size.height),
+            );
+        }
+        if let Some(canvas) = &self.canvas {
+            let size = canvas.texture.size();
+            report.add(
+                "canvas",
+                texture_bytes(CANVAS_FORMAT, size.width, size.height),
+            );
         }
+        if let Some(depth_target) = &self.depth_target {
+            let [width, height] = depth_target.size;
+            report.add("depth target", texture_bytes(DEPTH_FORMAT, width, height));
+        }
+        if let Some(supersample) = &self.supersample {
+            let [width, height] = supersample.size();
+            report.add(
+                "supersample target",
+                texture_bytes(supersample.texture.format(), width, height),
+            );
+        }
+        report
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+    /// Rebuilds the particle pipeline after a setting baked into its shader changed.
+    pub fn rebuild_render_pipeline(&mut self) {
+        let depth_sorted = self.depth_sorted();
+        self.render_pipeline = create_render_pipeline(
+            &self.gpu.device,
+            &self.render_pipeline_layout,
+            particle_color_target(self.hdr_target.is_some(), self.config.format),
+            particle_entry_point(&self.game_config),
+            particle_fragment_entry_point(&self.game_config, self.hdr_target.is_some()),
+            wgpu::PrimitiveTopology::TriangleList,
+            self.polygon_mode,
+            depth_sorted,
+        );
+        self.velocity_pipeline = create_render_pipeline(
+            &self.gpu.device,
+            &self.render_pipeline_layout,
+            particle_color_target(self.hdr_target.is_some(), self.config.format),
+            "vs_velocity",
+            "fs_main",
+            wgpu::PrimitiveTopology::LineList,
+            wgpu::PolygonMode::Fill,
+            depth_sorted,
+        );
+        self.trail_pipeline = create_render_pipeline(
+            &self.gpu.device,
+            &self.render_pipeline_layout,
+            trail_color_target(self.hdr_target.is_some(), self.config.format),
+            "vs_trail",
+            "fs_trail",
+            wgpu::PrimitiveTopology::LineList,
+            wgpu::PolygonMode::Fill,
+            false,
+        );
+        if self.canvas.is_some() {
+            let paint_pipeline = self.create_paint_pipeline();
+            if let Some(canvas) = &mut self.canvas {
+                canvas.paint_pipeline = paint_pipeline;
+            }
         }
     }
 
-    pub fn input(&mut self, _event: &WindowEvent) -> bool {
-        false
+    /// Switches the particles to the next polygon mode the device supports, rebuilding
+    /// their pipeline.
+    fn cycle_polygon_mode(&mut self) {
+        let features = self.gpu.device.features();
+        let supported = [
+            (wgpu::PolygonMode::Fill, wgpu::Features::empty()),
+            (wgpu::PolygonMode::Line, wgpu::Features::POLYGON_MODE_LINE),
+            (wgpu::PolygonMode::Point, wgpu::Features::POLYGON_MODE_POINT),
+        ]
+        .into_iter()
+        .filter(|(_, feature)| features.contains(*feature))
+        .map(|(mode, _)| mode)
+        .collect::<Vec<_>>();
+        if supported.len() == 1 {
+            warn!("the adapter supports neither wireframe nor point rendering");
+            return;
+        }
+
+        let current = supported
+            .iter()
+            .position(|mode| *mode == self.polygon_mode)
+            .unwrap_or(0);
+        self.polygon_mode = supported[(current + 1) % supported.len()];
+        info!(
+            "drawing particles with polygon mode {:?}",
+            self.polygon_mode
+        );
+        self.rebuild_render_pipeline();
     }
 
-    pub fn mouse_moved(
-        &mut self,
-        _device_id: winit::event::DeviceId,
-        position: winit::dpi::PhysicalPosition<f64>,
-    ) {
-        // Convert to normalized device coordinates
-        let x = (position.x / self.size.width as f64) * 2.0 - 1.0;
-        let y = -((position.y / self.size.height as f64) * 2.0 - 1.0);
-        self.mouse_position[0] = x as f32;
-        self.mouse_position[1] = y as f32;
+    /// Whether the particle pipelines test against the depth buffer.
+    pub fn depth_sorted(&self) -> bool {
+        uses_depth_sort(&self.game_config, self.hdr_target.is_some())
     }
 
-    pub fn update(&mut self) {
-        // Calculate delta time
-        let now = Instant::now();
-        let delta_time = now.duration_since(self.last_update).as_secs_f32();
-        self.last_update = now;
+    /// Replaces every particle with freshly seeded ones for the configured count, starting
+    /// over from the imported particles if there are any, reallocating the particle buffer.
+    pub fn recreate_particles(&mut self) {
+        let particles = seed_particles(
+            &self.game_config,
+            self.imported_particles.as_deref(),
+            self.game_config.num_particles,
+            &mut self.rng,
+        );
+        self.particle_buffer = create_particle_buffer(&self.gpu.device, &particles);
+        self.rebind_particle_buffer();
+    }
 
-        // Clamp delta time to avoid large jumps
-        let delta_time = delta_time.min(0.1);
+    /// Particles the particle buffer has room for, at least the configured count.
+    pub fn particle_capacity(&self) -> u32 {
+        buffer_capacity(&self.particle_buffer)
+    }
 
-        // Update time uniform
-        let time_data = TimeUniform {
-            delta_time,
-            particle_count: self.game_config.num_particles,
-            _padding1: [0.0; 2],
-            _padding2: [0.0; 4],
-        };
+    /// Overwrites the particles from index `start` on, leaving the rest of the buffer as it
+    /// is. Ranges past the end of the buffer are rejected with a warning.
+    pub fn write_particle_range(&self, start: u32, particles: &[Particle]) {
+        let end = start as u64 + particles.len() as u64;
+        if end > self.particle_capacity() as u64 {
+            warn!(
+                "cannot write particles {}..{}, the buffer only holds {}",
+                start,
+                end,
+                self.particle_capacity()
+            );
+            return;
+        }
+        ParticleLayout::CURRENT.write(
+            &self.gpu.queue,
+            &self.particle_buffer,
+            self.particle_capacity(),
+            start,
+            particles,
+        );
+    }
 
-        // update mouse position
-        let mouse_data = MouseUniform {
-            mouse_position: self.mouse_position,
-        };
+    /// Changes the number of simulated particles, keeping the ones that remain and seeding
+    /// any new ones.
+    pub fn set_particle_count(&mut self, count: u32) {
+        let old_count = self.game_config.num_particles;
+        self.game_config.num_particles = count;
+        self.resize_particles(old_count);
+    }
 
-        // update command
-        let command_data = CommandUniform::from_command(self.current_command);
+    /// Adapts the particles to a new `num_particles` after it was `old_count`. Particles
+    /// that exist in both keep moving where they are, only the added ones are seeded. The
+    /// buffer grows when it has to but never shrinks, fewer particles just use a prefix.
+    fn resize_particles(&mut self, old_count: u32) {
+        let count = self.game_config.num_particles;
+        let kept = old_count.min(count).min(self.particle_capacity());
+
+        if count > self.particle_capacity() {
+            let buffer = create_empty_particle_buffer(&self.gpu.device, count);
+            let mut encoder =
+                self.gpu
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Particle Resize Encoder"),
+                    });
+            ParticleLayout::CURRENT.copy_prefix(
+                &mut encoder,
+                (&self.particle_buffer, self.particle_capacity()),
+                (&buffer, buffer_capacity(&buffer)),
+                kept,
+            );
+            self.gpu.queue.submit(std::iter::once(encoder.finish()));
+            self.particle_buffer = buffer;
+            self.rebind_particle_buffer();
+        }
 
-        self.queue
-            .write_buffer(&self.time_buffer, 0, bytemuck::cast_slice(&[time_data]));
+        if count > kept {
+            let particles = initial_particles(&self.game_config, count - kept, &mut self.rng);
+            self.write_particle_range(kept, &particles);
+        }
+        debug!(
+            "resized to {} particles, {} kept, buffer holds {}",
+            count,
+            kept,
+            self.particle_capacity()
+        );
+    }
 
-        self.queue
-            .write_buffer(&self.mouse_buffer, 0, bytemuck::cast_slice(&[mouse_data]));
+    /// Rebinds everything that reads the particle buffer after it was replaced.
+    fn rebind_particle_buffer(&mut self) {
+        self.rebuild_compute_bind_group();
+        self.trails
+            .rebind(&self.gpu.device, &self.time_buffer, &self.particle_buffer);
+        self.net
+            .rebind(&self.gpu.device, &self.time_buffer, &self.particle_buffer);
+        self.tints.rebind(
+            &self.gpu.device,
+            &self.time_buffer,
+            &self.particle_buffer,
+            &self.mouse_buffer,
+        );
+        self.rebuild_render_bind_group();
+    }
 
-        self.queue.write_buffer(
+    /// Rebinds the buffers the particle pipelines read after one of them was replaced.
+    fn rebuild_render_bind_group(&mut self) {
+        self.render_bind_group = create_render_bind_group(
+            &self.gpu.device,
+            &self.render_bind_group_layout,
+            &self.particle_buffer,
             &self.resolution_buffer,
-            0,
-            bytemuck::cast_slice(&[self.current_resolution]),
+            &self.render_params_buffer,
+            &self.trails.history,
+            &self.tints.buffer,
         );
+    }
 
-        self.queue.write_buffer(
+    /// Rebinds the compute buffers after one of them was replaced.
+    fn rebuild_compute_bind_group(&mut self) {
+        self.compute_bind_group = create_compute_bind_group(
+            &self.gpu.device,
+            &self.compute_bind_group_layout,
+            &self.time_buffer,
+            &self.particle_buffer,
+            &self.mouse_buffer,
             &self.command_buffer,
-            0,
-            bytemuck::cast_slice(&[command_data]),
+            &self.simulation_buffer,
+            &self.grid,
+            &self.stats,
+            &self.chain_positions,
+            &self.position_low,
+            &self.flow_field_view,
         );
+    }
 
-        // Dispatch compute shader
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Compute Encoder"),
-            });
+    /// Loads a different `field_texture` for FieldFlow, or removes it for `None`. Keeps the
+    /// current one if the file cannot be read.
+    pub fn set_field_texture(&mut self, path: Option<PathBuf>) -> io::Result<()> {
+        let image = path.as_deref().map(flow_field::load_pfm).transpose()?;
+        let (flow_field, flow_field_view) =
+            flow_field::create_texture(&self.gpu.device, &self.gpu.queue, image.as_ref());
+        self.flow_field = flow_field;
+        self.flow_field_view = flow_field_view;
+        self.game_config.field_texture = path;
+        self.rebuild_compute_bind_group();
+        Ok(())
+    }
 
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Particle Compute Pass"),
-                timestamp_writes: None,
-            });
-            compute_pass.set_pipeline(&self.compute_pipeline);
-            compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+    /// Switches to a different configuration while running. The window settings and the
+    /// adapter stay as they are, and so do `hdr` and `smooth_render`, which are fixed when
+    /// the surface is created, and `fast_math`, which is baked into the compute shader.
+    pub fn apply_config(&mut self, mut config: GameConfiguration) {
+        config.window_width = self.game_config.window_width;
+        config.window_height = self.game_config.window_height;
+        config.window_position = self.game_config.window_position;
+        config.window_title = self.game_config.window_title.clone();
+        config.lock_aspect = self.game_config.lock_aspect;
+        config.adapter = self.game_config.adapter;
+        config.adapter_override = self.game_config.adapter_override;
+        if config.hdr != self.game_config.hdr {
+            warn!("hdr cannot change while running, restart to apply it");
+            config.hdr = self.game_config.hdr;
+        }
+        if config.smooth_render != self.game_config.smooth_render {
+            warn!("smooth_render cannot change while running, restart to apply it");
+            config.smooth_render = self.game_config.smooth_render;
+        }
+        if config.field_texture != self.game_config.field_texture {
+            warn!("field_texture cannot change while running, restart to apply it");
+            config.field_texture = self.game_config.field_texture.clone();
+        }
+        if config.fast_math != self.game_config.fast_math {
+            warn!("fast_math cannot change while running, restart to apply it");
+            config.fast_math = self.game_config.fast_math;
+        }
+        config.validate();
 
-            // Use 2D dispatch to avoid exceeding the 65535 limit per dimension
-            let workgroups_x = 65535u32; // Maximum value for x dimension
-            let workgroups_y = self.game_config.num_particles.div_ceil(workgroups_x * 1024); // Calculate y dimension
-            compute_pass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
+        let old_count = self.game_config.num_particles;
+        let particles_changed = config.num_particles != old_count;
+        let latency_changed = config.frame_latency() != self.game_config.frame_latency();
+        self.quality = DynamicQuality::new(&config.dynamic_quality);
+        self.game_config = config;
+        if particles_changed {
+            self.resize_particles(old_count);
         }
+        if latency_changed {
+            // reconfigures the surface with the new latency
+            self.resize(self.size);
+        }
+        self.rebuild_render_pipeline();
+    }
 
-        self.queue.submit(std::iter::once(encoder.finish()));
+    /// Prints the configuration as it is right now, including changes made while running,
+    /// to stdout as pretty JSON that can be used as a config file.
+    pub fn print_config(&self) {
+        match serde_json::to_string_pretty(&self.game_config) {
+            Ok(json) => println!("{}", json),
+            Err(err) => warn!("failed to serialize the configuration: {}", err),
+        }
     }
 
-    #[allow(clippy::single_match)]
+    /// Saves the current configuration as `presets/<name>.json`.
+    pub fn save_preset(&mut self, name: &str) -> io::Result<()> {
+        if !preset::is_valid_name(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "preset name {:?} may only contain letters, digits, '_' and '-'",
+                    name
+                ),
+            ));
+        }
+
+        fs::create_dir_all(preset::PRESET_DIR)?;
+        self.game_config.save(&preset::preset_path(name))?;
+        self.presets = preset::list_presets()?;
+        self.current_preset = self.presets.iter().position(|preset| preset == name);
+        info!("saved preset {}", name);
+        Ok(())
+    }
+
+    /// Loads `presets/<name>.json` and applies it live.
+    pub fn load_preset(&mut self, name: &str) -> io::Result<()> {
+        let config = GameConfiguration::load(&preset::preset_path(name))?;
+        self.apply_config(config);
+        self.current_preset = self.presets.iter().position(|preset| preset == name);
+        info!("loaded preset {}", name);
+        Ok(())
+    }
+
+    /// Applies a request from the control socket, `Quit` is left to the event loop.
+    pub fn apply_control(&mut self, request: &ControlRequest) -> Result<(), String> {
+        self.note_input();
+        match request {
+            ControlRequest::SetCommand(name) => {
+                let command = Command::from_name(name).ok_or_else(|| {
+                    let names: Vec<_> = Command::ALL.iter().map(|c| c.name()).collect();
+                    format!(
+                        "unknown command {:?}, expected one of {}",
+                        name,
+                        names.join(", ")
+                    )
+                })?;
+                self.current_command = command;
+                debug!("switched to {:?}", self.current_command);
+            }
+            ControlRequest::SetParticleCount(0) => {
+                return Err("the particle count has to be positive".to_string());
+            }
+            ControlRequest::SetParticleCount(count) => {
+                let mut config = self.game_config.clone();
+                config.num_particles = *count;
+                self.apply_config(config);
+            }
+            ControlRequest::SetPaused(paused) => self.paused = *paused,
+            ControlRequest::LoadPreset(name) => {
+                if !preset::is_valid_name(name) {
+                    return Err(format!("invalid preset name {:?}", name));
+                }
+                self.load_preset(name)
+                    .map_err(|err| format!("failed to load preset {}: {}", name, err))?;
+            }
+            ControlRequest::Quit => {}
+        }
+        Ok(())
+    }
+
+    /// Loads the preset `step` places after the current one, wrapping around.
+    fn cycle_preset(&mut self, step: isize) {
+        let count = self.presets.len();
+        if count == 0 {
+            info!("no presets in {}/", preset::PRESET_DIR);
+            return;
+        }
+
+        let index = match self.current_preset {
+            Some(current) => (current as isize + step).rem_euclid(count as isize) as usize,
+            None if step > 0 => 0,
+            None => count - 1,
+        };
+        let name = self.presets[index].clone();
+        if let Err(err) = self.load_preset(&name) {
+            warn!("failed to load preset {}: {}", name, err);
+        }
+    }
+
+    /// Copies the active particles back from the GPU through the staging buffer kept in
+    /// `staging`, whatever the particle layout. The particles stay in
+    /// `read_back_particles` until the next readback, which unpacks into the same `Vec`
+    /// instead of allocating a new one.
+    ///
+    /// This is a blocking call that waits for all submitted GPU work to finish, so it is
+    /// meant for tooling and inspection rather than for every frame.
+    pub fn copy_particles_to_staging(&mut self) -> &[Particle] {
+        let particles = &mut self.read_back_particles;
+        self.staging.read_buffer(
+            &self.gpu.device,
+            &self.gpu.queue,
+            &self.particle_buffer,
+            self.particle_buffer.size(),
+            |bytes| ParticleLayout::CURRENT.unpack_into(bytes, particles),
+        );
+        // the rest of the buffer is spare capacity the simulation leaves alone
+        let active = self.active_particles() as usize;
+        self.read_back_particles.truncate(active);
+        &self.read_back_particles
+    }
+
+    /// An owned copy of the active particles, see `copy_particles_to_staging`.
+    pub fn read_particles(&mut self) -> Vec<Particle> {
+        self.copy_particles_to_staging().to_vec()
+    }
+
+    /// Reads back only particle `index`, none if it is not an active one.
+    pub fn read_particle(&mut self, index: u32) -> Option<Particle> {
+        if index >= self.active_particles() {
+            return None;
+        }
+        let ranges = ParticleLayout::CURRENT.particle_ranges(self.particle_capacity(), index);
+        self.staging.read_ranges(
+            &self.gpu.device,
+            &self.gpu.queue,
+            &self.particle_buffer,
+            &ranges,
+            |bytes| ParticleLayout::CURRENT.unpack(bytes).first().copied(),
+        )
+    }
+
+    /// The cursor in the space the particles live in: `mouse_position` turned back by the
+    /// `view_angle` the particles are drawn turned by, in pixel proportions like `view` of
+    /// shader.wgsl.
+    pub fn simulation_cursor(&self) -> [f32; 2] {
+        let aspect_ratio = self.size.width.max(1) as f32 / self.size.height.max(1) as f32;
+        let [x, y] = self.mouse_position;
+        let (sin, cos) = (-self.view_angle).sin_cos();
+        let square = [x * aspect_ratio, y];
+        [
+            (cos * square[0] - sin * square[1]) / aspect_ratio,
+            sin * square[0] + cos * square[1],
+        ]
+    }
+
+    /// The active particle nearest to the cursor and its index, in the space the particles
+    /// live in. Reads the particles back, so it stalls for a moment with many particles.
+    fn nearest_particle(&mut self) -> Option<(u32, Particle)> {
+        let cursor = self.simulation_cursor();
+        let active = self.copy_particles_to_staging();
+        let distance_sq = |particle: &Particle| {
+            (particle.position[0] - cursor[0]).powi(2) + (particle.position[1] - cursor[1]).powi(2)
+        };
+        active
+            .iter()
+            .enumerate()
+            .filter(|(_, particle)| distance_sq(particle).is_finite())
+            .min_by(|(_, a), (_, b)| distance_sq(a).total_cmp(&distance_sq(b)))
+            .map(|(index, particle)| (index as u32, *particle))
+    }
+
+    /// Selects the active particle nearest to the cursor for inspection and logs its
+    /// fields, or clears the selection if that particle already is selected.
+    pub fn select_nearest(&mut self) {
+        let Some((index, particle)) = self.nearest_particle() else {
+            return;
+        };
+        if self
+            .selection
+            .is_some_and(|selection| selection.index == index)
+        {
+            self.selection = None;
+            info!("deselected particle {}", index);
+            return;
+        }
+        self.selection = Some(Selection {
+            index,
+            particle,
+            read_at: Instant::now(),
+        });
+        info!("selected particle {}: {}", index, particle);
+    }
+
+    /// Reads the selected particle back again if its copy is older than
+    /// `SELECTION_REFRESH_INTERVAL`, dropping the selection once it is no longer active.
+    fn refresh_selection(&mut self) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+        if selection.read_at.elapsed() < SELECTION_REFRESH_INTERVAL {
+            return;
+        }
+        self.selection = self
+            .read_particle(selection.index)
+            .map(|particle| Selection {
+                index: selection.index,
+                particle,
+                read_at: Instant::now(),
+            });
+    }
+
+    /// Pins the active particle nearest to the cursor in place, or releases it if it already
+    /// is pinned. Reads the particles back, so it stalls for a moment with many particles.
+    pub fn toggle_pin_nearest(&mut self) {
+        let Some((index, nearest)) = self.nearest_particle() else {
+            return;
+        };
+
+        // released particles start from rest rather than with the velocity they had
+        // before they were pinned
+        let particle = Particle {
+            velocity: [0.0, 0.0],
+            acceleration: [0.0, 0.0],
+            pinned: (nearest.pinned == 0) as u32,
+            ..nearest
+        };
+        self.write_particle_range(index, &[particle]);
+        info!(
+            "{} particle {} at ({:.3}, {:.3})",
+            if particle.pinned != 0 {
+                "pinned"
+            } else {
+                "released"
+            },
+            index,
+            particle.position[0],
+            particle.position[1]
+        );
+    }
+
+    /// Reads the particles back and logs a few aggregate figures about the active ones.
+    pub fn log_particle_summary(&mut self) {
+        let active = self.copy_particles_to_staging();
+
+        let mut center = [0.0f64; 2];
+        let mut speed = 0.0f64;
+        let mut non_finite = 0;
+        for particle in active {
+            if !(particle.position.iter().chain(&particle.velocity)).all(|v| v.is_finite()) {
+                non_finite += 1;
+                continue;
+            }
+            center[0] += particle.position[0] as f64;
+            center[1] += particle.position[1] as f64;
+            speed += (particle.velocity[0] as f64).hypot(particle.velocity[1] as f64);
+        }
+
+        let finite = (active.len() - non_finite).max(1) as f64;
+        info!(
+            "{} particles: center of mass ({:.4}, {:.4}), mean speed {:.4}, {} non-finite",
+            active.len(),
+            center[0] / finite,
+            center[1] / finite,
+            speed / finite,
+            non_finite
+        );
+    }
+
+    /// Number of particles simulated and drawn, reduced by the dynamic quality controller
+    /// when it is enabled.
+    pub fn active_particles(&self) -> u32 {
+        let num_particles = self.game_config.num_particles;
+        if self.game_config.dynamic_quality.enabled {
+            ((num_particles as f32 * self.quality.scale).ceil() as u32).min(num_particles)
+        } else {
+            num_particles
+        }
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.note_input();
+        let minimized = new_size.width == 0 || new_size.height == 0;
+        if minimized != self.minimized {
+            debug!("{}", if minimized { "minimized" } else { "restored" });
+            self.minimized = minimized;
+        }
+        if !minimized {
+            self.size = new_size;
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.config.desired_maximum_frame_latency = self.game_config.frame_latency();
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.gpu.device, &self.config);
+            }
+            if let Some(hdr_target) = &mut self.hdr_target {
+                hdr_target.resize(&self.gpu.device, new_size.width, new_size.height);
+            }
+            if let Some(canvas) = &mut self.canvas {
+                canvas.resize(
+                    &self.gpu.device,
+                    &self.gpu.queue,
+                    new_size.width,
+                    new_size.height,
+                );
+            }
+            // recreated at the new size by the next render
+            self.depth_target = None;
+        }
+    }
+
+    pub fn input(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.egui
+            .as_mut()
+            .is_some_and(|egui| egui.input(window, event))
+    }
+
+    /// Whether the device is gone and the whole state has to be recreated, nothing rendered
+    /// with it shows up anymore.
+    pub fn is_device_lost(&self) -> bool {
+        self.gpu.is_lost()
+    }
+
+    /// Records that the user interacted, which ends idling and holds `auto_cycle` back.
+    pub fn note_input(&mut self) {
+        self.last_input = Instant::now();
+        self.auto_cycle_since_input = 0.0;
+        self.auto_cycle_since_switch = 0.0;
+    }
+
+    /// Most recent particle statistics that made it back from the GPU. Only kept up to
+    /// date while idle detection, `show_kinetic_energy` or the F1 overlay uses them, and
+    /// up to `stats_interval` frames plus a frame or two late.
+    pub fn stats(&self) -> Option<&StatsData> {
+        self.stats.latest.as_ref()
+    }
+
+    /// Total kinetic energy of the active particles (unit masses) from the most recent
+    /// readback, 0 before the first one. Has the same delay as `stats`.
+    pub fn total_kinetic_energy(&self) -> f32 {
+        self.stats
+            .latest
+            .map_or(0.0, |stats| stats.kinetic_energy() as f32)
+    }
+
+    /// Remembers the first energy reading of the current command and particle count, which
+    /// the displayed drift is measured against.
+    fn update_energy_baseline(&mut self) {
+        if !self.game_config.show_kinetic_energy || self.stats.latest.is_none() {
+            return;
+        }
+        let key = (self.current_command, self.active_particles());
+        if self
+            .energy_baseline
+            .is_none_or(|(command, particles, _)| (command, particles) != key)
+        {
+            self.energy_baseline = Some((key.0, key.1, self.total_kinetic_energy()));
+        }
+    }
+
+    /// Whether the scene is static enough to redraw at the idle rate: no input for a while,
+    /// and either paused or every particle slower than the idle speed threshold.
+    pub fn is_idle(&self) -> bool {
+        let idle = &self.game_config.idle;
+        if !idle.enabled
+            || idle.fps.is_nan()
+            || idle.fps <= 0.0
+            || self.last_input.elapsed().as_secs_f32() < idle.input_timeout
+        {
+            return false;
+        }
+        self.paused
+            || self
+                .stats
+                .latest
+                .is_some_and(|stats| stats.max_speed < idle.speed_threshold)
+    }
+
+    pub fn mouse_moved(&mut self, position: winit::dpi::PhysicalPosition<f64>) {
+        self.note_input();
+        // Convert to normalized device coordinates
+        let x = (position.x / self.size.width as f64) * 2.0 - 1.0;
+        let y = -((position.y / self.size.height as f64) * 2.0 - 1.0);
+        self.mouse_position[0] = x as f32;
+        self.mouse_position[1] = y as f32;
+    }
+
+    pub fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
+        self.note_input();
+        if button == MouseButton::Left {
+            self.left_pressed = state == ElementState::Pressed;
+        }
+        if state == ElementState::Pressed && button == MouseButton::Right {
+            self.secondary_attractor = Some(self.simulation_cursor());
+            debug!(
+                "placed secondary attractor at {:?}",
+                self.secondary_attractor
+            );
+        }
+        if state == ElementState::Pressed && button == MouseButton::Middle {
+            self.select_nearest();
+        }
+    }
+
+    pub fn update(&mut self) {
+        // Calculate delta time
+        let now = Instant::now();
+        let frame_time = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        // the first frame after restoring only simulates its own time, not the time spent
+        // minimized
+        if self.minimized {
+            return;
+        }
+
+        self.quality
+            .record_frame(frame_time, &self.game_config.dynamic_quality);
+        self.stats.collect(&self.gpu.device);
+        self.update_energy_baseline();
+
+        // the view keeps turning while paused, it is not part of the simulation
+        if self.game_config.auto_rotate && self.game_config.auto_rotate_speed.is_finite() {
+            self.view_angle = (self.view_angle
+                + self.game_config.auto_rotate_speed * frame_time.min(0.1))
+            .rem_euclid(std::f32::consts::TAU);
+        }
+
+        if self.paused && !self.step_once {
+            return;
+        }
+
+        // Single steps use the fixed timestep, wall-clock time barely advances between them
+        let delta_time = if self.use_fixed_timestep || self.step_once {
+            self.game_config.fixed_timestep
+        } else {
+            frame_time
+        } * self.game_config.time_scale();
+        self.step_once = false;
+        self.command_since_switch += delta_time;
+        self.advance_timeline(delta_time);
+        self.advance_auto_cycle(delta_time);
+        self.simulate_frame(delta_time);
+    }
+
+    /// Applies the timeline entries that become due within the next `delta_time` seconds.
+    fn advance_timeline(&mut self, delta_time: f32) {
+        let Some(timeline) = &mut self.timeline else {
+            return;
+        };
+        let finished = timeline.is_finished();
+        let due = timeline.advance(delta_time).to_vec();
+        let now_finished = timeline.is_finished();
+
+        for entry in due {
+            self.current_command = entry.command;
+            if let Some(params) = &entry.params {
+                // validated when the timeline was loaded
+                match timeline::with_params(&self.game_config.commands, entry.command, params) {
+                    Ok(commands) => self.game_config.commands = commands,
+                    Err(err) => warn!("timeline entry at {}s: {}", entry.time, err),
+                }
+            }
+            debug!(
+                "timeline switched to {:?} at {}s",
+                entry.command, entry.time
+            );
+        }
+        if !finished && now_finished {
+            info!("timeline finished at frame {}", self.frame);
+        }
+    }
+
+    /// Switches to the next of the `auto_cycle` commands once `auto_cycle` seconds passed
+    /// since the last switch, and `auto_cycle_resume_after` seconds since the last input.
+    /// Counts simulated time, so recordings replay the same switches, and holds off while
+    /// a timeline is playing.
+    fn advance_auto_cycle(&mut self, delta_time: f32) {
+        let Some(interval) = self.game_config.auto_cycle else {
+            return;
+        };
+        if self
+            .timeline
+            .as_ref()
+            .is_some_and(|timeline| !timeline.is_finished())
+        {
+            return;
+        }
+        self.auto_cycle_since_input += delta_time;
+        self.auto_cycle_since_switch += delta_time;
+        if self.auto_cycle_since_input < self.game_config.auto_cycle_resume_after
+            || self.auto_cycle_since_switch < interval
+        {
+            return;
+        }
+        self.auto_cycle_since_switch = 0.0;
+
+        // commands that are not part of the rotation continue with its first one
+        let commands = self.game_config.auto_cycle_commands();
+        let next = commands
+            .iter()
+            .position(|&command| command == self.current_command)
+            .map_or(0, |index| (index + 1) % commands.len());
+        if let Some(&command) = commands.get(next) {
+            self.current_command = command;
+            debug!("auto cycle switched to {:?}", command);
+        }
+    }
+
+    /// Advances the simulation by `warmup_steps` fixed timesteps before anything is shown,
+    /// waiting for the GPU to finish them.
+    pub fn warm_up(&mut self) {
+        let steps = self.game_config.warmup_steps;
+        if steps == 0 {
+            return;
+        }
+
+        let start = Instant::now();
+        self.step(steps);
+        info!("warmed up for {} steps in {:.2?}", steps, start.elapsed());
+        // the first frame should not simulate the time the warmup took
+        self.last_update = Instant::now();
+    }
+
+    /// Advances the simulation by `steps` fixed timesteps and waits for the GPU to finish
+    /// them.
+    pub fn step(&mut self, steps: u32) {
+        for _ in 0..steps {
+            self.simulate_frame(self.game_config.fixed_timestep);
+        }
+        self.gpu.device.poll(wgpu::Maintain::Wait);
+    }
+
+    /// Advances the simulation by `delta_time` seconds in `substeps` equal steps.
+    fn simulate_frame(&mut self, delta_time: f32) {
+        // Clamp delta time to avoid large jumps
+        let delta_time = delta_time.min(0.1);
+        self.frame += 1;
+
+        // read once, so a frame always simulates all of its time even if the count changes
+        // before the next one
+        let substeps = self.game_config.substeps();
+        let step = delta_time / substeps as f32;
+        if self.game_config.batch_substeps {
+            // the passes still run one after the other, each seeing the previous one's writes
+            let mut encoder = self.create_compute_encoder();
+            let mut reduced_stats = false;
+            for _ in 0..substeps {
+                reduced_stats |= self.simulate(&mut encoder, step);
+            }
+            self.submit_compute(encoder, reduced_stats);
+        } else {
+            for _ in 0..substeps {
+                let mut encoder = self.create_compute_encoder();
+                let reduced_stats = self.simulate(&mut encoder, step);
+                self.submit_compute(encoder, reduced_stats);
+            }
+        }
+    }
+
+    fn create_compute_encoder(&self) -> wgpu::CommandEncoder {
+        self.gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Compute Encoder"),
+            })
+    }
+
+    /// Submits simulation steps recorded into `encoder`, starting the readback of the
+    /// statistics if one of them reduced them.
+    fn submit_compute(&mut self, encoder: wgpu::CommandEncoder, reduced_stats: bool) {
+        self.uploads.submit(&self.gpu.queue, encoder);
+        if reduced_stats {
+            self.stats.request_map();
+        }
+    }
+
+    /// Records one simulation step of `delta_time` seconds into `encoder`, with the uniforms
+    /// it needs. Returns whether it reduced the statistics, which then have to be mapped
+    /// once `encoder` is submitted.
+    fn simulate(&mut self, encoder: &mut wgpu::CommandEncoder, delta_time: f32) -> bool {
+        self.steps += 1;
+        self.elapsed_time += delta_time as f64;
+
+        let particle_count = self.active_particles();
+
+        // Update time uniform
+        let time_data = TimeUniform {
+            delta_time,
+            particle_count,
+            frame: self.steps as u32,
+            elapsed_time: self.elapsed_time as f32,
+            _padding2: [0.0; 4],
+        };
+
+        // cursor movement since the last step, over the time that step simulates
+        let cursor = self.simulation_cursor();
+        let step = delta_time.max(1e-6);
+        let mouse_velocity = [
+            (cursor[0] - self.previous_mouse_position[0]) / step,
+            (cursor[1] - self.previous_mouse_position[1]) / step,
+        ];
+        self.previous_mouse_position = cursor;
+
+        // cursor events do not arrive every step, the raw velocity drops to zero in between
+        let blend = 1.0 - (-step / FLING_SMOOTHING).exp();
+        for (fling, raw) in self.fling_velocity.iter_mut().zip(mouse_velocity) {
+            *fling += (raw - *fling) * blend;
+        }
+
+        // update mouse position
+        let mouse_data = MouseUniform {
+            mouse_position: cursor,
+            secondary_position: self.secondary_attractor.unwrap_or_default(),
+            weights: [
+                self.game_config.primary_attractor_weight,
+                self.game_config.secondary_attractor_weight,
+            ],
+            secondary_active: self.secondary_attractor.is_some() as u32,
+            left_pressed: self.left_pressed as u32,
+            velocity: mouse_velocity,
+            fling_velocity: self.fling_velocity,
+        };
+
+        // update command
+        let command_data = CommandUniform::from_command(self.current_command, &self.game_config);
+
+        // update simulation settings
+        let simulation_data = SimulationUniform::from_config(&self.game_config);
+
+        // the grid is reallocated when a setting changed its resolution or size
+        if !self.grid.fits(
+            &self.game_config,
+            self.current_command,
+            self.game_config.num_particles,
+        ) {
+            self.grid = create_grid(&self.gpu.device, &self.game_config, self.current_command);
+            self.rebuild_compute_bind_group();
+            self.grid_view
+                .rebind(&self.gpu.device, &self.resolution_buffer, &self.grid);
+        }
+        let grid_data = GridUniform::new(&self.game_config, &self.grid);
+
+        // Chain reads the previous step's positions, allocated once the command is first used,
+        // and the low position halves are allocated once high precision is turned on
+        let chain = self.current_command == Command::Chain;
+        let capacity = self.game_config.num_particles as u64 * 8;
+        if chain && self.chain_positions.size() < capacity {
+            debug!(
+                "allocating chain positions for {} particles",
+                self.game_config.num_particles
+            );
+            self.chain_positions = create_vec2_buffer(
+                &self.gpu.device,
+                "Chain Positions Buffer",
+                self.game_config.num_particles,
+            );
+            self.rebuild_compute_bind_group();
+        }
+        if self.game_config.high_precision && self.position_low.size() < capacity {
+            debug!(
+                "allocating high precision positions for {} particles",
+                self.game_config.num_particles
+            );
+            self.position_low = create_vec2_buffer(
+                &self.gpu.device,
+                "Position Low Buffer",
+                self.game_config.num_particles,
+            );
+            self.rebuild_compute_bind_group();
+        }
+
+        // the trail history is reallocated when the trail length or particle count changed
+        if self.trails.prepare(
+            &self.gpu.device,
+            encoder,
+            &mut self.uploads,
+            self.game_config.trail_length,
+            self.game_config.num_particles,
+            &self.time_buffer,
+            &self.particle_buffer,
+        ) {
+            debug!(
+                "allocating trails of {} positions for {} particles",
+                self.trails.length, self.trails.capacity
+            );
+            self.rebuild_render_bind_group();
+        }
+        // the tints are reallocated when enabled, disabled or the particle count grew
+        if self.tints.prepare(
+            &self.gpu.device,
+            encoder,
+            &mut self.uploads,
+            &self.game_config.tint,
+            self.game_config.num_particles,
+            &self.time_buffer,
+            &self.particle_buffer,
+            &self.mouse_buffer,
+        ) {
+            debug!("allocating tints for {} particles", self.tints.capacity);
+            self.rebuild_render_bind_group();
+        }
+        // the springs are regenerated when the net settings changed
+        self.net.prepare(
+            &self.gpu.device,
+            encoder,
+            &mut self.uploads,
+            &self.game_config.net,
+            particle_count,
+            &self.time_buffer,
+            &self.particle_buffer,
+        );
+        // recorded into the encoder, so every step of a batch sees its own values
+        let device = &self.gpu.device;
+        let uploads = &mut self.uploads;
+        uploads.write(device, encoder, &self.time_buffer, &time_data);
+        uploads.write(device, encoder, &self.mouse_buffer, &mouse_data);
+        uploads.write(device, encoder, &self.command_buffer, &command_data);
+        uploads.write(device, encoder, &self.simulation_buffer, &simulation_data);
+        uploads.write(device, encoder, &self.grid.uniform_buffer, &grid_data);
+
+        // The statistics are only reduced while idle detection, the energy display or the
+        // overlay uses them, every stats_interval frames, and not while the previous
+        // readback is still in flight
+        let reduce_stats = (self.game_config.idle.enabled
+            || self.game_config.show_kinetic_energy
+            || self.egui.as_ref().is_some_and(|egui| egui.visible))
+            && self.frame >= self.last_stats_frame + self.game_config.stats_interval.max(1) as u64
+            && self.stats.wants_update();
+        if reduce_stats {
+            self.last_stats_frame = self.frame;
+        }
+
+        // Dispatch compute shader
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Particle Compute Pass"),
+                timestamp_writes: None,
+            });
+
+            // Record the positions the particles leave this step
+            self.trails.record(&mut compute_pass, particle_count);
+
+            // Pull the net along its springs before anything moves
+            self.net.record(&mut compute_pass);
+
+            compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+
+            // Sort the particles into the grid before anything queries neighbors
+            if SpatialGrid::is_needed(&self.game_config, self.current_command) {
+                for ((_, grid_dispatch), pipeline) in GRID_KERNELS.iter().zip(&self.grid_pipelines)
+                {
+                    let config = match grid_dispatch {
+                        GridDispatch::Cells => DispatchConfig::Grid2d {
+                            width: self.grid.cells_per_axis,
+                            height: self.grid.cells_per_axis,
+                            workgroup_size: [TILE_SIZE, TILE_SIZE],
+                        },
+                        GridDispatch::Particles => DispatchConfig::Linear {
+                            count: particle_count,
+                        },
+                        GridDispatch::Single => DispatchConfig::Single,
+                    };
+                    dispatch::dispatch(&mut compute_pass, pipeline, config);
+                }
+            }
+
+            let particles = DispatchConfig::Linear {
+                count: particle_count,
+            };
+
+            if chain {
+                dispatch::dispatch(
+                    &mut compute_pass,
+                    &self.snapshot_positions_pipeline,
+                    particles,
+                );
+            }
+
+            dispatch::dispatch(
+                &mut compute_pass,
+                &self.compute_pipelines[self.active_compute],
+                particles,
+            );
+
+            if reduce_stats {
+                dispatch::dispatch(
+                    &mut compute_pass,
+                    &self.clear_stats_pipeline,
+                    DispatchConfig::Single,
+                );
+                dispatch::dispatch(&mut compute_pass, &self.reduce_stats_pipeline, particles);
+            }
+
+            // Tint the particles the cursor touches where they ended up
+            self.tints.record(&mut compute_pass, particle_count);
+        }
+
+        if reduce_stats {
+            self.stats.copy_to_staging(encoder);
+        }
+        reduce_stats
+    }
+
+    /// Every character `key_pressed` acts on, in the order of its match. A new binding
+    /// belongs here as well, the chaos run presses these at random.
+    pub const CHARACTER_KEYS: &'static [&'static str] = &[
+        "r", "s", "c", "d", "m", "h", "b", "e", "p", "l", "a", "z", ".", "v", "f", "t", "o", "g",
+        "i", "n", "j", "u", "x", "[", "]", "-", "=", "9", "0", "<", ">", "{", "}", "y", "w", "k",
+    ];
+
     pub fn keyboard_input(
         &mut self,
-        device_id: DeviceId,
+        _device_id: DeviceId,
         key_event: &KeyEvent,
         is_synthetic: bool,
         window: &Window,
     ) {
-        if key_event.state == winit::event::ElementState::Pressed && !is_synthetic {
-            match &key_event.logical_key {
-                Key::Character(a) => match a.as_str() {
-                    "r" => {
-                        self.current_command = Command::Roam;
+        if key_event.state == ElementState::Pressed && !is_synthetic {
+            self.key_pressed(&key_event.logical_key, Some(window));
+        }
+    }
+
+    /// Handles a key press, shared by live keyboard input, replayed events and the chaos
+    /// run. Without a `window` the keys that change it do nothing.
+    #[allow(clippy::single_match)]
+    pub fn key_pressed(&mut self, key: &Key, window: Option<&Window>) {
+        self.note_input();
+        // Escape leaves fullscreen first, even when it is the quit key
+        let leaves_fullscreen = *key == Key::Named(NamedKey::Escape)
+            && window.is_some_and(|window| window.fullscreen().is_some());
+        if self.is_quit_key(key) && !leaves_fullscreen {
+            self.quit_key_pressed();
+            return;
+        }
+        match key {
+            Key::Character(a) => match a.as_str() {
+                "r" => self.switch_command(Command::Roam),
+                "s" => self.switch_command(Command::Shuffle),
+                "c" => self.switch_command(Command::Collapse),
+                "d" => self.switch_command(Command::Dust),
+                "m" => self.switch_command(Command::Follow),
+                "h" => self.switch_command(Command::Chain),
+                "b" => self.switch_command(Command::Grab),
+                "e" => self.switch_command(Command::Emit),
+                "p" => self.switch_command(Command::Spiral),
+                "l" => self.switch_command(Command::Life),
+                "a" => self.switch_command(Command::Brownian),
+                "z" => {
+                    self.switch_command(Command::FieldFlow);
+                    if self.game_config.field_texture.is_none() {
+                        info!("FieldFlow has nothing to follow without a field_texture");
                     }
-                    "s" => {
-                        self.current_command = Command::Shuffle;
+                }
+                "." if self.paused => {
+                    self.step_once = true;
+                }
+                "v" => {
+                    self.show_velocity_vectors = !self.show_velocity_vectors;
+                }
+                "f" => {
+                    self.show_field = !self.show_field;
+                }
+                "t" => self.cycle_polygon_mode(),
+                "o" => {
+                    self.game_config.show_custom_cursor = !self.game_config.show_custom_cursor;
+                }
+                "g" => {
+                    self.show_grid = !self.show_grid;
+                    if self.show_grid
+                        && !SpatialGrid::is_needed(&self.game_config, self.current_command)
+                    {
+                        info!(
+                            "the spatial grid is only built while min_distance is above 0, Life is active or band_cohesion is on"
+                        );
                     }
-                    _ => {}
-                },
+                }
+                "i" => {
+                    info!("{}", self.describe_surface());
+                }
+                "n" => {
+                    self.log_particle_summary();
+                }
+                "j" => {
+                    self.toggle_pin_nearest();
+                }
+                "u" => {
+                    info!("{}", self.memory_report());
+                }
+                "x" => {
+                    if let Some(canvas) = &mut self.canvas {
+                        canvas.clear_pending = true;
+                        info!("cleared the canvas");
+                    }
+                }
+                "[" | "]" => {
+                    let factor = if a.as_str() == "]" {
+                        EXPOSURE_STEP
+                    } else {
+                        1.0 / EXPOSURE_STEP
+                    };
+                    self.game_config.exposure *= factor;
+                    info!("exposure {:.3}", self.game_config.exposure);
+                    if self.hdr_target.is_none() {
+                        info!("exposure only applies with hdr enabled");
+                    }
+                }
+                "-" | "=" => {
+                    let factor = if a.as_str() == "=" {
+                        EXPOSURE_STEP
+                    } else {
+                        1.0 / EXPOSURE_STEP
+                    };
+                    self.game_config.intensity = (self.game_config.clamped_intensity() * factor)
+                        .clamp(MIN_INTENSITY, MAX_INTENSITY);
+                    info!("intensity {:.3}", self.game_config.intensity);
+                }
+                "9" | "0" => {
+                    let factor = if a.as_str() == "0" {
+                        QUAD_SIZE_STEP
+                    } else {
+                        1.0 / QUAD_SIZE_STEP
+                    };
+                    self.game_config.quad_size =
+                        (self.game_config.quad_size * factor).clamp(MIN_QUAD_SIZE, MAX_QUAD_SIZE);
+                    info!("quad size {:.5}", self.game_config.quad_size);
+                }
+                "<" | ">" => {
+                    let substeps = self.game_config.substeps();
+                    self.game_config.substeps = if a.as_str() == ">" {
+                        (substeps + 1).min(MAX_SUBSTEPS)
+                    } else {
+                        (substeps - 1).max(1)
+                    };
+                    info!("{} substeps", self.game_config.substeps);
+                }
+                "{" | "}" => {
+                    let factor = if a.as_str() == "}" {
+                        EXPOSURE_STEP
+                    } else {
+                        1.0 / EXPOSURE_STEP
+                    };
+                    self.game_config.time_scale = (self.game_config.time_scale() * factor)
+                        .clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+                    info!("time scale {:.3}", self.game_config.time_scale);
+                }
+                "y" => {
+                    self.game_config.auto_rotate = !self.game_config.auto_rotate;
+                    info!(
+                        "auto rotate {}",
+                        if self.game_config.auto_rotate {
+                            "on"
+                        } else {
+                            "off"
+                        }
+                    );
+                }
+                "w" => {
+                    self.second_window_requested = true;
+                }
+                "k" => {
+                    self.active_compute = (self.active_compute + 1) % self.compute_pipelines.len();
+                    info!(
+                        "switched to compute kernel {}",
+                        COMPUTE_KERNELS[self.active_compute]
+                    );
+                }
+                _ => {}
+            },
 
-                Key::Named(nk) => {
-                    match *nk {
-                        NamedKey::F11 => {
-                            // Toggle fullscreen
+            Key::Named(nk) => {
+                match *nk {
+                    NamedKey::F11 => {
+                        // Toggle fullscreen
+                        if let Some(window) = window {
                             let is_fullscreen = window.fullscreen().is_some();
                             if is_fullscreen {
                                 window.set_fullscreen(None);
@@ -495,45 +2242,367 @@ impl<'a> State<'a> {
                                 )));
                             }
                         }
+                    }
 
-                        NamedKey::Escape => {
-                            // Exit fullscreen
-                            window.set_fullscreen(None);
+                    NamedKey::Space => {
+                        self.paused = !self.paused;
+                        info!("{}", if self.paused { "paused" } else { "resumed" });
+                    }
+
+                    NamedKey::Backspace => {
+                        self.secondary_attractor = None;
+                    }
+
+                    NamedKey::F1 => {
+                        if let Some(egui) = &mut self.egui {
+                            egui.visible = !egui.visible;
                         }
+                    }
+
+                    NamedKey::F2 => self.print_config(),
+
+                    NamedKey::F3 => {
+                        self.show_perf_graph = !self.show_perf_graph;
+                    }
 
-                        _ => {}
+                    NamedKey::PageUp => self.cycle_preset(-1),
+                    NamedKey::PageDown => self.cycle_preset(1),
+
+                    NamedKey::Escape => {
+                        // Exit fullscreen
+                        if let Some(window) = window {
+                            window.set_fullscreen(None);
+                        }
                     }
+
+                    _ => {}
                 }
+            }
 
-                _ => {}
+            _ => {}
+        }
+    }
+
+    /// Whether `key` is the configured `quit_key`.
+    fn is_quit_key(&self, key: &Key) -> bool {
+        let quit_key = self.game_config.quit_key.as_str();
+        match key {
+            Key::Character(character) => {
+                !quit_key.is_empty() && character.eq_ignore_ascii_case(quit_key)
             }
+            Key::Named(named) => format!("{:?}", named) == quit_key,
+            _ => false,
         }
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+    /// Switches to `command` from a key press, unless a key already switched it within the
+    /// last `command_cooldown_ms` of simulated time.
+    fn switch_command(&mut self, command: Command) {
+        let cooldown = self.game_config.command_cooldown_ms as f32 / 1000.0;
+        if self.command_since_switch < cooldown {
+            debug!("ignored switching to {:?} during the cooldown", command);
+            return;
+        }
+        self.current_command = command;
+        self.command_since_switch = 0.0;
+        debug!("switched to {:?}", self.current_command);
+    }
+
+    /// Requests to quit, or with `confirm_quit` only when the key was already pressed
+    /// within `QUIT_CONFIRM_WINDOW`.
+    fn quit_key_pressed(&mut self) {
+        if !self.game_config.confirm_quit
+            || self
+                .quit_pressed_at
+                .is_some_and(|pressed| pressed.elapsed() < QUIT_CONFIRM_WINDOW)
+        {
+            self.quit_requested = true;
+            return;
+        }
+        self.quit_pressed_at = Some(Instant::now());
+        info!(
+            "press {} again within {} seconds to quit",
+            self.game_config.quit_key,
+            QUIT_CONFIRM_WINDOW.as_secs()
+        );
+    }
+
+    /// Draws the fading line behind every particle, if trails are on.
+    fn draw_trails<'p>(&'p self, render_pass: &mut wgpu::RenderPass<'p>) {
+        if self.game_config.trail_length == 0 || self.trails.length == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.trail_pipeline);
+        render_pass.set_bind_group(0, &self.render_bind_group, &[]);
+        // Draw 2 vertices (1 line) per recorded position of every particle
+        let particles = self.active_particles().min(self.trails.capacity);
+        render_pass.draw(0..particles * self.trails.length * 2, 0..1);
+    }
+
+    /// Creates, resizes or drops the supersample target to match `supersample` and the
+    /// window, keeping every dimension within the device's texture limit.
+    fn prepare_supersample(&mut self) {
+        let factor = self.game_config.supersample();
+        if factor <= 1.0 {
+            self.supersample = None;
+            return;
+        }
+        let frame_size = [self.config.width, self.config.height];
+        let max_dimension = self.gpu.device.limits().max_texture_dimension_2d;
+        let size = supersample::target_size(frame_size, factor, max_dimension);
+        if self.supersample.as_ref().is_some_and(|supersample| {
+            supersample.size() == size && supersample.frame_size == frame_size
+        }) {
+            return;
+        }
+        if size.contains(&max_dimension) {
+            warn!(
+                "supersample = {} exceeds the {} pixel texture limit, clamping the target to {}x{}",
+                factor, max_dimension, size[0], size[1]
+            );
+        }
+        self.supersample = Some(Supersample::new(
+            &self.gpu.device,
+            particle_color_target(self.hdr_target.is_some(), self.config.format).format,
+            self.hdr_target.is_some(),
+            size,
+            frame_size,
+        ));
+    }
+
+    /// Draws the particles, and their velocity lines when shown, into a started pass.
+    fn draw_particles<'p>(&'p self, render_pass: &mut wgpu::RenderPass<'p>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        self.draw_particle_quads(render_pass);
+
+        if self.show_velocity_vectors {
+            render_pass.set_pipeline(&self.velocity_pipeline);
+            // Draw 2 vertices (1 line) per particle
+            render_pass.draw(0..self.active_particles() * 2, 0..1);
+        }
+    }
+
+    /// Draws one quad or polygon per particle with whichever particle pipeline is set.
+    fn draw_particle_quads<'p>(&'p self, render_pass: &mut wgpu::RenderPass<'p>) {
+        render_pass.set_bind_group(0, &self.render_bind_group, &[]);
+        // 2 triangles per quad, or one per polygon side
+        let vertices = self.game_config.vertices_per_particle();
+        if self.game_config.instanced_rendering {
+            render_pass.draw(0..vertices, 0..self.active_particles());
+        } else {
+            // validate() warns when this does not fit, the last particles are left out
+            render_pass.draw(0..self.active_particles().saturating_mul(vertices), 0..1);
+        }
+    }
+
+    /// Adds this frame's particles onto the canvas, clearing it first if requested.
+    fn paint_canvas(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(canvas) = &self.canvas else {
+            return;
+        };
+        {
+            let load = if canvas.clear_pending {
+                wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+            } else {
+                wgpu::LoadOp::Load
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Canvas Paint Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &canvas.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            let deposit = self.game_config.canvas_deposit as f64;
+            render_pass.set_pipeline(&canvas.paint_pipeline);
+            render_pass.set_blend_constant(wgpu::Color {
+                r: deposit,
+                g: deposit,
+                b: deposit,
+                a: deposit,
+            });
+            self.draw_particle_quads(&mut render_pass);
+        }
+        if let Some(canvas) = &mut self.canvas {
+            canvas.clear_pending = false;
+        }
+    }
+
+    /// Pipeline drawing the particles onto the canvas.
+    fn create_paint_pipeline(&self) -> wgpu::RenderPipeline {
+        create_render_pipeline(
+            &self.gpu.device,
+            &self.render_pipeline_layout,
+            Canvas::paint_color_target(),
+            particle_entry_point(&self.game_config),
+            "fs_main",
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            false,
+        )
+    }
+
+    pub fn render(&mut self, window: &Window) -> Result<(), wgpu::SurfaceError> {
+        // a zero-size surface cannot be configured, the last one would be drawn stretched
+        if self.minimized {
+            return Ok(());
+        }
+        let Some(surface) = &self.surface else {
+            return Ok(());
+        };
+        let output = surface.get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        self.draw(&view, Some(window));
+        output.present();
+        Ok(())
+    }
 
+    /// Renders a frame into a texture of the surface's size and format instead of the
+    /// surface, for measuring headless states. Nothing is shown or read back.
+    pub fn render_offscreen(&mut self) {
+        if self.minimized {
+            return;
+        }
+        let size = wgpu::Extent3d {
+            width: self.config.width,
+            height: self.config.height,
+            depth_or_array_layers: 1,
+        };
+        if self
+            .offscreen
+            .as_ref()
+            .is_none_or(|texture| texture.size() != size)
+        {
+            self.offscreen = Some(self.gpu.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Offscreen Target"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            }));
+        }
+        let view = self
+            .offscreen
+            .as_ref()
+            .expect("created above")
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.draw(&view, None);
+    }
+
+    /// Records and submits everything drawn in a frame into `view`. Without a `window` the
+    /// system cursor is left alone and the overlay is not drawn.
+    fn draw(&mut self, view: &wgpu::TextureView, window: Option<&Window>) {
         let mut encoder = self
+            .gpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
+        self.prepare_supersample();
+        let target_size = self
+            .supersample
+            .as_ref()
+            .map_or([self.config.width, self.config.height], Supersample::size);
+        let depth_sorted = self.depth_sorted();
+        if depth_sorted
+            && self
+                .depth_target
+                .as_ref()
+                .is_none_or(|depth_target| depth_target.size != target_size)
+        {
+            self.depth_target = Some(DepthTarget::new(
+                &self.gpu.device,
+                target_size[0],
+                target_size[1],
+            ));
+        }
+
+        if self.game_config.canvas_mode {
+            if self.canvas.is_none() {
+                self.canvas = Some(Canvas::new(
+                    &self.gpu.device,
+                    particle_color_target(self.hdr_target.is_some(), self.config.format).format,
+                    self.config.width,
+                    self.config.height,
+                    self.create_paint_pipeline(),
+                ));
+            }
+            self.paint_canvas(&mut encoder);
+        }
+
+        // Particles go to the HDR target when enabled and get tonemapped onto the surface
+        let particle_view = self.hdr_target.as_ref().map_or(view, |hdr| &hdr.view);
+        // and while supersampling are drawn larger first and averaged down onto it
+        let supersample_view = self
+            .supersample
+            .as_ref()
+            .map(|supersample| &supersample.view);
+
+        // the tonemap pass writes linear values, so only the surface format decides
+        let background = self
+            .game_config
+            .background
+            .for_target(self.config.format.is_srgb());
+        let clear_color = background.clear_color();
+        self.background.prepare(&self.gpu.queue, &background);
+
+        // written every frame, so intensity changes show while paused as well
+        let render_params = RenderParamsUniform {
+            trail_length: self.trails.length,
+            tinted: (self.tints.capacity > 0) as u32,
+            selected_particle: self
+                .selection
+                .map_or(NO_SELECTION, |selection| selection.index),
+            view_angle: self.view_angle,
+            pulse_phase: self.game_config.pulse_phase(self.elapsed_time),
+            splat_radius: if uses_splats(&self.game_config, self.hdr_target.is_some()) {
+                self.game_config.splat_radius
+            } else {
+                0.0
+            },
+            ..RenderParamsUniform::from_config(&self.game_config, self.current_command)
+        };
+        self.gpu.queue.write_buffer(
+            &self.render_params_buffer,
+            0,
+            bytemuck::cast_slice(&[render_params]),
+        );
+        // the view keeps turning while paused, so this is written per frame rather than per step
+        let resolution = ResolutionUniform {
+            view_angle: self.view_angle,
+            ..self.current_resolution
+        };
+        self.gpu.queue.write_buffer(
+            &self.resolution_buffer,
+            0,
+            bytemuck::cast_slice(&[resolution]),
+        );
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: particle_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.1,
-                            b: 0.1,
-                            a: 1.0,
+                            r: clear_color[0] as f64,
+                            g: clear_color[1] as f64,
+                            b: clear_color[2] as f64,
+                            a: clear_color[3] as f64,
                         }),
                         store: wgpu::StoreOp::Store,
                     },
@@ -543,35 +2612,624 @@ impl<'a> State<'a> {
                 occlusion_query_set: None,
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.render_bind_group, &[]);
-            // Draw 6 vertices (2 triangles) per particle
-            render_pass.draw(0..self.game_config.num_particles * 6, 0..1);
+            self.background
+                .draw(&mut render_pass, &self.game_config.background);
+
+            if self.show_field && self.current_command.has_field() {
+                self.field_view.draw(&mut render_pass);
+            }
+
+            if self.show_grid && SpatialGrid::is_needed(&self.game_config, self.current_command) {
+                self.grid_view.draw(&mut render_pass);
+            }
+
+            if let Some(canvas) = self
+                .canvas
+                .as_ref()
+                .filter(|_| self.game_config.canvas_mode)
+            {
+                canvas.draw(&mut render_pass);
+            }
+
+            if supersample_view.is_none() {
+                self.draw_trails(&mut render_pass);
+                if !depth_sorted {
+                    self.draw_particles(&mut render_pass);
+                }
+            }
         }
 
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        if let Some(supersample_view) = supersample_view {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Supersampled Particle Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: supersample_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.draw_trails(&mut render_pass);
+            if !depth_sorted {
+                self.draw_particles(&mut render_pass);
+            }
+        }
 
-        Ok(())
+        // Depth sorted particles get a pass of their own, so the full-screen views above
+        // need no depth state
+        if let Some(depth_target) = self.depth_target.as_ref().filter(|_| depth_sorted) {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Depth Sorted Particle Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: supersample_view.unwrap_or(particle_view),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_target.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.draw_particles(&mut render_pass);
+        }
+
+        if let Some(supersample) = &self.supersample {
+            supersample.downsample(&mut encoder, particle_view);
+        }
+
+        if let Some(hdr_target) = &self.hdr_target {
+            hdr_target.prepare(&self.gpu.queue, &self.game_config);
+            hdr_target.tonemap(&mut encoder, view);
+        }
+
+        if self.show_perf_graph {
+            self.perf_graph.draw(
+                &self.gpu.queue,
+                &mut encoder,
+                view,
+                [self.config.width, self.config.height],
+                &self.quality.frame_times,
+                self.game_config.dynamic_quality.target_fps,
+            );
+        }
+
+        if let Some(window) = window {
+            self.cursor
+                .sync_os_cursor(window, self.game_config.show_custom_cursor);
+        }
+        if self.game_config.show_custom_cursor {
+            // normalized device coordinates back to pixels, y pointing down
+            let position = [
+                (self.mouse_position[0] + 1.0) * 0.5 * self.size.width as f32,
+                (1.0 - self.mouse_position[1]) * 0.5 * self.size.height as f32,
+            ];
+            self.cursor
+                .draw(&self.gpu.queue, &mut encoder, view, position);
+        }
+
+        let mut response = OverlayResponse::default();
+        if let Some(window) = window
+            && self.egui.as_ref().is_some_and(|egui| egui.visible)
+        {
+            self.refresh_selection();
+            let hud = HudInfo {
+                quality_scale: self.quality.scale,
+                active_particles: self.active_particles(),
+                kinetic_energy: self
+                    .energy_baseline
+                    .map(|(_, _, baseline)| (self.total_kinetic_energy(), baseline)),
+                center_of_mass: self.stats().and_then(StatsData::center_of_mass),
+                selection: self
+                    .selection
+                    .map(|selection| (selection.index, selection.particle)),
+            };
+            let game_config = &mut self.game_config;
+            let preset_name = &mut self.preset_name;
+            let egui = self.egui.as_mut().expect("checked above");
+            egui.draw(
+                &self.gpu.device,
+                &self.gpu.queue,
+                &mut encoder,
+                view,
+                window,
+                [self.config.width, self.config.height],
+                |context| {
+                    response = overlay::parameters_window(context, game_config, preset_name, &hud)
+                },
+            );
+        }
+
+        self.gpu.queue.submit(std::iter::once(encoder.finish()));
+
+        if response.save_preset {
+            let name = self.preset_name.clone();
+            if let Err(err) = self.save_preset(&name) {
+                warn!("failed to save preset {}: {}", name, err);
+            }
+        }
+    }
+}
+
+/// Zeroed per-particle `vec2<f32>` storage with room for `capacity` particles.
+fn create_vec2_buffer(device: &wgpu::Device, label: &str, capacity: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: capacity.max(1) as u64 * 8,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+/// A grid sized for `config` and `command`, or the minimal stand-in when nothing needs
+/// neighbor queries.
+fn create_grid(device: &wgpu::Device, config: &GameConfiguration, command: Command) -> SpatialGrid {
+    if SpatialGrid::is_needed(config, command) {
+        let cells_per_axis =
+            SpatialGrid::cells_per_axis(SpatialGrid::query_distance(config, command));
+        debug!(
+            "allocating {}x{} spatial grid for {} particles",
+            cells_per_axis, cells_per_axis, config.num_particles
+        );
+        SpatialGrid::new(device, cells_per_axis, config.num_particles)
+    } else {
+        SpatialGrid::new(device, 1, 1)
+    }
+}
+
+const PARTICLE_BUFFER_USAGES: wgpu::BufferUsages = wgpu::BufferUsages::STORAGE
+    .union(wgpu::BufferUsages::VERTEX)
+    .union(wgpu::BufferUsages::COPY_DST)
+    .union(wgpu::BufferUsages::COPY_SRC);
+
+fn create_particle_buffer(device: &wgpu::Device, particles: &[Particle]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Particle Buffer"),
+        contents: &ParticleLayout::CURRENT.pack(particles),
+        usage: PARTICLE_BUFFER_USAGES,
+    })
+}
+
+/// Particles a particle buffer has room for.
+fn buffer_capacity(buffer: &wgpu::Buffer) -> u32 {
+    (buffer.size() / std::mem::size_of::<Particle>() as u64) as u32
+}
+
+/// Particle buffer with room for `capacity` particles, left for the caller to fill.
+fn create_empty_particle_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Particle Buffer"),
+        size: capacity.max(1) as u64 * std::mem::size_of::<Particle>() as u64,
+        usage: PARTICLE_BUFFER_USAGES,
+        mapped_at_creation: false,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_compute_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    time_buffer: &wgpu::Buffer,
+    particle_buffer: &wgpu::Buffer,
+    mouse_buffer: &wgpu::Buffer,
+    command_buffer: &wgpu::Buffer,
+    simulation_buffer: &wgpu::Buffer,
+    grid: &SpatialGrid,
+    stats: &ParticleStats,
+    chain_positions: &wgpu::Buffer,
+    position_low: &wgpu::Buffer,
+    flow_field_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Compute Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: time_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: particle_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: mouse_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: command_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: simulation_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: grid.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: grid.cell_counts.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: grid.cell_starts.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: grid.sorted_indices.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: grid.sorted_positions.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: stats.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: chain_positions.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: position_low.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 17,
+                resource: wgpu::BindingResource::TextureView(flow_field_view),
+            },
+        ],
+    })
+}
+
+fn create_render_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    particle_buffer: &wgpu::Buffer,
+    resolution_buffer: &wgpu::Buffer,
+    render_params_buffer: &wgpu::Buffer,
+    trail_history: &wgpu::Buffer,
+    tints: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Render Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: particle_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: resolution_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: render_params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: trail_history.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: tints.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// The present mode `AutoNoVsync` would pick, resolved here so the choice can be reported:
+/// the first of Immediate, Mailbox and Fifo that the surface supports.
+fn resolve_present_mode(surface_caps: &wgpu::SurfaceCapabilities) -> wgpu::PresentMode {
+    [
+        wgpu::PresentMode::Immediate,
+        wgpu::PresentMode::Mailbox,
+        wgpu::PresentMode::Fifo,
+    ]
+    .into_iter()
+    .find(|mode| surface_caps.present_modes.contains(mode))
+    .unwrap_or(wgpu::PresentMode::Fifo)
+}
+
+/// The first `count` imported particles with their accelerations seeded, followed by random
+/// ones for the rest of `count`. The particles of the net, if enabled, are placed on its
+/// nodes instead.
+fn seed_particles(
+    config: &GameConfiguration,
+    imported: Option<&[Particle]>,
+    count: u32,
+    rng: &mut StdRng,
+) -> Vec<Particle> {
+    let mut particles: Vec<Particle> = imported
+        .unwrap_or_default()
+        .iter()
+        .take(count as usize)
+        .map(|particle| Particle {
+            acceleration: config.initial_acceleration.sample(particle.position, rng),
+            phase: rng.gen_range(0.0..1.0),
+            ..*particle
+        })
+        .collect();
+    particles.extend(initial_particles(
+        config,
+        count - particles.len() as u32,
+        rng,
+    ));
+    net::seed(&config.net, &mut particles);
+    particles
+}
+
+/// Random particles spread over most of the screen, moving in the configured general
+/// direction.
+fn initial_particles(config: &GameConfiguration, count: u32, rng: &mut StdRng) -> Vec<Particle> {
+    let mut particles = Vec::with_capacity(count as usize);
+    let bias = config.initial_velocity_bias;
+    let spread = config.initial_velocity_spread.max(0.0);
+
+    for _ in 0..count {
+        // gen_range panics on an empty range, a spread of 0 gives every particle the bias
+        let mut jitter = || {
+            if spread > 0.0 {
+                rng.gen_range(-spread..spread)
+            } else {
+                0.0
+            }
+        };
+        let velocity = [bias[0] + jitter(), bias[1] + jitter()];
+        let position = [rng.gen_range(-0.9..0.9), rng.gen_range(-0.9..0.9)];
+        particles.push(Particle {
+            position,
+            velocity,
+            acceleration: config.initial_acceleration.sample(position, rng),
+            pinned: 0,
+            phase: rng.gen_range(0.0..1.0),
+        });
+    }
+
+    particles
+}
+
+/// Color target of the particle pass: additive blending into the HDR target when enabled,
+/// otherwise straight onto the surface.
+pub fn particle_color_target(
+    hdr: bool,
+    surface_format: wgpu::TextureFormat,
+) -> wgpu::ColorTargetState {
+    if hdr {
+        // accumulate overlapping particles beyond white, the tonemap pass
+        // brings the result back into range
+        wgpu::ColorTargetState {
+            format: HDR_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::REPLACE,
+            }),
+            write_mask: wgpu::ColorWrites::ALL,
+        }
+    } else {
+        wgpu::ColorTargetState {
+            format: surface_format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+        }
+    }
+}
+
+/// Color target of the trail pass, which blends by the fade the shader writes to alpha:
+/// additively into the HDR target, otherwise over what is already on the surface.
+fn trail_color_target(hdr: bool, surface_format: wgpu::TextureFormat) -> wgpu::ColorTargetState {
+    if hdr {
+        wgpu::ColorTargetState {
+            format: HDR_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::REPLACE,
+            }),
+            write_mask: wgpu::ColorWrites::ALL,
+        }
+    } else {
+        wgpu::ColorTargetState {
+            format: surface_format,
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        }
+    }
+}
+
+/// Whether particles are drawn depth sorted. Additive HDR blending gives the same result in
+/// any order, so sorting is skipped there.
+fn uses_depth_sort(config: &GameConfiguration, hdr: bool) -> bool {
+    config.depth_sort && !hdr
+}
+
+/// Whether particles are drawn as splats. They need the HDR target to add up in, without it
+/// they stay quads.
+fn uses_splats(config: &GameConfiguration, hdr: bool) -> bool {
+    config.smooth_render && hdr
+}
+
+/// Fragment entry point of the particle pipeline, a hard quad or a Gaussian splat.
+fn particle_fragment_entry_point(config: &GameConfiguration, hdr: bool) -> &'static str {
+    if uses_splats(config, hdr) {
+        "fs_splat"
+    } else {
+        "fs_main"
+    }
+}
+
+/// Vertex entry point of the particle pipeline, which has to match the draw call in
+/// `State::render`.
+fn particle_entry_point(config: &GameConfiguration) -> &'static str {
+    if config.instanced_rendering {
+        "vs_main_instanced"
+    } else {
+        "vs_main"
     }
 }
 
-pub fn get_shader(config: &GameConfiguration) -> String {
-    let string = include_str!("shader.wgsl");
-    /*
-       // $RUST_REPLACEME
-       const QUAD_SIZE: f32 = 0.001;
-       // $RUST_REPLACEMEEND
-
-       we need to replace "const QUAD_SIZE: f32 = 0.001;" with whatever is provided from the GameConfiguration.
-       the place is marked with $RUST_REPLACEME and $RUST_REPLACEMEEND
-    */
-
-    let mut string = string.to_string();
-    let start = string.find("$RUST_REPLACEME").unwrap();
-    let end = string.find("$RUST_REPLACEMEEND").unwrap() + "$RUST_REPLACEMEEND".len();
-    let replacement = format!("\nconst QUAD_SIZE: f32 = {};", config.quad_size);
-    string.replace_range(start..end, &replacement);
-    // println!("Shader: {}", string);
+/// Builds a pipeline drawing particles from shader.wgsl with the given entry points.
+#[allow(clippy::too_many_arguments)]
+pub fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    target: wgpu::ColorTargetState,
+    vertex_entry_point: &str,
+    fragment_entry_point: &str,
+    topology: wgpu::PrimitiveTopology,
+    polygon_mode: wgpu::PolygonMode,
+    depth_sorted: bool,
+) -> wgpu::RenderPipeline {
+    let render_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Render Shader"),
+        source: wgpu::ShaderSource::Wgsl(get_shader().into()),
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(vertex_entry_point),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &render_shader,
+            entry_point: vertex_entry_point,
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &render_shader,
+            entry_point: fragment_entry_point,
+            targets: &[Some(target)],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: depth_sorted.then(DepthTarget::depth_stencil_state),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+/// Builds a compute pipeline running `entry_point` of `module`.
+pub fn create_compute_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(layout),
+        module,
+        entry_point,
+    })
+}
+
+pub fn get_shader() -> String {
+    let mut string = include_str!("shader.wgsl").to_string();
+    replace_section(
+        &mut string,
+        "PARTICLES",
+        &ParticleLayout::CURRENT.shader_section(false),
+    );
+    trace!("generated render shader:\n{}", string);
+    string
+}
+
+pub fn get_compute_shader(config: &GameConfiguration) -> String {
+    let mut string = include_str!("compute.wgsl").to_string();
+    replace_section(&mut string, "FIELD", &field_source());
+    replace_section(
+        &mut string,
+        "PARTICLES",
+        &ParticleLayout::CURRENT.shader_section(true),
+    );
+    replace_section(
+        &mut string,
+        "WORKGROUP",
+        &format!(
+            "\nconst WORKGROUP_SIZE: u32 = {}u;\nconst TILE_SIZE: u32 = {}u;",
+            WORKGROUP_SIZE, TILE_SIZE
+        ),
+    );
+    // The command constants are generated from the Command enum, so the shader's switch
+    // cannot drift out of sync with CommandUniform::from_command.
+    replace_section(&mut string, "COMMANDS", &command_constants());
+    replace_section(
+        &mut string,
+        "FAST_MATH",
+        &format!("\nconst FAST_MATH: bool = {};", config.fast_math),
+    );
+    trace!("generated compute shader:\n{}", string);
+    string
+}
+
+pub fn get_field_view_shader() -> String {
+    let mut string = include_str!("field_view.wgsl").to_string();
+    replace_section(&mut string, "FIELD", &field_source());
+    replace_section(&mut string, "COMMANDS", &command_constants());
+    trace!("generated field view shader:\n{}", string);
     string
 }
+
+/// field.wgsl, shared by every shader that evaluates the command's force field.
+fn field_source() -> String {
+    format!("\n{}\n//", include_str!("field.wgsl"))
+}
+
+/// WGSL constants for every command index plus the number of commands.
+fn command_constants() -> String {
+    let mut constants = String::new();
+    for command in Command::ALL {
+        constants.push_str(&format!(
+            "\nconst COMMAND_{}: u32 = {}u;",
+            command.name().to_uppercase(),
+            command.as_u32()
+        ));
+    }
+    constants.push_str(&format!(
+        "\nconst COMMAND_COUNT: u32 = {}u;",
+        Command::COUNT
+    ));
+    constants
+}
+
+/// Replaces everything from `$RUST_<marker>` up to and including `$RUST_<marker>END` in a
+/// shader source. The markers live in comments, so the unprocessed shader stays valid WGSL.
+fn replace_section(source: &mut String, marker: &str, replacement: &str) {
+    let start_marker = format!("$RUST_{}", marker);
+    let end_marker = format!("$RUST_{}END", marker);
+    let start = source.find(&start_marker).unwrap();
+    let end = source.find(&end_marker).unwrap() + end_marker.len();
+    source.replace_range(start..end, replacement);
+}
@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+
+use log::warn;
+
+use crate::types::StatsData;
+
+/// Aggregates computed over all particles by the reduction kernels of compute.wgsl, read
+/// back without stalling: a copy is mapped asynchronously and picked up by a later frame.
+pub struct ParticleStats {
+    /// Written by the reduction kernels.
+    pub buffer: wgpu::Buffer,
+    staging_buffer: wgpu::Buffer,
+    /// A copy into the staging buffer is in flight or mapped.
+    pending: bool,
+    /// Set by the map callback once mapping the staging buffer finished.
+    map_result: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+    /// Most recent values that made it back to the CPU.
+    pub latest: Option<StatsData>,
+}
+
+impl ParticleStats {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let size = std::mem::size_of::<StatsData>() as u64;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Stats Buffer"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Stats Staging Buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            staging_buffer,
+            pending: false,
+            map_result: Arc::new(Mutex::new(None)),
+            latest: None,
+        }
+    }
+
+    /// Bytes held by the stats buffer and its staging copy.
+    pub fn memory_size(&self) -> u64 {
+        self.buffer.size() + self.staging_buffer.size()
+    }
+
+    /// Whether the reduction should run this step, which is whenever the previous result
+    /// has been collected.
+    pub fn wants_update(&self) -> bool {
+        !self.pending
+    }
+
+    /// Records the copy of the freshly reduced values into the staging buffer.
+    pub fn copy_to_staging(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &self.staging_buffer, 0, self.buffer.size());
+        self.pending = true;
+    }
+
+    /// Starts mapping the staging buffer, call after submitting the copy.
+    pub fn request_map(&self) {
+        let map_result = self.map_result.clone();
+        self.staging_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                *map_result.lock().unwrap() = Some(result);
+            });
+    }
+
+    /// Picks up a finished readback, if any. Polls the device without blocking.
+    pub fn collect(&mut self, device: &wgpu::Device) {
+        if !self.pending {
+            return;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        let Some(result) = self.map_result.lock().unwrap().take() else {
+            return;
+        };
+        self.pending = false;
+        if let Err(err) = result {
+            warn!("failed to read back particle stats: {}", err);
+            return;
+        }
+
+        let bytes = self.staging_buffer.slice(..).get_mapped_range();
+        self.latest = Some(bytemuck::pod_read_unaligned(&bytes));
+        drop(bytes);
+        self.staging_buffer.unmap();
+    }
+}
@@ -0,0 +1,208 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+/// Largest `supersample` factor, 16 times the pixels of the window.
+pub const MAX_SUPERSAMPLE: f32 = 4.0;
+
+// Settings of the downsample pass, matches the Downsample struct of supersample.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct DownsampleUniform {
+    scale: [f32; 2],
+    _padding: [u32; 2],
+}
+
+/// Offscreen target the trails and particles are drawn into at a multiple of the window
+/// resolution, plus the pass averaging it back down onto the frame. Only the passes that
+/// alias go through it; the background and the full-screen views are drawn at the window
+/// resolution as before.
+///
+/// The target starts out transparent every frame and the downsample pass composites it
+/// with premultiplied alpha, or additively into the HDR target, so the result matches
+/// drawing the particles straight onto the frame.
+pub struct Supersample {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    /// Pixels of the frame the target is averaged down onto.
+    pub frame_size: [u32; 2],
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Supersample {
+    /// A target of `size` pixels in the format of the particle pass, downsampled onto a
+    /// frame of `frame_size` pixels. `hdr` selects additive compositing.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        hdr: bool,
+        size: [u32; 2],
+        frame_size: [u32; 2],
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Supersample Target"),
+            size: wgpu::Extent3d {
+                width: size[0].max(1),
+                height: size[1].max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Downsample Bind Group Layout"),
+            entries: &[
+                // Supersampled target (read with textureLoad, no sampler)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Texels per pixel
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let uniform = DownsampleUniform {
+            scale: [
+                size[0].max(1) as f32 / frame_size[0].max(1) as f32,
+                size[1].max(1) as f32 / frame_size[1].max(1) as f32,
+            ],
+            _padding: [0; 2],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Downsample Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Downsample Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Downsample Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("supersample.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Downsample Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // the particles add up in the HDR target, elsewhere they cover what is below them
+        let color = if hdr {
+            wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            }
+        } else {
+            wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            }
+        };
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Downsample Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState {
+                        color,
+                        alpha: wgpu::BlendComponent::OVER,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            texture,
+            view,
+            frame_size,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// Pixels of the target.
+    pub fn size(&self) -> [u32; 2] {
+        let size = self.texture.size();
+        [size.width, size.height]
+    }
+
+    /// Records the pass averaging the target down onto `frame_view`, keeping what is
+    /// already there.
+    pub fn downsample(&self, encoder: &mut wgpu::CommandEncoder, frame_view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Downsample Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// Pixels of the supersampled target for a window of `size` pixels, `factor` times as
+/// many on each axis but no more than `max_dimension`.
+pub fn target_size(size: [u32; 2], factor: f32, max_dimension: u32) -> [u32; 2] {
+    size.map(|dimension| ((dimension as f32 * factor).round() as u32).clamp(1, max_dimension))
+}
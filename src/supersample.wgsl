@@ -0,0 +1,45 @@
+// Full-screen pass averaging the supersampled particle target down onto the frame
+
+@group(0) @binding(0) var source_texture: texture_2d<f32>;
+
+struct Downsample {
+    // source texels per destination pixel on each axis
+    scale: vec2<f32>,
+    padding1: u32,
+    padding2: u32,
+};
+
+@group(0) @binding(1) var<uniform> downsample: Downsample;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// A single triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+// Box filter over every source texel the pixel's footprint touches. The result keeps the
+// coverage in alpha, with colors premultiplied by it as the blend state expects.
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = floor(input.position.xy);
+    let last_texel = vec2<i32>(textureDimensions(source_texture)) - 1;
+    let first = min(vec2<i32>(floor(pixel * downsample.scale)), last_texel);
+    let last = min(vec2<i32>(ceil((pixel + 1.0) * downsample.scale)) - 1, last_texel);
+
+    var sum = vec4<f32>(0.0);
+    for (var y = first.y; y <= last.y; y++) {
+        for (var x = first.x; x <= last.x; x++) {
+            sum += textureLoad(source_texture, vec2<i32>(x, y), 0);
+        }
+    }
+    let count = vec2<f32>(last - first + 1);
+    return sum / (count.x * count.y);
+}
@@ -0,0 +1,122 @@
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::Path,
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{config::CommandsConfig, types::Command};
+
+/// A timeline line as written in the file.
+#[derive(Deserialize)]
+struct RawEntry {
+    time: f32,
+    command: String,
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+/// Command switch scheduled at a point of simulated time.
+#[derive(Clone, Debug)]
+pub struct TimelineEntry {
+    /// Simulated seconds since the start of the run.
+    pub time: f32,
+    pub command: Command,
+    /// Values replacing fields of the command's section of `commands`, e.g.
+    /// `{"stiffness": 80.0}` for Collapse.
+    pub params: Option<Value>,
+}
+
+/// Switches commands at fixed points of simulated time, for scripted demos. The file has
+/// one JSON object per line, `{"time": 2.5, "command": "dust", "params": {...}}`, with
+/// `params` optional.
+pub struct Timeline {
+    entries: Vec<TimelineEntry>,
+    next: usize,
+    /// Simulated seconds so far.
+    elapsed: f32,
+}
+
+impl Timeline {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        let mut entries = Vec::new();
+        for (number, line) in file.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} line {}: {}", path.display(), number + 1, message),
+                )
+            };
+
+            let raw: RawEntry =
+                serde_json::from_str(&line).map_err(|err| invalid(err.to_string()))?;
+            let command = Command::from_name(&raw.command)
+                .ok_or_else(|| invalid(format!("unknown command {:?}", raw.command)))?;
+            if !raw.time.is_finite() || raw.time < 0.0 {
+                return Err(invalid(format!("time {} is not a time", raw.time)));
+            }
+            if let Some(params) = &raw.params {
+                // fail at load time rather than halfway through a demo
+                with_params(&CommandsConfig::default(), command, params).map_err(invalid)?;
+            }
+            entries.push(TimelineEntry {
+                time: raw.time,
+                command,
+                params: raw.params,
+            });
+        }
+        // stable, so entries with the same time apply in file order
+        entries.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Ok(Self {
+            entries,
+            next: 0,
+            elapsed: 0.0,
+        })
+    }
+
+    /// Advances the timeline by `delta_time` simulated seconds and returns the entries
+    /// whose time has come.
+    pub fn advance(&mut self, delta_time: f32) -> &[TimelineEntry] {
+        self.elapsed += delta_time;
+        let start = self.next;
+        while self.next < self.entries.len() && self.entries[self.next].time <= self.elapsed {
+            self.next += 1;
+        }
+        &self.entries[start..self.next]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.entries.len()
+    }
+}
+
+/// Returns `commands` with the fields in `params` replaced in the section of `command`.
+pub fn with_params(
+    commands: &CommandsConfig,
+    command: Command,
+    params: &Value,
+) -> Result<CommandsConfig, String> {
+    let Value::Object(params) = params else {
+        return Err("params must be an object".to_string());
+    };
+    let mut value = serde_json::to_value(commands).map_err(|err| err.to_string())?;
+    let Some(Value::Object(section)) = value.get_mut(command.name()) else {
+        return Err(format!("{} has no parameters", command.name()));
+    };
+    for (key, param) in params {
+        // serde would silently drop a misspelled field
+        if !section.contains_key(key) {
+            return Err(format!("{} has no parameter {:?}", command.name(), key));
+        }
+        section.insert(key.clone(), param.clone());
+    }
+    serde_json::from_value(value).map_err(|err| err.to_string())
+}
@@ -0,0 +1,225 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    config::TintConfig,
+    dispatch::{self, DispatchConfig},
+    state::create_compute_pipeline,
+    upload::Uploads,
+};
+
+// Settings of update_tints, matches the Tint struct of compute.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct TintUniform {
+    color: [f32; 4],
+    radius: f32,
+    fade_time: f32,
+    _padding: [u32; 2],
+}
+
+/// A color per particle that the particle pass blends over the color mode, written by
+/// `update_tints` of compute.wgsl after the particles moved: particles that come within
+/// `radius` of the cursor take on the tint color, which fades again over `fade_time`.
+///
+/// Like the trails, the tints run with a bind group of their own, so the update kernels do
+/// not need another storage buffer, and `Particle` stays 32 bytes. They are only allocated
+/// at full size, 16 bytes per particle, while enabled.
+pub struct Tints {
+    /// One RGBA color per particle, alpha is how strongly it replaces the particle's color.
+    pub buffer: wgpu::Buffer,
+    /// Particles `buffer` has room for, 0 while tints are disabled.
+    pub capacity: u32,
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Tints {
+    /// `module` is the compute shader, which holds the `update_tints` kernel.
+    pub fn new(
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+        time_buffer: &wgpu::Buffer,
+        particle_buffer: &wgpu::Buffer,
+        mouse_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        // the same binding numbers as the main compute layout, compute.wgsl declares both
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tint Bind Group Layout"),
+            entries: &[
+                // Time buffer
+                entry(0, wgpu::BufferBindingType::Uniform),
+                // Particle buffer
+                entry(1, wgpu::BufferBindingType::Storage { read_only: false }),
+                // Mouse position buffer
+                entry(2, wgpu::BufferBindingType::Uniform),
+                // Tints
+                entry(18, wgpu::BufferBindingType::Storage { read_only: false }),
+                // Tint uniform
+                entry(19, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tint Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_compute_pipeline(device, &layout, module, "update_tints");
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tint Buffer"),
+            size: std::mem::size_of::<TintUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let buffer = create_tints(device, 1);
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            time_buffer,
+            particle_buffer,
+            mouse_buffer,
+            &buffer,
+            &uniform_buffer,
+        );
+
+        Self {
+            buffer,
+            capacity: 0,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// Makes room for `particle_count` tints while `config` enables them, releases them
+    /// otherwise, and records the upload of the settings for this step into `encoder`. Returns whether `buffer` was
+    /// reallocated, in which case the render bind group has to be rebuilt.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        uploads: &mut Uploads,
+        config: &TintConfig,
+        particle_count: u32,
+        time_buffer: &wgpu::Buffer,
+        particle_buffer: &wgpu::Buffer,
+        mouse_buffer: &wgpu::Buffer,
+    ) -> bool {
+        let reallocate = if config.enabled {
+            particle_count > self.capacity
+        } else {
+            self.capacity > 0
+        };
+        if reallocate {
+            // new buffers start out zeroed, so every particle starts untinted
+            self.capacity = if config.enabled { particle_count } else { 0 };
+            self.buffer = create_tints(device, self.capacity);
+            self.rebind(device, time_buffer, particle_buffer, mouse_buffer);
+        }
+
+        let uniform = TintUniform {
+            color: config.color,
+            radius: config.radius,
+            fade_time: config.fade_time,
+            _padding: [0; 2],
+        };
+        uploads.write(device, encoder, &self.uniform_buffer, &uniform);
+        reallocate
+    }
+
+    /// Records the tint update for `particle_count` particles, after `prepare`. Changes
+    /// the pass's bind group, callers have to set their own again afterwards.
+    pub fn record<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>, particle_count: u32) {
+        if self.capacity == 0 {
+            return;
+        }
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        dispatch::dispatch(
+            pass,
+            &self.pipeline,
+            DispatchConfig::Linear {
+                count: particle_count.min(self.capacity),
+            },
+        );
+    }
+
+    /// Points the bind group at a replaced particle buffer.
+    pub fn rebind(
+        &mut self,
+        device: &wgpu::Device,
+        time_buffer: &wgpu::Buffer,
+        particle_buffer: &wgpu::Buffer,
+        mouse_buffer: &wgpu::Buffer,
+    ) {
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            time_buffer,
+            particle_buffer,
+            mouse_buffer,
+            &self.buffer,
+            &self.uniform_buffer,
+        );
+    }
+}
+
+fn create_tints(device: &wgpu::Device, particles: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Tint Colors Buffer"),
+        size: particles.max(1) as u64 * std::mem::size_of::<[f32; 4]>() as u64,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    time_buffer: &wgpu::Buffer,
+    particle_buffer: &wgpu::Buffer,
+    mouse_buffer: &wgpu::Buffer,
+    tints: &wgpu::Buffer,
+    uniform_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Tint Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: time_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: particle_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: mouse_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 18,
+                resource: tints.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 19,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -0,0 +1,57 @@
+// Full-screen pass converting the floating point HDR target to the display format
+
+@group(0) @binding(0) var hdr_texture: texture_2d<f32>;
+
+struct Tonemap {
+    // 0: Reinhard, 1: ACES, 2: linear clamp, see Tonemap in config.rs
+    curve: u32,
+    // multiplier applied before the curve
+    exposure: f32,
+    padding1: u32,
+    padding2: u32,
+};
+
+@group(0) @binding(1) var<uniform> tonemap: Tonemap;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// A single triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let hdr = textureLoad(hdr_texture, vec2<i32>(input.position.xy), 0).rgb * tonemap.exposure;
+
+    var mapped: vec3<f32>;
+    switch tonemap.curve {
+        case 1u: {
+            mapped = aces(hdr);
+        }
+        case 2u: {
+            // everything past white clips
+            mapped = clamp(hdr, vec3<f32>(0.0), vec3<f32>(1.0));
+        }
+        default: {
+            // Reinhard, maps [0, inf) smoothly onto [0, 1)
+            mapped = hdr / (1.0 + hdr);
+        }
+    }
+    return vec4<f32>(mapped, 1.0);
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve: a toe that deepens dark regions and a
+// shoulder that reaches white at a finite value, so bright clusters keep more contrast
+// than with Reinhard
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+    return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+}
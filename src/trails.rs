@@ -0,0 +1,218 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    dispatch::{self, DispatchConfig},
+    state::create_compute_pipeline,
+    upload::Uploads,
+};
+
+/// Longest trail, in recorded positions per particle.
+pub const MAX_TRAIL_LENGTH: u32 = 16;
+
+// Settings of record_trails, matches the Trail struct of compute.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct TrailUniform {
+    length: u32,
+    reset: u32, // Nonzero fills the whole history with the current positions
+    _padding: [u32; 2],
+}
+
+/// The last few positions of every particle, which the particle pass draws as a fading
+/// line behind it. `record_trails` of compute.wgsl shifts the history by one entry every
+/// step, before the particles move.
+///
+/// The history runs with a bind group of its own, so the update kernels do not need
+/// another storage buffer. It is only allocated at full size while trails are enabled.
+pub struct Trails {
+    /// `length` positions per particle, most recent first.
+    pub history: wgpu::Buffer,
+    /// Positions per particle in `history`, 0 until trails are first enabled.
+    pub length: u32,
+    /// Particles `history` has room for.
+    pub capacity: u32,
+    /// The history was just allocated and holds no positions yet, cleared once `prepare`
+    /// uploaded it.
+    reset: bool,
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Trails {
+    /// `module` is the compute shader, which holds the `record_trails` kernel.
+    pub fn new(
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+        time_buffer: &wgpu::Buffer,
+        particle_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        // the same binding numbers as the main compute layout, compute.wgsl declares both
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Trail Bind Group Layout"),
+            entries: &[
+                // Time buffer
+                entry(0, wgpu::BufferBindingType::Uniform),
+                // Particle buffer
+                entry(1, wgpu::BufferBindingType::Storage { read_only: false }),
+                // Trail history
+                entry(13, wgpu::BufferBindingType::Storage { read_only: false }),
+                // Trail uniform
+                entry(14, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Trail Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_compute_pipeline(device, &layout, module, "record_trails");
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Trail Buffer"),
+            size: std::mem::size_of::<TrailUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let history = create_history(device, 1);
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            time_buffer,
+            particle_buffer,
+            &history,
+            &uniform_buffer,
+        );
+
+        Self {
+            history,
+            length: 0,
+            capacity: 1,
+            reset: true,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// Makes room for `particle_count` trails of `length` positions and records the
+    /// upload of the settings for this step into `encoder`. Returns whether `history` was reallocated, in which case
+    /// the render bind group has to be rebuilt.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        uploads: &mut Uploads,
+        length: u32,
+        particle_count: u32,
+        time_buffer: &wgpu::Buffer,
+        particle_buffer: &wgpu::Buffer,
+    ) -> bool {
+        let length = length.min(MAX_TRAIL_LENGTH);
+        let reallocate = length != self.length || particle_count > self.capacity;
+        if reallocate {
+            self.history = create_history(device, length as u64 * particle_count as u64);
+            self.length = length;
+            self.capacity = particle_count;
+            self.reset = true;
+            self.rebind(device, time_buffer, particle_buffer);
+        }
+
+        let uniform = TrailUniform {
+            length: self.length,
+            reset: self.reset as u32,
+            _padding: [0; 2],
+        };
+        uploads.write(device, encoder, &self.uniform_buffer, &uniform);
+        self.reset = false;
+        reallocate
+    }
+
+    /// Records the history update for `particle_count` particles, after `prepare`. Changes
+    /// the pass's bind group, callers have to set their own again afterwards.
+    pub fn record<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>, particle_count: u32) {
+        if self.length == 0 {
+            return;
+        }
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        dispatch::dispatch(
+            pass,
+            &self.pipeline,
+            DispatchConfig::Linear {
+                count: particle_count.min(self.capacity),
+            },
+        );
+    }
+
+    /// Points the bind group at a replaced particle buffer.
+    pub fn rebind(
+        &mut self,
+        device: &wgpu::Device,
+        time_buffer: &wgpu::Buffer,
+        particle_buffer: &wgpu::Buffer,
+    ) {
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            time_buffer,
+            particle_buffer,
+            &self.history,
+            &self.uniform_buffer,
+        );
+    }
+}
+
+fn create_history(device: &wgpu::Device, positions: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Trail History Buffer"),
+        size: positions.max(1) * 8,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    time_buffer: &wgpu::Buffer,
+    particle_buffer: &wgpu::Buffer,
+    history: &wgpu::Buffer,
+    uniform_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Trail Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: time_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: particle_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 13,
+                resource: history.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 14,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
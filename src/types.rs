@@ -1,5 +1,12 @@
+use std::fmt;
+
 use bytemuck::{Pod, Zeroable};
 
+use crate::{
+    config::{Background, GameConfiguration},
+    grid::SpatialGrid,
+};
+
 // Particle structure to store in the GPU buffer
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
@@ -7,6 +14,28 @@ pub struct Particle {
     pub position: [f32; 2],
     pub velocity: [f32; 2],
     pub acceleration: [f32; 2],
+    /// Nonzero for anchors that keep their place whatever forces act on them.
+    pub pinned: u32,
+    /// Random offset in [0, 1) of the particle's cycle of per-particle effects like the
+    /// pulse, drawn when it is seeded.
+    pub phase: f32,
+}
+
+impl fmt::Display for Particle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [x, y] = self.position;
+        let [vx, vy] = self.velocity;
+        let [ax, ay] = self.acceleration;
+        write!(
+            f,
+            "position ({:.4}, {:.4}), velocity ({:.4}, {:.4}), acceleration ({:.4}, {:.4})",
+            x, y, vx, vy, ax, ay
+        )?;
+        if self.pinned != 0 {
+            write!(f, ", pinned")?;
+        }
+        Ok(())
+    }
 }
 
 // Time uniform to pass deltaTime to the compute shader
@@ -15,7 +44,8 @@ pub struct Particle {
 pub struct TimeUniform {
     pub delta_time: f32,
     pub particle_count: u32,
-    pub _padding1: [f32; 2], // Adjust padding to keep 16-byte alignment
+    pub frame: u32,          // Simulation step counter, wraps around
+    pub elapsed_time: f32,   // Simulated seconds since the start, up to the end of this step
     pub _padding2: [f32; 4], // Second padding to 32 bytes total
 }
 
@@ -24,6 +54,12 @@ pub struct TimeUniform {
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct MouseUniform {
     pub mouse_position: [f32; 2],
+    pub secondary_position: [f32; 2],
+    pub weights: [f32; 2], // Weights of the cursor and the secondary attractor
+    pub secondary_active: u32,
+    pub left_pressed: u32,
+    pub velocity: [f32; 2], // Cursor velocity in clip space units per second
+    pub fling_velocity: [f32; 2], // Cursor velocity smoothed over a few steps, kept by flung particles
 }
 
 // Resolution
@@ -32,6 +68,19 @@ pub struct MouseUniform {
 pub struct ResolutionUniform {
     pub width: f32,
     pub height: f32,
+    pub view_angle: f32, // Radians the view is turned by, for the overlays drawn under the particles
+    pub _padding: u32,
+}
+
+impl ResolutionUniform {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: width as f32,
+            height: height as f32,
+            view_angle: 0.0,
+            _padding: 0,
+        }
+    }
 }
 
 // Command uniform to pass commands that are shared between all particles
@@ -39,22 +88,336 @@ pub struct ResolutionUniform {
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct CommandUniform {
     pub command: u32,
+    pub _padding: [u32; 3], // Align params to 16 bytes
+    pub params: [f32; 4],   // Tunables of the active command, see CommandsConfig::params
 }
 
 impl CommandUniform {
-    pub fn from_command(command: Command) -> Self {
-        let val = match command {
-            Command::Roam => 0,
-            Command::Shuffle => 1,
-        };
+    pub fn from_command(command: Command, config: &GameConfiguration) -> Self {
+        let mut params = config.commands.params(command);
+        if command == Command::FieldFlow && config.field_texture.is_none() {
+            // an empty field, rather than steering every particle towards rest
+            params = [0.0; 4];
+        }
+        Self {
+            command: command.as_u32(),
+            _padding: [0; 3],
+            params,
+        }
+    }
+}
+
+// Settings of the render shaders that can change every frame
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct RenderParamsUniform {
+    pub velocity_vector_scale: f32,
+    /// Positions per particle in the trail history, 0 while trails are off.
+    pub trail_length: u32,
+    pub intensity: f32,
+    pub color_bands: u32,
+    /// Tint of the active command, its alpha is how strongly it applies.
+    pub command_color: [f32; 4],
+    /// Index of the particle selected for inspection, `NO_SELECTION` if there is none.
+    pub selected_particle: u32,
+    /// Angle in radians the view is turned by counterclockwise, see `auto_rotate`.
+    pub view_angle: f32,
+    /// Radius of the particle splats in pixels, 0 while particles are drawn as quads.
+    pub splat_radius: f32,
+    /// Swing of the particle size and brightness, 0 while pulsing is off.
+    pub pulse_amount: f32,
+    /// Position in the shared pulse cycle, in [0, 1).
+    pub pulse_phase: f32,
+    /// Half the side of a particle quad in clip space units, see `quad_size`.
+    pub quad_size: f32,
+    /// Nonzero while the tint buffer holds a tint for every particle.
+    pub tinted: u32,
+    /// Sides of the polygon every particle is drawn as, 0 for a quad.
+    pub particle_segments: u32,
+}
+
+/// `selected_particle` while no particle is selected, never the index of a particle.
+pub const NO_SELECTION: u32 = u32::MAX;
+
+impl RenderParamsUniform {
+    pub fn from_config(config: &GameConfiguration, command: Command) -> Self {
+        Self {
+            velocity_vector_scale: config.velocity_vector_scale,
+            trail_length: 0,
+            intensity: config.clamped_intensity(),
+            color_bands: config.color_bands,
+            command_color: config.command_color(command),
+            selected_particle: NO_SELECTION,
+            view_angle: 0.0,
+            splat_radius: 0.0,
+            pulse_amount: config.clamped_pulse_amount(),
+            pulse_phase: 0.0,
+            quad_size: config.quad_size,
+            tinted: 0,
+            particle_segments: config.particle_segments(),
+        }
+    }
+}
+
+// Simulation settings shared by every command
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct SimulationUniform {
+    pub accumulate_acceleration: u32,
+    pub drag: f32,
+    pub softening: f32,
+    pub high_precision: u32,
+    pub keep_on_screen: u32,
+    pub keep_on_screen_margin: f32,
+    pub keep_on_screen_stiffness: f32,
+    pub _padding: u32, // Pad to 32 bytes for uniform layout
+}
+
+impl SimulationUniform {
+    pub fn from_config(config: &GameConfiguration) -> Self {
+        Self {
+            accumulate_acceleration: config.accumulate_acceleration as u32,
+            drag: config.drag,
+            softening: config.softening,
+            high_precision: config.high_precision as u32,
+            keep_on_screen: config.keep_on_screen as u32,
+            keep_on_screen_margin: config.keep_on_screen_margin,
+            keep_on_screen_stiffness: config.keep_on_screen_stiffness,
+            _padding: 0,
+        }
+    }
+}
+
+// Settings of the tonemapping pass, see hdr.rs
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct TonemapUniform {
+    pub operator: u32,
+    pub exposure: f32,
+    pub _padding: [u32; 2], // Pad to 16 bytes for uniform layout
+}
+
+impl TonemapUniform {
+    pub fn from_config(config: &GameConfiguration) -> Self {
+        Self {
+            operator: config.tonemap as u32,
+            exposure: config.exposure,
+            _padding: [0; 2],
+        }
+    }
+}
+
+// Layout and settings of the spatial grid, see grid.rs
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct GridUniform {
+    pub cells_per_axis: u32,
+    pub cell_count: u32,
+    pub cell_size: f32,
+    pub min_distance: f32,           // 0 disables the separation force
+    pub min_distance_stiffness: f32, // Fraction of a pair's overlap resolved per step
+    pub color_bands: u32,
+    pub band_cohesion: f32, // 0 disables the pull towards particles of the same band
+    pub band_cohesion_radius: f32,
+}
+
+impl GridUniform {
+    pub fn new(config: &GameConfiguration, grid: &SpatialGrid) -> Self {
+        Self {
+            cells_per_axis: grid.cells_per_axis,
+            cell_count: grid.cell_count(),
+            cell_size: 2.0 / grid.cells_per_axis as f32,
+            min_distance: config.min_distance.max(0.0),
+            min_distance_stiffness: config.min_distance_stiffness,
+            color_bands: config.color_bands,
+            band_cohesion: if config.band_cohesion_enabled() {
+                config.band_cohesion
+            } else {
+                0.0
+            },
+            band_cohesion_radius: config.band_cohesion_radius,
+        }
+    }
+}
+
+// Aggregates written by the reduction kernels, see stats.rs
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct StatsData {
+    pub max_speed: f32, // Reduced with atomicMax on the bits, which order like the values for non-negative floats
+    pub kinetic_energy_low: u32, // 64 bit fixed point with 16 fractional bits, see kinetic_energy
+    pub kinetic_energy_high: u32,
+    pub finite_count: u32, // Particles with a finite position, the position sum is taken over them
+    pub position_sum_x: [u32; 2], // Low and high word of 64 bit two's complement fixed point, see position_sum
+    pub position_sum_y: [u32; 2],
+}
 
-        Self { command: val }
+impl StatsData {
+    /// Total kinetic energy of the active particles, each with unit mass.
+    pub fn kinetic_energy(&self) -> f64 {
+        let fixed = ((self.kinetic_energy_high as u64) << 32) | self.kinetic_energy_low as u64;
+        fixed as f64 / 65536.0
     }
+
+    /// Sum of the finite positions.
+    pub fn position_sum(&self) -> [f64; 2] {
+        [self.position_sum_x, self.position_sum_y].map(|[low, high]| {
+            let fixed = (((high as u64) << 32) | low as u64) as i64;
+            fixed as f64 / 65536.0
+        })
+    }
+
+    /// Mean position of the particles with a finite position, if there are any.
+    pub fn center_of_mass(&self) -> Option<[f32; 2]> {
+        if self.finite_count == 0 {
+            return None;
+        }
+        let count = self.finite_count as f64;
+        Some(self.position_sum().map(|sum| (sum / count) as f32))
+    }
+}
+
+// Corner of a shape of the frame time graph, see perf_graph.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct GraphVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+// Ring drawn at the cursor, see cursor.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct CursorUniform {
+    pub position: [f32; 2],
+    pub radius: f32,
+    pub thickness: f32,
 }
 
-// Human readable command names
-#[derive(Copy, Clone, Debug)]
+// Colors of the gradient background, see background.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct BackgroundUniform {
+    pub kind: u32,
+    pub _padding: [u32; 3], // Align colors to 16 bytes
+    pub first: [f32; 4],
+    pub second: [f32; 4],
+}
+
+impl BackgroundUniform {
+    pub fn from_background(background: &Background) -> Self {
+        let (kind, first, second) = match *background {
+            Background::Solid(color) => (0, color, color),
+            Background::VerticalGradient(top, bottom) => (1, top, bottom),
+            Background::Radial(center, corner) => (2, center, corner),
+        };
+        Self {
+            kind,
+            _padding: [0; 3],
+            first,
+            second,
+        }
+    }
+}
+
+// Human readable command names, the discriminant is the value the compute shader sees
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Command {
-    Roam,    // particles gravitate around the cursor
-    Shuffle, // particles are randomly offset by an amount
+    Roam = 0,       // particles gravitate around the cursor
+    Shuffle = 1,    // particles are randomly offset by an amount
+    Collapse = 2,   // particles are pulled onto the cursor by a critically damped spring
+    Dust = 3,       // weak cursor attraction plus smooth curl noise, particles drift like dust
+    Follow = 4,     // particles near the cursor are pushed along the direction it moves in
+    Chain = 5,      // particle 0 chases the cursor, every other particle chases its predecessor
+    Grab = 6,       // while the left button is held particles near the cursor are carried along
+    Emit = 7,       // particles keep respawning at the cursor and spray away from it
+    Spiral = 8, // every particle is pulled to its own slot of a sunflower spiral around the cursor
+    Life = 9,   // sparse neighborhoods gather and crowded ones disperse, forming blobs
+    Brownian = 10, // random kicks every step make particles diffuse, optionally drifting
+    FieldFlow = 11, // particles follow the vectors of an image stretched over the window
+}
+
+impl Command {
+    /// Every command, indexed by its discriminant.
+    pub const ALL: [Command; 12] = [
+        Command::Roam,
+        Command::Shuffle,
+        Command::Collapse,
+        Command::Dust,
+        Command::Follow,
+        Command::Chain,
+        Command::Grab,
+        Command::Emit,
+        Command::Spiral,
+        Command::Life,
+        Command::Brownian,
+        Command::FieldFlow,
+    ];
+    pub const COUNT: u32 = Self::ALL.len() as u32;
+
+    pub fn as_u32(self) -> u32 {
+        self as u32
+    }
+
+    /// Lowercase name, as used by the control socket.
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Roam => "roam",
+            Command::Shuffle => "shuffle",
+            Command::Collapse => "collapse",
+            Command::Dust => "dust",
+            Command::Follow => "follow",
+            Command::Chain => "chain",
+            Command::Grab => "grab",
+            Command::Emit => "emit",
+            Command::Spiral => "spiral",
+            Command::Life => "life",
+            Command::Brownian => "brownian",
+            Command::FieldFlow => "field_flow",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Command> {
+        Self::ALL.into_iter().find(|command| command.name() == name)
+    }
+
+    /// Tint of the particles while the command is active, unless `command_colors` sets
+    /// another.
+    pub fn default_color(self) -> [f32; 4] {
+        match self {
+            Command::Roam => [0.6, 0.8, 1.0, 1.0],
+            Command::Shuffle => [1.0, 0.9, 0.6, 1.0],
+            Command::Collapse => [1.0, 0.6, 0.6, 1.0],
+            Command::Dust => [0.9, 0.8, 0.65, 1.0],
+            Command::Follow => [0.6, 1.0, 0.75, 1.0],
+            Command::Chain => [1.0, 1.0, 1.0, 1.0],
+            Command::Grab => [1.0, 0.7, 1.0, 1.0],
+            Command::Emit => [1.0, 0.75, 0.5, 1.0],
+            Command::Spiral => [0.8, 0.65, 1.0, 1.0],
+            Command::Life => [0.7, 1.0, 0.55, 1.0],
+            Command::Brownian => [0.8, 0.8, 0.8, 1.0],
+            Command::FieldFlow => [0.55, 0.9, 0.9, 1.0],
+        }
+    }
+
+    /// Whether field.wgsl describes the command's forces, so the field view has something
+    /// to show.
+    pub fn has_field(self) -> bool {
+        match self {
+            Command::Roam | Command::Shuffle | Command::Collapse | Command::Follow => true,
+            // the noise part is per particle and lives in compute.wgsl only
+            Command::Dust => false,
+            // every particle has its own target
+            Command::Chain | Command::Spiral => false,
+            // moves particles directly instead of accelerating them
+            Command::Grab | Command::Emit => false,
+            // depends on the neighbors, not on the position alone
+            Command::Life => false,
+            // only the drift, the kicks are per particle and live in compute.wgsl
+            Command::Brownian => true,
+            // the texture is only bound to the simulation
+            Command::FieldFlow => false,
+        }
+    }
 }
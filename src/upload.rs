@@ -0,0 +1,45 @@
+use bytemuck::Pod;
+use wgpu::util::StagingBelt;
+
+/// Staging memory is handed out in chunks of this many bytes, enough for the uniforms of
+/// every substep of a frame.
+const CHUNK_SIZE: u64 = 16 << 10;
+
+/// Uniform writes recorded into a command encoder, so each takes effect between the passes
+/// recorded around it. `Queue::write_buffer` instead applies every write before the next
+/// submitted command buffer starts, so steps that share one submit would all see the
+/// values of the last of them.
+pub struct Uploads {
+    belt: StagingBelt,
+}
+
+impl Uploads {
+    pub fn new() -> Self {
+        Self {
+            belt: StagingBelt::new(CHUNK_SIZE),
+        }
+    }
+
+    /// Records a copy of `value` to the start of `buffer`, which needs `COPY_DST` usage.
+    pub fn write<T: Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        value: &T,
+    ) {
+        let bytes = bytemuck::bytes_of(value);
+        let size = wgpu::BufferSize::new(bytes.len() as u64).expect("uniforms are not empty");
+        self.belt
+            .write_buffer(encoder, buffer, 0, size, device)
+            .copy_from_slice(bytes);
+    }
+
+    /// Submits `encoder` with the writes recorded into it.
+    pub fn submit(&mut self, queue: &wgpu::Queue, encoder: wgpu::CommandEncoder) {
+        self.belt.finish();
+        queue.submit(std::iter::once(encoder.finish()));
+        // the chunks become writable again once the GPU is done with them
+        self.belt.recall();
+    }
+}